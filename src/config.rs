@@ -0,0 +1,772 @@
+//! The settings of the server, from a TOML file, `config.toml` by default:
+//! where it listens, how it reaches the gateway, who may chat, the defaults
+//! and limits of its chat rooms, how it keeps its chat connections, and
+//! where it keeps what it saves. Every setting is optional, see
+//! `config.toml.example`, and the server runs with the defaults when there
+//! is no file at all.
+//!
+//! Every setting can be overridden with an environment variable named
+//! after its section and key, such as `APP_JANUS__URL` for `url` of
+//! `[janus]`, so that containers need no file of their own.
+//! `APP_SERVER__PORT` changes the port of `listen` alone, and an empty
+//! value clears a setting without a default, such as `APP_JANUS__APISECRET`.
+//! Variables like them that name no setting are refused, while those
+//! without `__`, such as `APP_ENV`, are left to whoever set them.
+//!
+//! Settings are taken, from lowest to highest precedence, from the defaults,
+//! the file, the variables the server read before it had a file, such as
+//! `JANUS_APISECRET` or `CHAT_HISTORY`, the `APP_` variables, and the
+//! options of the command line. The server does not start with settings it
+//! cannot run with, telling why.
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::janus::{self, JanusAuth};
+use crate::server::auth::ChatAuth;
+use crate::server::commands::MAX_BAN_MINUTES;
+use crate::server::files::Files;
+use crate::server::filters::{self, Filters};
+use crate::server::keepalive::Keepalive;
+use crate::server::limits::SizeLimits;
+use crate::server::ratelimit::RateLimit;
+use crate::server::roles::Role;
+
+/// Where the settings are read from when nothing else is said.
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// The prefix of the environment variables overriding the settings.
+const ENV_PREFIX: &str = "APP_";
+
+/// The environment variables the server read before it had a config file,
+/// with the settings they override under the `APP_` variables.
+const LEGACY_VARS: &[(&str, &str)] = &[
+    ("JANUS_APISECRET", "janus__apisecret"),
+    ("JANUS_TOKEN", "janus__token"),
+    ("JANUS_ADMIN_SECRET", "janus__admin_secret"),
+    ("JANUS_ADMIN_KEY", "janus__admin_key"),
+    ("JANUS_ROOM", "janus__room"),
+    ("JANUS_ROOM_SECRET", "janus__room_secret"),
+    ("JANUS_ROOM_SECRETS", "janus__room_secrets"),
+    ("JANUS_TEXTROOM_SECRET", "janus__textroom_secret"),
+    ("JANUS_CUSTOM_PLUGINS", "janus__custom_plugins"),
+    ("JANUS_RECORDING_FILENAME", "janus__recording_filename"),
+    ("CHAT_TOKENS", "auth__tokens"),
+    ("CHAT_JWT_SECRET", "auth__jwt_secret"),
+    ("CHAT_JWT_PUBLIC_KEY", "auth__jwt_public_key"),
+    ("CHAT_DEFAULT_ROLE", "auth__default_role"),
+    ("CHAT_HISTORY", "rooms__history"),
+    ("CHAT_ROOM_CAPACITY", "rooms__capacity"),
+    ("CHAT_MAX_TEXT", "limits__max_text"),
+    ("CHAT_MAX_MESSAGE", "limits__max_message"),
+    ("CHAT_RATE_LIMIT", "limits__rate_limit"),
+    ("CHAT_RATE_STRIKES", "limits__rate_strikes"),
+    ("CHAT_MAX_FILE", "limits__max_file"),
+    ("CHAT_FILE_QUOTA", "limits__file_quota"),
+    ("CHAT_PING_SECS", "chat__ping_secs"),
+    ("CHAT_PING_MISSES", "chat__ping_misses"),
+    ("CHAT_RESUME_SECS", "chat__resume_secs"),
+    ("CHAT_BANS", "chat__bans"),
+    ("CHAT_MUTES", "chat__mutes"),
+    ("CHAT_BAN_MINUTES", "chat__ban_minutes"),
+    ("CHAT_FILTERS", "chat__filters"),
+    ("CHAT_PROFANITY", "chat__profanity"),
+    ("CHAT_DROP_WORDS", "chat__drop_words"),
+    ("CHAT_DB", "store__db"),
+    ("CHAT_RETENTION_DAYS", "store__retention_days"),
+];
+
+/// Every setting of the server.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub janus: JanusConfig,
+    pub auth: AuthConfig,
+    pub rooms: RoomsConfig,
+    pub limits: LimitsConfig,
+    pub chat: ChatConfig,
+    pub store: StoreConfig,
+}
+
+/// `[server]`: the warp server.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address the chat and the other routes are served on.
+    pub listen: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: ([0, 0, 0, 0], 8080).into(),
+        }
+    }
+}
+
+/// `[janus]`: how we reach the gateway, and its Admin API.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JanusConfig {
+    /// Websocket or HTTP address of the gateway API.
+    pub url: String,
+    /// The `apisecret` configured on the gateway.
+    pub apisecret: Option<String>,
+    /// A token added to the gateway through its Admin API, when there is no
+    /// `apisecret`.
+    pub token: Option<String>,
+    /// Websocket address of the Admin API.
+    pub admin_url: String,
+    /// The `admin_secret` configured on the gateway, without which the
+    /// `/admin` routes do not exist.
+    pub admin_secret: Option<String>,
+    /// The `admin_key` of the videoroom plugin, which creating videorooms
+    /// takes when the plugin is configured with one.
+    pub admin_key: Option<String>,
+    /// The videoroom the moderation commands act on when they name none,
+    /// which `GET /chat` is the chat room of.
+    pub room: u64,
+    /// The secret of that videoroom, and of the others of `kick` and the
+    /// like, when `room_secrets` has none for them.
+    pub room_secret: Option<String>,
+    /// The file the secrets and pins of the videorooms we create are saved
+    /// in.
+    pub room_secrets: PathBuf,
+    /// The secret of the TextRooms we create, and announce in.
+    pub textroom_secret: Option<String>,
+    /// The plugins besides ours chat users may send messages to.
+    pub custom_plugins: Vec<String>,
+    /// Where the `recorduser` command records, with `{room}`, `{user}` and
+    /// `{timestamp}` filled in.
+    pub recording_filename: String,
+    /// How often the session is kept alive, in seconds.
+    pub keepalive_secs: u64,
+    /// How long to wait before connecting again after a failure or drop, in
+    /// milliseconds.
+    pub reconnect_delay_ms: u64,
+    /// How many requests per second may go out, so that chat users spamming
+    /// commands do not flood the gateway.
+    pub requests_per_second: u32,
+    /// How many requests may go out at once.
+    pub requests_burst: u32,
+}
+
+impl Default for JanusConfig {
+    fn default() -> Self {
+        let client = janus::Config::default();
+        let admin = janus::AdminConfig::default();
+        JanusConfig {
+            url: client.urls[0].clone(),
+            apisecret: None,
+            token: None,
+            admin_url: admin.url,
+            admin_secret: None,
+            admin_key: None,
+            room: 1234,
+            room_secret: None,
+            room_secrets: PathBuf::from("room_secrets.json"),
+            textroom_secret: None,
+            custom_plugins: Vec::new(),
+            recording_filename: "/recordings/room-{room}-user-{user}-{timestamp}".to_string(),
+            keepalive_secs: client.keepalive_interval.as_secs(),
+            reconnect_delay_ms: client.reconnect_delay.as_millis() as u64,
+            requests_per_second: 20,
+            requests_burst: 40,
+        }
+    }
+}
+
+impl JanusConfig {
+    /// How our requests authenticate with the gateway, preferring the
+    /// secret when there is a token as well.
+    pub fn auth(&self) -> JanusAuth {
+        match (&self.apisecret, &self.token) {
+            (Some(secret), _) => JanusAuth::ApiSecret(secret.clone()),
+            (None, Some(token)) => JanusAuth::Token(token.clone()),
+            (None, None) => JanusAuth::None,
+        }
+    }
+
+    pub fn keepalive_interval(&self) -> Duration {
+        Duration::from_secs(self.keepalive_secs)
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_millis(self.reconnect_delay_ms)
+    }
+
+    pub fn rate_limit(&self) -> janus::RateLimit {
+        janus::RateLimit {
+            per_second: self.requests_per_second,
+            burst: self.requests_burst,
+        }
+    }
+}
+
+/// The secrets are never printed.
+impl std::fmt::Debug for JanusConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JanusConfig")
+            .field("url", &self.url)
+            .field("auth", &self.auth())
+            .field("admin_url", &self.admin_url)
+            .field(
+                "admin_secret",
+                &self.admin_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("admin_key", &self.admin_key.as_ref().map(|_| "<redacted>"))
+            .field("room", &self.room)
+            .field(
+                "room_secret",
+                &self.room_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("room_secrets", &self.room_secrets)
+            .field(
+                "textroom_secret",
+                &self.textroom_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("custom_plugins", &self.custom_plugins)
+            .field("recording_filename", &self.recording_filename)
+            .field("keepalive_secs", &self.keepalive_secs)
+            .field("reconnect_delay_ms", &self.reconnect_delay_ms)
+            .field("requests_per_second", &self.requests_per_second)
+            .field("requests_burst", &self.requests_burst)
+            .finish()
+    }
+}
+
+/// `[auth]`: who may chat, and what they may do. Without `jwt_secret`,
+/// `jwt_public_key` or `tokens`, anyone may.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// The tokens that let websockets in.
+    pub tokens: Vec<String>,
+    /// The shared secret of HS256 JWTs, over `tokens`.
+    pub jwt_secret: Option<String>,
+    /// The file with the PEM public key of RS256 JWTs, over `tokens`.
+    pub jwt_public_key: Option<PathBuf>,
+    /// The role of the users whose token claims none.
+    pub default_role: Role,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            tokens: Vec::new(),
+            jwt_secret: None,
+            jwt_public_key: None,
+            default_role: Role::User,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn chat_auth(&self) -> ChatAuth {
+        ChatAuth::new(
+            self.jwt_secret.as_deref(),
+            self.jwt_public_key.as_deref(),
+            &self.tokens,
+        )
+    }
+}
+
+/// The secrets are never printed.
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("tokens", &self.tokens.len())
+            .field(
+                "jwt_secret",
+                &self.jwt_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("jwt_public_key", &self.jwt_public_key)
+            .field("default_role", &self.default_role)
+            .finish()
+    }
+}
+
+/// `[rooms]`: what chat rooms are like.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomsConfig {
+    /// How many of the last messages of a room the users joining it get.
+    pub history: usize,
+    /// How many users a room takes, as many as they like without it.
+    pub capacity: Option<usize>,
+}
+
+impl Default for RoomsConfig {
+    fn default() -> Self {
+        RoomsConfig {
+            history: 50,
+            capacity: None,
+        }
+    }
+}
+
+/// `[limits]`: what chat users may send.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// The longest chat messages and commands, in bytes.
+    pub max_text: Option<usize>,
+    /// The longest websocket messages, in bytes.
+    pub max_message: Option<usize>,
+    /// How fast users may send messages, such as `5/s`, `60/min` or
+    /// `600/h`, or `off`.
+    pub rate_limit: Option<String>,
+    /// How fast users may send WebRTC signaling, the same way.
+    pub signal_rate_limit: Option<String>,
+    /// How many messages over the rate limits are dropped before the
+    /// websocket is closed.
+    pub rate_strikes: Option<u32>,
+    /// The biggest file sent in the chat, in bytes.
+    pub max_file: Option<usize>,
+    /// How many bytes of files every connection may send.
+    pub file_quota: Option<usize>,
+}
+
+impl LimitsConfig {
+    pub fn size_limits(&self) -> SizeLimits {
+        let default = SizeLimits::default();
+        SizeLimits {
+            text: self.max_text.unwrap_or(default.text),
+            message: self.max_message.unwrap_or(default.message),
+        }
+    }
+
+    pub fn files(&self) -> Files {
+        let default = Files::default();
+        Files::new(
+            self.max_file.unwrap_or(default.max_size),
+            self.file_quota.unwrap_or(default.quota),
+        )
+    }
+
+    /// The rate limit, `None` when it is `off`.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.limit(self.rate_limit.as_deref(), RateLimit::default())
+    }
+
+    /// The rate limit of signaling, `None` when it is `off`.
+    pub fn signal_rate_limit(&self) -> Option<RateLimit> {
+        self.limit(self.signal_rate_limit.as_deref(), RateLimit::signaling())
+    }
+
+    /// Rate limit `limit`, or else `default`, with the strikes.
+    fn limit(&self, limit: Option<&str>, default: RateLimit) -> Option<RateLimit> {
+        let mut limit = match limit {
+            Some("off") => return None,
+            Some(limit) => RateLimit::parse(limit)?,
+            None => default,
+        };
+        if let Some(strikes) = self.rate_strikes {
+            limit.strikes = strikes;
+        }
+        Some(limit)
+    }
+}
+
+/// `[chat]`: the chat connections, and who is kept out.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    /// How often chat websockets are pinged, in seconds.
+    pub ping_secs: u64,
+    /// How many pings in a row may go unanswered before a websocket is
+    /// dropped.
+    pub ping_misses: u32,
+    /// How long users who left may come back as who they were, in seconds,
+    /// not at all with 0.
+    pub resume_secs: u64,
+    /// The file the bans are saved in.
+    pub bans: PathBuf,
+    /// The file who muted whom is saved in.
+    pub mutes: PathBuf,
+    /// How long the `ban` command bans for when it is not told, in minutes.
+    pub ban_minutes: u64,
+    /// The filters chat messages go through, in order, out of `profanity`,
+    /// `links` and `drop`.
+    pub filters: Vec<String>,
+    /// The words the `profanity` filter masks.
+    pub profanity: Vec<String>,
+    /// The words the `drop` filter drops the messages with.
+    pub drop_words: Vec<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        let keepalive = Keepalive::default();
+        ChatConfig {
+            ping_secs: keepalive.interval.as_secs(),
+            ping_misses: keepalive.misses,
+            resume_secs: 120,
+            bans: PathBuf::from("chat_bans.json"),
+            mutes: PathBuf::from("chat_mutes.json"),
+            ban_minutes: 60,
+            filters: Vec::new(),
+            profanity: Vec::new(),
+            drop_words: Vec::new(),
+        }
+    }
+}
+
+impl ChatConfig {
+    pub fn keepalive(&self) -> Keepalive {
+        Keepalive {
+            interval: Duration::from_secs(self.ping_secs),
+            misses: self.ping_misses,
+        }
+    }
+
+    pub fn resume_window(&self) -> Duration {
+        Duration::from_secs(self.resume_secs)
+    }
+
+    pub fn filters(&self) -> Filters {
+        Filters::named(&self.filters, &self.profanity, &self.drop_words)
+    }
+}
+
+/// `[store]`: the chat history on disk, with the `sqlite` feature.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// The database file every chat message is written to, none without
+    /// it.
+    pub db: Option<PathBuf>,
+    /// How many days the messages are kept, forever without it.
+    pub retention_days: Option<u64>,
+}
+
+impl StoreConfig {
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    }
+}
+
+impl Config {
+    /// The settings of the file at `path`, or the defaults if there is
+    /// none, overridden by the environment variables as `override_with`
+    /// says. The answer tells why they cannot be used otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, String> {
+        Config::load_with(path, |_| ())
+    }
+
+    /// Like `load`, with the settings then changed by `f`, such as with the
+    /// options of the command line, before they are checked.
+    pub fn load_with(
+        path: impl AsRef<Path>,
+        f: impl FnOnce(&mut Config),
+    ) -> Result<Config, String> {
+        let mut config = Config::read(path.as_ref())?;
+        let vars = env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        config.override_with(vars)?;
+        f(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The settings of the file at `path` alone.
+    fn read(path: &Path) -> Result<Config, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("{} cannot be read: {}", path.display(), e)),
+        };
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Overrides the settings with those of the variables of `vars` named
+    /// in `LEGACY_VARS`, then with those of its `APP_` variables, and
+    /// ignores the other variables. `APP_SERVER__PORT` goes last, so that
+    /// it changes the port of whatever `APP_SERVER__LISTEN` says.
+    pub fn override_with(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), String> {
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+        for (legacy, key) in LEGACY_VARS {
+            if let Some((name, value)) = vars.iter().find(|(name, _)| name == legacy) {
+                self.set(name, key, value)?;
+            }
+        }
+        let mut port = None;
+        for (name, value) in &vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) if key.contains("__") => key.to_ascii_lowercase(),
+                _ => continue,
+            };
+            match key.as_str() {
+                "server__port" => port = Some((name, value)),
+                key => self.set(name, key, value)?,
+            }
+        }
+        if let Some((name, value)) = port {
+            self.set(name, "server__port", value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the setting `key`, such as `janus__url`, to `value` of
+    /// variable `name`.
+    fn set(&mut self, name: &str, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let (server, janus, auth, rooms, limits, chat, store) = (
+            &mut self.server,
+            &mut self.janus,
+            &mut self.auth,
+            &mut self.rooms,
+            &mut self.limits,
+            &mut self.chat,
+            &mut self.store,
+        );
+        match key {
+            "server__listen" => server.listen = parse(name, value)?,
+            "server__port" => server.listen.set_port(parse(name, value)?),
+            "janus__url" => janus.url = value.to_string(),
+            "janus__apisecret" => janus.apisecret = optional(value),
+            "janus__token" => janus.token = optional(value),
+            "janus__admin_url" => janus.admin_url = value.to_string(),
+            "janus__admin_secret" => janus.admin_secret = optional(value),
+            "janus__admin_key" => janus.admin_key = optional(value),
+            "janus__room" => janus.room = parse(name, value)?,
+            "janus__room_secret" => janus.room_secret = optional(value),
+            "janus__room_secrets" => janus.room_secrets = PathBuf::from(value),
+            "janus__textroom_secret" => janus.textroom_secret = optional(value),
+            "janus__custom_plugins" => janus.custom_plugins = list(value),
+            "janus__recording_filename" => janus.recording_filename = value.to_string(),
+            "auth__tokens" => auth.tokens = list(value),
+            "auth__jwt_secret" => auth.jwt_secret = optional(value),
+            "auth__jwt_public_key" => auth.jwt_public_key = optional(value).map(PathBuf::from),
+            "auth__default_role" => auth.default_role = parse(name, value)?,
+            "janus__keepalive_secs" => janus.keepalive_secs = parse(name, value)?,
+            "janus__reconnect_delay_ms" => janus.reconnect_delay_ms = parse(name, value)?,
+            "janus__requests_per_second" => janus.requests_per_second = parse(name, value)?,
+            "janus__requests_burst" => janus.requests_burst = parse(name, value)?,
+            "rooms__history" => rooms.history = parse(name, value)?,
+            "rooms__capacity" => rooms.capacity = parse_optional(name, value)?,
+            "limits__max_text" => limits.max_text = parse_optional(name, value)?,
+            "limits__max_message" => limits.max_message = parse_optional(name, value)?,
+            "limits__rate_limit" => limits.rate_limit = optional(value),
+            "limits__signal_rate_limit" => limits.signal_rate_limit = optional(value),
+            "limits__rate_strikes" => limits.rate_strikes = parse_optional(name, value)?,
+            "limits__max_file" => limits.max_file = parse_optional(name, value)?,
+            "limits__file_quota" => limits.file_quota = parse_optional(name, value)?,
+            "chat__ping_secs" => chat.ping_secs = parse(name, value)?,
+            "chat__ping_misses" => chat.ping_misses = parse(name, value)?,
+            "chat__resume_secs" => chat.resume_secs = parse(name, value)?,
+            "chat__bans" => chat.bans = PathBuf::from(value),
+            "chat__mutes" => chat.mutes = PathBuf::from(value),
+            "chat__ban_minutes" => chat.ban_minutes = parse(name, value)?,
+            "chat__filters" => chat.filters = list(value),
+            "chat__profanity" => chat.profanity = list(value),
+            "chat__drop_words" => chat.drop_words = list(value),
+            "store__db" => store.db = optional(value).map(PathBuf::from),
+            "store__retention_days" => {
+                store.retention_days = parse_optional(name, value)?;
+            }
+            _ => return Err(format!("{} is not a setting", name)),
+        }
+        Ok(())
+    }
+
+    /// Refuses the settings the server cannot run with.
+    fn validate(&self) -> Result<(), String> {
+        for (key, url) in &[
+            ("janus.url", &self.janus.url),
+            ("janus.admin_url", &self.janus.admin_url),
+        ] {
+            let known = ["ws://", "wss://", "http://", "https://"];
+            if !known.iter().any(|scheme| url.starts_with(scheme)) {
+                return Err(format!("{} is not a ws(s):// or http(s):// URL", key));
+            }
+        }
+        if self.janus.keepalive_secs == 0 {
+            return Err("janus.keepalive_secs must be more than 0".to_string());
+        }
+        if self.janus.requests_per_second == 0 || self.janus.requests_burst == 0 {
+            return Err(
+                "janus.requests_per_second and janus.requests_burst must be more than 0"
+                    .to_string(),
+            );
+        }
+        if self.chat.ban_minutes == 0 || self.chat.ban_minutes > MAX_BAN_MINUTES {
+            return Err(format!(
+                "chat.ban_minutes must be from 1 to {}",
+                MAX_BAN_MINUTES
+            ));
+        }
+        if let Some(unknown) = self
+            .chat
+            .filters
+            .iter()
+            .find(|name| !filters::NAMES.contains(&name.as_str()))
+        {
+            return Err(format!("chat.filters: no such filter: {}", unknown));
+        }
+        if self.chat.ping_secs == 0 {
+            return Err("chat.ping_secs must be more than 0".to_string());
+        }
+        if self.store.retention_days == Some(0) {
+            return Err("store.retention_days must be more than 0".to_string());
+        }
+        if self.rooms.capacity == Some(0) {
+            return Err("rooms.capacity must be more than 0".to_string());
+        }
+        if self.limits.max_text == Some(0) || self.limits.max_message == Some(0) {
+            return Err("limits.max_text and limits.max_message must be more than 0".to_string());
+        }
+        for (key, limit) in &[
+            ("limits.rate_limit", &self.limits.rate_limit),
+            ("limits.signal_rate_limit", &self.limits.signal_rate_limit),
+        ] {
+            if let Some(limit) = limit {
+                if limit != "off" && RateLimit::parse(limit).is_none() {
+                    return Err(format!(
+                        "{} {} is not like 5/s, 60/min, 600/h or off",
+                        key, limit
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `value` of variable `name`, as the setting it overrides.
+fn parse<T>(name: &str, value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("{} {}: {}", name, value, e))
+}
+
+/// `value` of variable `name`, unless it is empty.
+fn parse_optional<T>(name: &str, value: &str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse(name, value).map(Some)
+}
+
+/// `value`, unless it is empty.
+fn optional(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+/// The comma separated items of `value`.
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn app_variables_override_the_file() {
+        let mut config: Config = toml::from_str("[rooms]\nhistory = 10\n").unwrap();
+        config
+            .override_with(vars(&[("APP_ROOMS__HISTORY", "20")]))
+            .unwrap();
+
+        assert_eq!(config.rooms.history, 20);
+    }
+
+    #[test]
+    fn legacy_variables_lose_to_app_variables() {
+        let mut config = Config::default();
+        config
+            .override_with(vars(&[
+                ("APP_ROOMS__HISTORY", "20"),
+                ("CHAT_HISTORY", "10"),
+                ("CHAT_ROOM_CAPACITY", "5"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.rooms.history, 20);
+        assert_eq!(config.rooms.capacity, Some(5));
+    }
+
+    #[test]
+    fn port_applies_to_the_listen_address_whatever_the_order() {
+        for order in &[
+            [
+                ("APP_SERVER__PORT", "9000"),
+                ("APP_SERVER__LISTEN", "10.0.0.1:80"),
+            ],
+            [
+                ("APP_SERVER__LISTEN", "10.0.0.1:80"),
+                ("APP_SERVER__PORT", "9000"),
+            ],
+        ] {
+            let mut config = Config::default();
+            config.override_with(vars(order)).unwrap();
+
+            assert_eq!(config.server.listen, "10.0.0.1:9000".parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn empty_values_clear_optional_settings() {
+        let mut config = Config::default();
+        config.janus.apisecret = Some("janusrocks".to_string());
+        config
+            .override_with(vars(&[("APP_JANUS__APISECRET", "")]))
+            .unwrap();
+
+        assert_eq!(config.janus.apisecret, None);
+    }
+
+    #[test]
+    fn lists_are_separated_by_commas() {
+        let mut config = Config::default();
+        config
+            .override_with(vars(&[("CHAT_FILTERS", "drop, links,")]))
+            .unwrap();
+
+        assert_eq!(config.chat.filters, vec!["drop", "links"]);
+    }
+
+    #[test]
+    fn unknown_settings_are_refused() {
+        let mut config = Config::default();
+
+        assert!(config
+            .override_with(vars(&[("APP_JANUS__NOPE", "1")]))
+            .is_err());
+        assert!(config.override_with(vars(&[("APP_ENV", "prod")])).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::time;
+
+use super::client::connect;
+use super::engine::{self, Engine, Queues};
+use super::event::EventDispatcher;
+use super::protocol::AdminRequest;
+use super::tls::TlsConfig;
+use super::{Config, Error, Result};
+
+/// The websocket subprotocol the Janus Admin API is served under.
+const SUBPROTOCOL: &str = "janus-admin-protocol";
+
+/// Settings of the Admin API client.
+#[derive(Clone)]
+pub struct AdminConfig {
+    /// Websocket address of the gateway's Admin API.
+    pub url: String,
+    /// The `admin_secret` configured on the gateway, sent along with every
+    /// request.
+    pub admin_secret: Option<String>,
+    /// How `wss://` connections are secured.
+    pub tls: TlsConfig,
+    /// How long to wait before connecting again after a failure or drop.
+    pub reconnect_delay: Duration,
+    /// How long to wait for the reply to a request.
+    pub request_timeout: Duration,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            url: "ws://127.0.0.1:7188/admin".to_string(),
+            admin_secret: None,
+            tls: TlsConfig::default(),
+            reconnect_delay: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("url", &self.url)
+            .field("admin_secret", &self.admin_secret.as_ref().map(|_| ".."))
+            .field("tls", &self.tls)
+            .field("reconnect_delay", &self.reconnect_delay)
+            .field("request_timeout", &self.request_timeout)
+            .finish()
+    }
+}
+
+/// Handle to a connection to the gateway's Admin API, for looking into
+/// the sessions and handles the gateway holds and tuning it at runtime.
+///
+/// The Admin API has no sessions of its own, so there is nothing to set up
+/// or keep alive: the connection task only reconnects when the connection
+/// drops.
+///
+/// Cloning it is cheap, every clone talks to the same connection task.
+#[derive(Clone)]
+pub struct AdminClient {
+    config: Arc<AdminConfig>,
+    engine: Engine,
+}
+
+impl AdminClient {
+    /// Starts the connection task in the background and returns a handle
+    /// to it.
+    pub fn spawn(config: AdminConfig) -> AdminClient {
+        let (client, queues) = AdminClient::new(config);
+        tokio::task::spawn(run(client.engine.clone(), queues));
+        client
+    }
+
+    /// A client without a connection task, for running without a gateway:
+    /// every request fails with `Error::Closed` at once.
+    pub fn offline(config: AdminConfig) -> AdminClient {
+        AdminClient::new(config).0
+    }
+
+    fn new(config: AdminConfig) -> (AdminClient, Queues) {
+        let (engine, queues) = Engine::new(Arc::new(Config {
+            urls: vec![config.url.clone()],
+            tls: config.tls.clone(),
+            reconnect_delay: config.reconnect_delay,
+            request_timeout: config.request_timeout,
+            ..Config::default()
+        }));
+        let client = AdminClient {
+            config: Arc::new(config),
+            engine,
+        };
+        (client, queues)
+    }
+
+    pub fn config(&self) -> &AdminConfig {
+        &self.config
+    }
+
+    /// Closes the connection for good.
+    pub async fn shutdown(&self) {
+        self.engine.close().await;
+    }
+
+    /// Sends `request` with our `admin_secret` and returns the whole reply.
+    pub async fn request(&self, request: AdminRequest) -> Result<Value> {
+        let mut request = serde_json::to_value(request)?;
+        if let Some(secret) = &self.config.admin_secret {
+            request["admin_secret"] = Value::String(secret.clone());
+        }
+        self.engine.send(request, self.engine.options()).await
+    }
+
+    /// The ids of every session on the gateway.
+    pub async fn list_sessions(&self) -> Result<Vec<u64>> {
+        let reply = self.request(AdminRequest::ListSessions).await?;
+        field(reply, "sessions")
+    }
+
+    /// The ids of the handles attached to a session.
+    pub async fn list_handles(&self, session_id: u64) -> Result<Vec<u64>> {
+        let reply = self
+            .request(AdminRequest::ListHandles { session_id })
+            .await?;
+        field(reply, "handles")
+    }
+
+    /// Everything the gateway knows about a handle: its plugin, the state of
+    /// its PeerConnection, its media stats and so on.
+    pub async fn handle_info(&self, session_id: u64, handle_id: u64) -> Result<Value> {
+        let reply = self
+            .request(AdminRequest::HandleInfo {
+                session_id,
+                handle_id,
+            })
+            .await?;
+        field(reply, "info")
+    }
+
+    /// Changes how much the gateway logs, from 0 (nothing) to 7 (everything),
+    /// and returns the level now in use.
+    pub async fn set_log_level(&self, level: u8) -> Result<u8> {
+        let reply = self.request(AdminRequest::SetLogLevel { level }).await?;
+        field(reply, "level")
+    }
+}
+
+/// Takes `key` out of a reply.
+fn field<T: DeserializeOwned>(mut reply: Value, key: &str) -> Result<T> {
+    match reply.get_mut(key).map(Value::take) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Err(Error::Unexpected(reply.to_string())),
+    }
+}
+
+/// Keeps the connection to the Admin API alive until `shutdown`,
+/// connecting again `reconnect_delay` after every failure or drop.
+async fn run(engine: Engine, mut queues: Queues) {
+    let config = engine.config();
+    // The Admin API sends no events, anything unexpected just gets logged.
+    let events = EventDispatcher::default();
+
+    while !queues.closing() {
+        match connect(&config.urls[0], &config.tls, SUBPROTOCOL).await {
+            Ok(socket) => {
+                match engine::serve(socket, &mut queues, &config.transaction_ids, &events).await {
+                    Ok(()) => eprintln!("janus admin connection closed"),
+                    Err(e) => eprintln!("janus admin connection error: {}", e),
+                }
+            }
+            Err(e) => eprintln!("janus admin connection error: {}", e),
+        }
+        time::delay_for(config.reconnect_delay).await;
+    }
+}
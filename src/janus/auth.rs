@@ -0,0 +1,39 @@
+use std::fmt;
+
+use serde_json::Value;
+
+/// How our requests authenticate with the gateway.
+///
+/// The secret is never printed, `Debug` only shows which kind of
+/// authentication is used.
+#[derive(Clone, Default)]
+pub enum JanusAuth {
+    /// The gateway does not require authentication.
+    #[default]
+    None,
+    /// The shared `apisecret` configured on the gateway.
+    ApiSecret(String),
+    /// A token added to the gateway through its admin API.
+    Token(String),
+}
+
+impl JanusAuth {
+    /// Adds our credentials to an outgoing request.
+    pub fn apply(&self, request: &mut Value) {
+        match self {
+            JanusAuth::None => {}
+            JanusAuth::ApiSecret(secret) => request["apisecret"] = Value::String(secret.clone()),
+            JanusAuth::Token(token) => request["token"] = Value::String(token.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for JanusAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JanusAuth::None => f.write_str("None"),
+            JanusAuth::ApiSecret(_) => f.write_str("ApiSecret(..)"),
+            JanusAuth::Token(_) => f.write_str("Token(..)"),
+        }
+    }
+}
@@ -0,0 +1,687 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::{Sink, SinkExt, Stream};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time;
+use tokio_tungstenite::client_async_tls_with_config;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::SEC_WEBSOCKET_PROTOCOL;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use super::auth::JanusAuth;
+use super::engine::{self, Engine, Queues};
+use super::event::{Event, EventDispatcher, JanusEventHandler};
+use super::handle::{Handle, HandleManager, PluginReply};
+use super::hooks::{ReconnectHooks, Reconnected};
+use super::http;
+use super::metrics::Metrics;
+use super::protocol::{Request, Response, ServerInfo};
+use super::ratelimit::RateLimit;
+use super::session::{KeepaliveEnd, SessionManager};
+use super::tls::TlsConfig;
+use super::transaction::TransactionIds;
+use super::{Error, Result};
+
+/// The websocket subprotocol the Janus API is served under.
+const SUBPROTOCOL: &str = "janus-protocol";
+
+/// Settings of the Janus client.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Addresses of the gateway API, the primary server first: `ws://` or
+    /// `wss://` ones for its websocket transport, `http://` or `https://`
+    /// ones for its REST transport, with events long-polled.
+    ///
+    /// When a server cannot be reached we fail over to the next one, and
+    /// keep probing the primary to fail back to it.
+    pub urls: Vec<String>,
+    /// Credentials sent along with every request.
+    pub auth: JanusAuth,
+    /// How `wss://` connections are secured.
+    pub tls: TlsConfig,
+    /// Plugin our handle gets attached to.
+    pub plugin: String,
+    /// How long to wait before connecting again after a failure or drop.
+    pub reconnect_delay: Duration,
+    /// How often the primary server is probed while we are on another one.
+    pub failback_interval: Duration,
+    /// How often the session is kept alive. Janus drops sessions that stay
+    /// quiet for 60 seconds by default.
+    pub keepalive_interval: Duration,
+    /// How many keepalives in a row may go without `ack` before the
+    /// connection counts as dead and is opened again.
+    pub keepalive_misses: u32,
+    /// How long to wait for the reply to a request by default.
+    pub request_timeout: Duration,
+    /// How many requests may queue up towards the connection task, e.g.
+    /// while the gateway is unreachable.
+    pub queue_capacity: usize,
+    /// How many requests per second may go out, keepalives and claims
+    /// aside. Unlimited when `None`.
+    pub rate_limit: Option<RateLimit>,
+    /// Whether a request that finds the queue full, or the rate limit
+    /// reached, waits instead of failing with `Error::Busy` or
+    /// `Error::RateLimited`.
+    pub wait_when_busy: bool,
+    /// Where the transaction strings of our requests come from.
+    pub transaction_ids: TransactionIds,
+    /// How many events of a handle may wait for a slow subscriber of
+    /// `Handle::events` before the oldest ones are dropped.
+    pub event_capacity: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            urls: vec!["ws://127.0.0.1:8188/janus".to_string()],
+            auth: JanusAuth::default(),
+            tls: TlsConfig::default(),
+            plugin: "janus.plugin.videoroom".to_string(),
+            reconnect_delay: Duration::from_secs(1),
+            failback_interval: Duration::from_secs(30),
+            keepalive_interval: Duration::from_secs(30),
+            keepalive_misses: 2,
+            request_timeout: Duration::from_secs(10),
+            queue_capacity: 64,
+            rate_limit: None,
+            wait_when_busy: false,
+            transaction_ids: TransactionIds::default(),
+            event_capacity: 32,
+        }
+    }
+}
+
+/// Sets up a `JanusClient`, starting from the default `Config`.
+#[derive(Debug, Default)]
+pub struct JanusClientBuilder {
+    config: Config,
+    /// Whether `url` was called, and the default url is gone.
+    urls: bool,
+}
+
+impl JanusClientBuilder {
+    /// Adds a server to connect to. The first one replaces the default, the
+    /// others are failed over to in the order they were added.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        if !self.urls {
+            self.config.urls.clear();
+            self.urls = true;
+        }
+        self.config.urls.push(url.into());
+        self
+    }
+
+    pub fn auth(mut self, auth: JanusAuth) -> Self {
+        self.config.auth = auth;
+        self
+    }
+
+    /// Authenticates with the `apisecret` configured on the gateway.
+    pub fn apisecret(mut self, secret: impl Into<String>) -> Self {
+        self.config.auth = JanusAuth::ApiSecret(secret.into());
+        self
+    }
+
+    /// Authenticates with a token added to the gateway.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.config.auth = JanusAuth::Token(token.into());
+        self
+    }
+
+    /// Attaches our main handle to `plugin` rather than the VideoRoom.
+    pub fn plugin(mut self, plugin: impl Into<String>) -> Self {
+        self.config.plugin = plugin.into();
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls = tls;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.config.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = timeout;
+        self
+    }
+
+    /// Changes whatever else needs changing.
+    pub fn configure(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(&mut self.config);
+        self
+    }
+
+    /// Starts the client without waiting for it to connect, see
+    /// `JanusClient::spawn`.
+    pub fn spawn(self) -> JanusClient {
+        JanusClient::spawn(self.config)
+    }
+
+    /// A client that never connects, see `JanusClient::offline`.
+    pub fn offline(self) -> JanusClient {
+        JanusClient::offline(self.config)
+    }
+
+    /// Starts the client and waits until it is ready, giving up after
+    /// `request_timeout`.
+    pub async fn connect(self) -> Result<JanusClient> {
+        let timeout = self.config.request_timeout;
+        let client = self.spawn();
+        match time::timeout(timeout, client.ready()).await {
+            Ok(Ok(())) => Ok(client),
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                client.shutdown().await;
+                Err(Error::Timeout)
+            }
+        }
+    }
+}
+
+/// Handle used by the rest of the program to talk to the gateway.
+///
+/// Cloning it is cheap, every clone talks to the same connection task.
+#[derive(Clone)]
+pub struct JanusClient {
+    engine: Engine,
+    sessions: SessionManager,
+    handles: HandleManager,
+    events: EventDispatcher,
+    hooks: ReconnectHooks,
+    info: Arc<RwLock<Option<ServerInfo>>>,
+    ready: watch::Receiver<bool>,
+}
+
+impl JanusClient {
+    /// Starts setting up a client, for those who only need to change a few
+    /// settings, e.g.
+    /// `JanusClient::builder().url("ws://10.0.0.2:8188/janus").apisecret("...").connect().await`.
+    pub fn builder() -> JanusClientBuilder {
+        JanusClientBuilder::default()
+    }
+
+    /// Starts the connection task in the background and returns a handle
+    /// to it.
+    pub fn spawn(config: Config) -> JanusClient {
+        let (client, queues, ready_tx) = JanusClient::new(config);
+        tokio::task::spawn(run(client.clone(), queues, ready_tx));
+        client
+    }
+
+    /// A client without a connection task, for running without a gateway:
+    /// every request fails with `Error::Closed` at once.
+    pub fn offline(config: Config) -> JanusClient {
+        let (client, _, _) = JanusClient::new(config);
+        client
+    }
+
+    fn new(config: Config) -> (JanusClient, Queues, watch::Sender<bool>) {
+        let (engine, queues) = Engine::new(Arc::new(config));
+        let sessions = SessionManager::new(engine.clone());
+        let events = EventDispatcher::default();
+        let handles = HandleManager::new(engine.clone(), sessions.clone(), events.clone());
+        let (ready_tx, ready_rx) = watch::channel(false);
+
+        let client = JanusClient {
+            engine,
+            sessions,
+            handles,
+            events,
+            hooks: ReconnectHooks::default(),
+            info: Arc::default(),
+            ready: ready_rx,
+        };
+        (client, queues, ready_tx)
+    }
+
+    /// Sends `request` and waits for the reply carrying the same
+    /// transaction.
+    pub async fn request(&self, request: Request) -> Result<Response> {
+        self.engine.request(request).await
+    }
+
+    /// What the gateway we are connected to told about itself, once we
+    /// were connected.
+    pub fn info(&self) -> Option<ServerInfo> {
+        self.info.read().unwrap().clone()
+    }
+
+    /// How quickly the gateway replied to our requests, by request.
+    pub fn metrics(&self) -> &Metrics {
+        self.engine.metrics()
+    }
+
+    /// The manager of our Janus session.
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+
+    /// The manager of the plugin handles attached to our session.
+    pub fn handles(&self) -> &HandleManager {
+        &self.handles
+    }
+
+    /// Adds `handler` to the ones receiving the events of the gateway.
+    pub fn register_handler<H>(&self, handler: H)
+    where
+        H: JanusEventHandler + 'static,
+    {
+        self.events.register(Arc::new(handler));
+    }
+
+    /// Adds `hook` to the callbacks run after every reconnect, once the
+    /// session is back and the handles are attached, to restore whatever
+    /// else the application needs on the gateway.
+    ///
+    /// The hooks run in a task of their own, one after the other, while
+    /// requests already flow again.
+    pub fn on_reconnect<F, Fut>(&self, hook: F)
+    where
+        F: Fn(JanusClient, Reconnected) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.hooks.register(hook);
+    }
+
+    /// Waits until the connection has a session with its handles attached.
+    pub async fn ready(&self) -> Result<()> {
+        let mut ready = self.ready.clone();
+        loop {
+            if *ready.borrow() {
+                return Ok(());
+            }
+            if ready.recv().await.is_none() {
+                return Err(Error::Closed);
+            }
+        }
+    }
+
+    /// Sends `body` to the plugin of our main handle and returns the data of
+    /// the plugin's reply.
+    pub async fn message(&self, body: Value) -> Result<Value> {
+        self.ready().await?;
+        self.handles
+            .message(&self.engine.config().plugin, body)
+            .await
+    }
+
+    /// Sends `body` and the browser's `jsep` to the plugin behind the handle
+    /// registered under `key`, and returns the plugin's answer along with
+    /// the gateway's `jsep`.
+    pub async fn message_with_jsep(
+        &self,
+        key: &str,
+        body: Value,
+        jsep: Option<Value>,
+    ) -> Result<PluginReply> {
+        self.ready().await?;
+        self.handles.message_with_jsep(key, body, jsep).await
+    }
+
+    /// Like `message_with_jsep`, but hands back the plugin's answer as it
+    /// is, see `HandleManager::message_raw`.
+    pub async fn message_raw(
+        &self,
+        key: &str,
+        body: Value,
+        jsep: Option<Value>,
+    ) -> Result<PluginReply> {
+        self.ready().await?;
+        self.handles.message_raw(key, body, jsep).await
+    }
+
+    /// Leaves the gateway cleanly before the program exits: detaches our
+    /// handles, destroys our session and closes the connection, which is
+    /// not opened again.
+    ///
+    /// Whatever cannot be cleaned up, e.g. because the gateway is down, is
+    /// left for the gateway to time out.
+    pub async fn shutdown(&self) {
+        for key in self.handles.keys() {
+            if let Err(e) = self.handles.detach(&key).await {
+                eprintln!("janus handle {} could not be detached: {}", key, e);
+            }
+        }
+        if let Err(e) = self.sessions.destroy().await {
+            eprintln!("janus session could not be destroyed: {}", e);
+        }
+        self.engine.close().await;
+    }
+
+    /// The handle registered under `key`, attaching a new one to our plugin
+    /// first if there is none.
+    pub async fn handle(&self, key: &str) -> Result<Handle> {
+        self.ready().await?;
+        match self.handles.get(key) {
+            Some(handle) => Ok(handle),
+            None => self.handles.attach(key, &self.engine.config().plugin).await,
+        }
+    }
+
+    /// Like `handle`, for a handle attached to `plugin` rather than to our
+    /// main one.
+    pub async fn plugin_handle(&self, key: &str, plugin: &str) -> Result<Handle> {
+        self.ready().await?;
+        match self.handles.get(key) {
+            Some(handle) => Ok(handle),
+            None => self.handles.attach(key, plugin).await,
+        }
+    }
+
+    /// Hands an ICE candidate of a browser to the handle registered under
+    /// `key`.
+    pub async fn trickle(&self, key: &str, candidate: Value) -> Result<()> {
+        self.ready().await?;
+        self.handles.trickle(key, candidate).await
+    }
+}
+
+/// How a connection that was up came to an end.
+enum Disconnect {
+    /// The connection closed or dropped.
+    Closed,
+    /// We left for the primary server, which is reachable again.
+    FailBack,
+}
+
+/// Keeps a connection to the gateway alive forever.
+///
+/// Whenever the connection fails or drops we wait `reconnect_delay` and
+/// start over: connect, take over or create the session and attach the
+/// handles. A dropped connection is retried on the same server, so the
+/// session can be claimed, while a server we cannot connect to makes us
+/// fail over to the next one in `urls`.
+///
+/// Only a `shutdown` ends it.
+async fn run(client: JanusClient, mut queues: Queues, ready: watch::Sender<bool>) {
+    let config = client.engine.config();
+    let mut server = 0;
+    let mut bootstrapped = false;
+
+    loop {
+        if queues.closing() {
+            eprintln!("janus client shut down");
+            return;
+        }
+
+        let result = match connect(&config.urls[server], &config.tls, SUBPROTOCOL).await {
+            Ok(socket) => {
+                run_connection(
+                    &client,
+                    socket,
+                    &mut queues,
+                    &ready,
+                    server != 0,
+                    &mut bootstrapped,
+                )
+                .await
+            }
+            Err(e) => {
+                eprintln!("janus connection error: {}", e);
+                if config.urls.len() > 1 {
+                    server = (server + 1) % config.urls.len();
+                    // Sessions do not move between servers.
+                    client.sessions.forget();
+                    eprintln!("janus failing over to {}", config.urls[server]);
+                }
+                time::delay_for(config.reconnect_delay).await;
+                continue;
+            }
+        };
+        let _ = ready.broadcast(false);
+        if queues.closing() {
+            continue;
+        }
+
+        match result {
+            Ok(Disconnect::Closed) => eprintln!("janus connection closed"),
+            Ok(Disconnect::FailBack) => {
+                eprintln!("janus failing back to {}", config.urls[0]);
+                server = 0;
+                client.sessions.forget();
+                continue;
+            }
+            Err(e) => eprintln!("janus connection error: {}", e),
+        }
+        time::delay_for(config.reconnect_delay).await;
+    }
+}
+
+/// Serves a single connection until it drops: creates the session, then
+/// keeps it alive while commands and replies flow.
+///
+/// Every connection after the first one runs the reconnect hooks, and so
+/// does a session that expired on the gateway: it is replaced with a new
+/// one and the handles are attached again, as if we had reconnected.
+///
+/// On a server other than the primary we also probe the primary, and leave
+/// once it can be reached again.
+async fn run_connection<S>(
+    client: &JanusClient,
+    socket: S,
+    queues: &mut Queues,
+    ready: &watch::Sender<bool>,
+    probe_primary: bool,
+    bootstrapped: &mut bool,
+) -> Result<Disconnect>
+where
+    S: Stream<Item = tungstenite::Result<Message>> + Sink<Message, Error = tungstenite::Error>,
+{
+    let config = client.engine.config();
+    let mut timeouts = client.events.timeouts();
+    let serve = engine::serve(socket, queues, &config.transaction_ids, &client.events);
+    tokio::pin!(serve);
+
+    let claimed = tokio::select! {
+        result = &mut serve => return result.map(|()| Disconnect::Closed),
+        result = bootstrap(client) => result?,
+    };
+    let _ = ready.broadcast(true);
+
+    if *bootstrapped {
+        run_hooks(client, claimed);
+    }
+    *bootstrapped = true;
+
+    let failback = probe(&config.urls[0], &config.tls, config.failback_interval);
+    tokio::pin!(failback);
+    loop {
+        let expired = tokio::select! {
+            result = &mut serve => return result.map(|()| Disconnect::Closed),
+            end = client.sessions.keepalive() => match end {
+                KeepaliveEnd::Expired(session_id) => session_id,
+                KeepaliveEnd::Unanswered(session_id) => {
+                    eprintln!("janus connection is dead, reconnecting");
+                    client.events.dispatch(Event::KeepaliveMissed {
+                        session_id,
+                        missed: config.keepalive_misses,
+                    });
+                    return Ok(Disconnect::Closed);
+                }
+                KeepaliveEnd::Destroyed => return Ok(Disconnect::Closed),
+            },
+            timeout = timeouts.recv() => match timeout {
+                Ok(session_id) if client.sessions.id() == Some(session_id) => session_id,
+                _ => continue,
+            },
+            _ = &mut failback, if probe_primary => break,
+        };
+
+        eprintln!("janus session {} expired, starting over", expired);
+        let _ = ready.broadcast(false);
+        client.sessions.forget();
+        tokio::select! {
+            result = &mut serve => return result.map(|()| Disconnect::Closed),
+            result = open_session(client) => result.map(|_| ())?,
+        }
+        let _ = ready.broadcast(true);
+        run_hooks(client, false);
+    }
+
+    // Leave nothing behind on the server we are leaving.
+    tokio::select! {
+        _ = &mut serve => {}
+        result = client.sessions.destroy() => {
+            if let Err(e) = result {
+                eprintln!("janus session could not be destroyed: {}", e);
+            }
+        }
+    }
+    Ok(Disconnect::FailBack)
+}
+
+/// Runs the reconnect hooks in the background, for the session we have
+/// now.
+fn run_hooks(client: &JanusClient, claimed: bool) {
+    if let Some(session_id) = client.sessions.id() {
+        let reconnected = Reconnected {
+            session_id,
+            claimed,
+        };
+        let client = client.clone();
+        tokio::task::spawn(async move { client.hooks.run(client.clone(), reconnected).await });
+    }
+}
+
+/// Returns once a connection to `url` can be opened again, trying every
+/// `interval`.
+async fn probe(url: &str, tls: &TlsConfig, interval: Duration) {
+    loop {
+        time::delay_for(interval).await;
+        if let Ok(mut socket) = connect(url, tls, SUBPROTOCOL).await {
+            let _ = socket.close().await;
+            return;
+        }
+    }
+}
+
+/// A connection to the gateway, whichever transport it uses.
+pub trait Transport:
+    Stream<Item = tungstenite::Result<Message>> + Sink<Message, Error = tungstenite::Error> + Send
+{
+}
+
+impl<T> Transport for T where
+    T: Stream<Item = tungstenite::Result<Message>>
+        + Sink<Message, Error = tungstenite::Error>
+        + Send
+{
+}
+
+pub type Socket = Pin<Box<dyn Transport>>;
+
+/// Opens a connection to the gateway: to its REST API for `http://` and
+/// `https://` urls, to its websocket API otherwise.
+///
+/// The gateway only speaks its websocket APIs under their own subprotocol,
+/// such as `janus-protocol`, so a handshake that does not settle on
+/// `subprotocol` fails right away instead of leaving us waiting for replies
+/// that never come.
+///
+/// `wss://` and `https://` urls are secured according to `tls`.
+pub async fn connect(url: &str, tls: &TlsConfig, subprotocol: &'static str) -> Result<Socket> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let socket = http::connect(url, tls).await?;
+        eprintln!("connected to janus at {}", url);
+        return Ok(Box::pin(socket));
+    }
+
+    let mut request = url.into_client_request()?;
+    request.headers_mut().insert(
+        SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(subprotocol),
+    );
+
+    let uri = request.uri();
+    let secure = uri.scheme_str() == Some("wss");
+    let host = uri.host().unwrap_or_default();
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+    let stream = TcpStream::connect((host, port)).await?;
+
+    let connector = if secure { Some(tls.connector()?) } else { None };
+    let (socket, response) = client_async_tls_with_config(request, stream, None, connector).await?;
+    match response.headers().get(SEC_WEBSOCKET_PROTOCOL) {
+        Some(protocol) if protocol == subprotocol => {}
+        protocol => {
+            return Err(Error::Subprotocol(
+                protocol.map(|p| String::from_utf8_lossy(p.as_bytes()).into_owned()),
+            ))
+        }
+    }
+    eprintln!("connected to janus at {}", url);
+
+    Ok(Box::pin(socket))
+}
+
+/// Gets a session for the new connection and makes sure our plugin
+/// handles are attached to it.
+///
+/// A session that survived the previous connection is claimed, keeping its
+/// handles (and whatever they joined) alive across brief network blips.
+/// Only when that fails a new session is created and the handles attached
+/// again.
+///
+/// Before that we ask the gateway about itself, and refuse to go on with a
+/// gateway that lacks our plugin.
+///
+/// Returns whether the session was claimed.
+async fn bootstrap(client: &JanusClient) -> Result<bool> {
+    let config = client.engine.config();
+    let reply = client
+        .engine
+        .request_raw(Request::Info, client.engine.options())
+        .await?;
+    let info: ServerInfo = serde_json::from_value(reply)?;
+    eprintln!("janus server: {} {}", info.name, info.version_string);
+    let has_plugin = info.plugins.contains_key(&config.plugin);
+    *client.info.write().unwrap() = Some(info);
+    if !has_plugin {
+        return Err(Error::NoPlugin(config.plugin.clone()));
+    }
+
+    open_session(client).await
+}
+
+/// Claims the session we had, or creates a new one and attaches the
+/// handles to it, then attaches the handle for our plugin unless we have
+/// it already.
+///
+/// Returns whether the session was claimed.
+async fn open_session(client: &JanusClient) -> Result<bool> {
+    let config = client.engine.config();
+    let claimed = match client.sessions.id() {
+        Some(_) => match client.sessions.claim().await {
+            Ok(session_id) => {
+                eprintln!("janus session {} claimed", session_id);
+                true
+            }
+            Err(e) => {
+                eprintln!("janus session could not be claimed: {}", e);
+                false
+            }
+        },
+        None => false,
+    };
+    if !claimed {
+        let session_id = client.sessions.create().await?;
+        eprintln!("janus session {}", session_id);
+        client.handles.reattach().await?;
+    }
+
+    let plugin = &config.plugin;
+    if client.handles.get(plugin).is_none() {
+        client.handles.attach(plugin, plugin).await?;
+    }
+
+    Ok(claimed)
+}
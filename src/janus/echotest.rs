@@ -0,0 +1,64 @@
+//! A round trip through the EchoTest plugin, `janus.plugin.echotest`, to
+//! check that the whole signaling path to the gateway works and how fast.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+
+use super::{Error, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.echotest";
+
+/// Tells the handles of round trips running at the same time apart.
+static NEXT_ROUND_TRIP: AtomicUsize = AtomicUsize::new(1);
+
+/// How long every step of a round trip took, in milliseconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct RoundTrip {
+    pub session_id: Option<u64>,
+    pub attach_ms: f64,
+    pub message_ms: f64,
+    pub detach_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Attaches a handle to the plugin, has it answer a message and detaches
+/// it again.
+pub async fn round_trip(janus: &JanusClient) -> Result<RoundTrip> {
+    let key = format!(
+        "echotest/{}",
+        NEXT_ROUND_TRIP.fetch_add(1, Ordering::Relaxed)
+    );
+    let started = Instant::now();
+
+    janus.plugin_handle(&key, PLUGIN).await?;
+    let attached = Instant::now();
+
+    // The plugin replies with {"echotest": "event", "result": "ok"}
+    let body = json!({ "audio": true, "video": true });
+    let reply = janus.message_with_jsep(&key, body, None).await;
+    let answered = Instant::now();
+
+    let detached = janus.handles().detach(&key).await;
+    let done = Instant::now();
+
+    let reply = reply?;
+    if reply.data["result"] != "ok" {
+        return Err(Error::Unexpected(reply.data.to_string()));
+    }
+    detached?;
+
+    Ok(RoundTrip {
+        session_id: janus.sessions().id(),
+        attach_ms: millis(attached - started),
+        message_ms: millis(answered - attached),
+        detach_ms: millis(done - answered),
+        total_ms: millis(done - started),
+    })
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, Sink, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::time;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tracing::{debug, debug_span, field, Instrument, Span};
+
+use super::event::{Event, EventDispatcher};
+use super::metrics::Metrics;
+use super::protocol::{ErrorInfo, Request, Response};
+use super::ratelimit::TokenBucket;
+use super::transaction::TransactionIds;
+use super::{Config, Error, JanusError, Result};
+
+/// A request waiting to be written to the gateway.
+pub struct Command {
+    request: Value,
+    wait_event: bool,
+    reply: oneshot::Sender<Value>,
+    /// The span of the request, for following it through the queue.
+    span: Span,
+}
+
+/// A request written to the gateway, waiting for its reply.
+struct Waiting {
+    /// The `janus` of the request, such as `attach`.
+    kind: String,
+    sent: time::Instant,
+    wait_event: bool,
+    reply: oneshot::Sender<Value>,
+    span: Span,
+}
+
+/// How long to wait for the reply to a request, and how often to try
+/// again when it does not come.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestOptions {
+    /// How long to wait for the reply.
+    pub timeout: Duration,
+    /// How many more times the request is sent after a timeout. Only
+    /// idempotent requests such as `keepalive` should be retried.
+    pub retries: u32,
+    /// Whether an `ack` is only the first of two replies, and the one to
+    /// wait for is the `event` with the plugin's answer.
+    pub wait_event: bool,
+    /// Whether the request jumps the queue of ordinary commands. Meant for
+    /// the few requests that keep the session alive, such as `keepalive`
+    /// and `claim`.
+    pub priority: bool,
+}
+
+/// How long to wait for the gateway to answer our close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The receiving ends the connection task reads its commands from.
+pub struct Queues {
+    commands: mpsc::Receiver<Command>,
+    priority: mpsc::UnboundedReceiver<Command>,
+    closing: watch::Receiver<bool>,
+    metrics: Arc<Metrics>,
+}
+
+impl Queues {
+    /// Whether the engine was closed, and the connection is not to be
+    /// opened again.
+    pub fn closing(&self) -> bool {
+        *self.closing.borrow()
+    }
+}
+
+/// Sends requests to the connection task and pairs them with their
+/// replies.
+///
+/// Cloning it is cheap, every clone talks to the same connection task.
+#[derive(Clone)]
+pub struct Engine {
+    config: Arc<Config>,
+    commands: mpsc::Sender<Command>,
+    priority: mpsc::UnboundedSender<Command>,
+    closing: Arc<Mutex<watch::Sender<bool>>>,
+    limiter: Option<Arc<TokenBucket>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Engine {
+    /// Creates an engine along with the receiving ends the connection task
+    /// has to `serve`.
+    pub fn new(config: Arc<Config>) -> (Engine, Queues) {
+        let (commands_tx, commands_rx) = mpsc::channel(config.queue_capacity);
+        let (priority_tx, priority_rx) = mpsc::unbounded_channel();
+        let (closing_tx, closing_rx) = watch::channel(false);
+        let limiter = config
+            .rate_limit
+            .map(|limit| Arc::new(TokenBucket::new(limit)));
+        let metrics = Arc::new(Metrics::default());
+        let engine = Engine {
+            config,
+            commands: commands_tx,
+            priority: priority_tx,
+            closing: Arc::new(Mutex::new(closing_tx)),
+            limiter,
+            metrics: metrics.clone(),
+        };
+        let queues = Queues {
+            commands: commands_rx,
+            priority: priority_rx,
+            closing: closing_rx,
+            metrics,
+        };
+        (engine, queues)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// How quickly the gateway replied so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Makes the connection task close the connection with a close frame
+    /// and stop, instead of connecting again, and waits until it is gone.
+    pub async fn close(&self) {
+        let mut closing = self.closing.lock().await;
+        let _ = closing.broadcast(true);
+        closing.closed().await;
+    }
+
+    /// The options requests are sent with unless told otherwise.
+    pub fn options(&self) -> RequestOptions {
+        RequestOptions {
+            timeout: self.config.request_timeout,
+            retries: 0,
+            wait_event: false,
+            priority: false,
+        }
+    }
+
+    /// Sends `request` with the default options and waits for the first
+    /// reply carrying the same transaction.
+    ///
+    /// This is what requests the gateway merely acknowledges, such as
+    /// `keepalive`, use: their `ack` is all there is to wait for.
+    pub async fn request(&self, request: Request) -> Result<Response> {
+        self.request_with(request, self.options()).await
+    }
+
+    /// Sends `request` with the default options and waits for its final
+    /// reply: past the `ack` of a plugin request handled asynchronously,
+    /// up to the `event` carrying the plugin's answer.
+    pub async fn request_event(&self, request: Request) -> Result<Response> {
+        let options = RequestOptions {
+            wait_event: true,
+            ..self.options()
+        };
+        self.request_with(request, options).await
+    }
+
+    /// Sends `request` and waits for the reply carrying the same
+    /// transaction.
+    pub async fn request_with(
+        &self,
+        request: Request,
+        options: RequestOptions,
+    ) -> Result<Response> {
+        let reply = self.request_raw(request, options).await?;
+        Ok(serde_json::from_value(reply)?)
+    }
+
+    /// Like `request_with`, but returns the reply as it was received, for
+    /// the replies `Response` has no model for.
+    ///
+    /// Our credentials are filled in for us, the rest is up to `send`.
+    pub async fn request_raw(&self, request: Request, options: RequestOptions) -> Result<Value> {
+        let mut request = serde_json::to_value(request)?;
+        self.config.auth.apply(&mut request);
+
+        self.send(request, options).await
+    }
+
+    /// Sends a raw json `request` and waits for the reply carrying the
+    /// same transaction, giving up with `Error::Timeout` once every try
+    /// timed out.
+    ///
+    /// When the queue towards the connection task is full we either fail
+    /// with `Error::Busy` or, with `wait_when_busy`, wait for room as part
+    /// of the try's timeout. The same goes for requests over the
+    /// `rate_limit`, which fail with `Error::RateLimited`. Priority requests
+    /// never wait, their queue has no bound and no limit.
+    ///
+    /// The transaction string is filled in for us, and an `error` reply
+    /// comes back as `Error::Janus`.
+    ///
+    /// Everything that happens to the request is logged in a `janus_request`
+    /// span, which gets its `transaction` once the request is written.
+    pub async fn send(&self, request: Value, options: RequestOptions) -> Result<Value> {
+        let span = debug_span!(
+            "janus_request",
+            request = request["janus"].as_str().unwrap_or_default(),
+            session_id = field::Empty,
+            handle_id = field::Empty,
+            transaction = field::Empty,
+        );
+        if let Some(session_id) = request["session_id"].as_u64() {
+            span.record("session_id", session_id);
+        }
+        if let Some(handle_id) = request["handle_id"].as_u64() {
+            span.record("handle_id", handle_id);
+        }
+        self.exchange(request, options).instrument(span).await
+    }
+
+    async fn exchange(&self, request: Value, options: RequestOptions) -> Result<Value> {
+        let mut tries = 0;
+        let reply = loop {
+            let deadline = time::Instant::now() + options.timeout;
+            let (tx, rx) = oneshot::channel();
+            let command = Command {
+                request: request.clone(),
+                wait_event: options.wait_event,
+                reply: tx,
+                span: Span::current(),
+            };
+
+            if let (Some(limiter), false) = (&self.limiter, options.priority) {
+                if self.config.wait_when_busy {
+                    limiter.take_until(deadline).await?;
+                } else {
+                    limiter.try_take().map_err(|_| Error::RateLimited)?;
+                }
+            }
+
+            let mut commands = self.commands.clone();
+            if options.priority {
+                self.priority.send(command).map_err(|_| Error::Closed)?;
+            } else if self.config.wait_when_busy {
+                match time::timeout_at(deadline, commands.send(command)).await {
+                    Ok(sent) => sent.map_err(|_| Error::Closed)?,
+                    Err(_) => return Err(Error::Timeout),
+                }
+            } else {
+                commands.try_send(command).map_err(|e| match e {
+                    TrySendError::Full(_) => Error::Busy,
+                    TrySendError::Closed(_) => Error::Closed,
+                })?;
+            }
+
+            match time::timeout_at(deadline, rx).await {
+                Ok(reply) => break reply.map_err(|_| Error::Closed)?,
+                Err(_) if tries < options.retries => {
+                    tries += 1;
+                    debug!(tries, "timed out, trying again");
+                }
+                Err(_) => {
+                    debug!("timed out");
+                    return Err(Error::Timeout);
+                }
+            }
+        };
+
+        if reply["janus"] == "error" {
+            let error: ErrorInfo = serde_json::from_value(reply["error"].clone())?;
+            debug!(code = error.code, reason = %error.reason, "failed");
+            return Err(Error::Janus {
+                kind: JanusError::from_code(error.code),
+                reason: error.reason,
+            });
+        }
+        Ok(reply)
+    }
+}
+
+/// Moves commands to the gateway and replies back to whoever is waiting
+/// for them, until the connection drops.
+///
+/// Pending transactions live only as long as the connection, so callers
+/// still waiting when it drops get `Error::Closed`.
+///
+/// Once the engine is closed we send a close frame and return as soon as
+/// the gateway answered it.
+pub async fn serve<S>(
+    socket: S,
+    queues: &mut Queues,
+    transaction_ids: &TransactionIds,
+    events: &EventDispatcher,
+) -> Result<()>
+where
+    S: Stream<Item = tungstenite::Result<Message>> + Sink<Message, Error = tungstenite::Error>,
+{
+    let mut pending: HashMap<String, Waiting> = HashMap::new();
+    let (mut socket_tx, mut socket_rx) = socket.split();
+
+    loop {
+        // Priority commands go out before anything else that is queued, so a
+        // burst of plugin requests cannot hold back a keepalive.
+        while let Ok(command) = queues.priority.try_recv() {
+            send(&mut socket_tx, command, &mut pending, transaction_ids).await?;
+        }
+
+        tokio::select! {
+            command = queues.priority.recv() => match command {
+                Some(command) => send(&mut socket_tx, command, &mut pending, transaction_ids).await?,
+                None => return Ok(()),
+            },
+            command = queues.commands.recv() => match command {
+                Some(command) => send(&mut socket_tx, command, &mut pending, transaction_ids).await?,
+                None => return Ok(()),
+            },
+            _ = closed(&mut queues.closing) => {
+                // The gateway may hang up before we are done saying goodbye,
+                // which is fine at this point.
+                let _ = socket_tx.close().await;
+                let answered = async {
+                    while let Some(Ok(msg)) = socket_rx.next().await {
+                        if msg.is_close() {
+                            break;
+                        }
+                    }
+                };
+                let _ = time::timeout(CLOSE_TIMEOUT, answered).await;
+                return Ok(());
+            }
+            msg = socket_rx.next() => {
+                let text = match msg {
+                    Some(msg) => match msg? {
+                        Message::Text(text) => text,
+                        _ => continue,
+                    },
+                    None => return Ok(()),
+                };
+                let reply: Value = match serde_json::from_str(&text) {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        eprintln!("janus sent invalid json ({}): {}", e, text);
+                        continue;
+                    }
+                };
+
+                let transaction = reply["transaction"].as_str().unwrap_or_default();
+                let waiting = match pending.get(transaction) {
+                    // The event with the answer is still to come.
+                    Some(waiting) if waiting.wait_event && reply["janus"] == "ack" => continue,
+                    Some(_) => pending.remove(transaction),
+                    None => None,
+                };
+                match waiting {
+                    Some(waiting) => {
+                        let latency = waiting.sent.elapsed();
+                        debug!(parent: &waiting.span, reply = %reply["janus"], ?latency, "replied");
+                        queues.metrics.observe(&waiting.kind, latency);
+                        // The caller may have given up on the reply already.
+                        let _ = waiting.reply.send(reply);
+                    }
+                    None => events.dispatch(Event::parse(reply)),
+                }
+            }
+        }
+    }
+}
+
+/// Resolves once the engine is closed.
+async fn closed(closing: &mut watch::Receiver<bool>) {
+    while let Some(closing) = closing.recv().await {
+        if closing {
+            return;
+        }
+    }
+    // The engine is gone, and so are the queues' senders: `serve` returns
+    // on its own.
+    future::pending().await
+}
+
+/// Writes `command` to the gateway under a fresh transaction and remembers
+/// who waits for its reply.
+async fn send<S>(
+    socket_tx: &mut S,
+    command: Command,
+    pending: &mut HashMap<String, Waiting>,
+    transaction_ids: &TransactionIds,
+) -> Result<()>
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    let Command {
+        mut request,
+        wait_event,
+        reply,
+        span,
+    } = command;
+    let transaction = transaction_ids.next(pending);
+    request["transaction"] = Value::String(transaction.clone());
+    socket_tx.send(Message::text(request.to_string())).await?;
+    span.record("transaction", transaction.as_str());
+    debug!(parent: &span, "sent");
+
+    // Forget the transactions whose callers timed out.
+    pending.retain(|_, pending| !pending.reply.is_closed());
+    let waiting = Waiting {
+        kind: request["janus"].as_str().unwrap_or_default().to_string(),
+        sent: time::Instant::now(),
+        wait_event,
+        reply,
+        span,
+    };
+    pending.insert(transaction, waiting);
+    Ok(())
+}
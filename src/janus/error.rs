@@ -0,0 +1,377 @@
+use std::fmt;
+use std::io;
+
+use tokio_tungstenite::tungstenite;
+
+/// Everything that can go wrong while talking to the gateway.
+#[derive(Debug)]
+pub enum Error {
+    /// The websocket transport failed.
+    Ws(tungstenite::Error),
+    /// The TLS setup of a `wss://` connection failed.
+    Tls(native_tls::Error),
+    /// A file, such as a certificate, could not be read.
+    Io(io::Error),
+    /// The gateway did not accept the subprotocol of the API we asked for,
+    /// and answered with this one instead.
+    Subprotocol(Option<String>),
+    /// A message could not be encoded or decoded.
+    Json(serde_json::Error),
+    /// The gateway or one of its plugins answered our request with an
+    /// error.
+    Janus { kind: JanusError, reason: String },
+    /// The gateway answered with something we did not expect.
+    Unexpected(String),
+    /// The connection was closed before we got a reply.
+    Closed,
+    /// The reply did not arrive in time.
+    Timeout,
+    /// Too many requests are already queued.
+    Busy,
+    /// We are sending requests faster than the rate limit allows.
+    RateLimited,
+    /// There is no session to work with.
+    NoSession,
+    /// There is no plugin handle registered under the given key.
+    NoHandle,
+    /// The gateway does not have the plugin we need.
+    NoPlugin(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// What the gateway or plugin reported, if that is what went wrong.
+    pub fn janus_error(&self) -> Option<&JanusError> {
+        match self {
+            Error::Janus { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+/// The kinds of errors the gateway reports, by their code.
+///
+/// Codes the core uses map to a variant of their own, as do those of the
+/// VideoRoom plugin. Whatever else comes along keeps its bare code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JanusError {
+    /// 403: the request lacks a valid token or api secret.
+    Unauthorized,
+    /// 405: our token does not give access to the plugin.
+    UnauthorizedPlugin,
+    /// 450: the transport failed on its own terms.
+    TransportSpecific,
+    /// 452: the request had no `janus` field.
+    MissingRequest,
+    /// 453: the gateway does not know the request.
+    UnknownRequest,
+    /// 454: the request was not json.
+    InvalidJson,
+    /// 455: the request was json but not an object.
+    InvalidJsonObject,
+    /// 456: a mandatory field was missing.
+    MissingMandatoryElement,
+    /// 457: the request was sent to the wrong path.
+    InvalidRequestPath,
+    /// 458: the session does not exist (anymore).
+    SessionNotFound,
+    /// 459: the handle does not exist (anymore).
+    HandleNotFound,
+    /// 460: the gateway does not have the plugin.
+    PluginNotFound,
+    /// 461: the plugin refused the attach.
+    PluginAttach,
+    /// 462: the plugin could not handle the message.
+    PluginMessage,
+    /// 463: the plugin refused the detach.
+    PluginDetach,
+    /// 464: the jsep was neither an offer nor an answer.
+    JsepUnknownType,
+    /// 465: the sdp of the jsep could not be parsed.
+    JsepInvalidSdp,
+    /// 466: the candidate was for a stream that does not exist.
+    TrickleInvalidStream,
+    /// 467: a field had the wrong type.
+    InvalidElementType,
+    /// 468: the session is already in use by another transport.
+    SessionConflict,
+    /// 469: an answer came without an offer.
+    UnexpectedAnswer,
+    /// 470: the token is not known.
+    TokenNotFound,
+    /// 471: the PeerConnection is not in a state that allows the request.
+    WebrtcState,
+    /// 472: the gateway does not accept new sessions right now.
+    NotAcceptingSessions,
+    /// 490: the gateway failed without saying why.
+    Unknown,
+    /// An error of the VideoRoom plugin.
+    VideoRoom(VideoRoomError),
+    /// An error of a plugin we have no model for, or a code of the
+    /// VideoRoom plugin we do not know.
+    Plugin { plugin: String, code: i64 },
+    /// A code of the core we do not know.
+    Other(i64),
+}
+
+/// The errors of the VideoRoom plugin, by their `error_code`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoRoomError {
+    /// 499: the plugin failed without saying why.
+    Unknown,
+    /// 421: the message had no body.
+    NoMessage,
+    /// 422: the body was not a json object.
+    InvalidJson,
+    /// 423: the plugin does not know the request.
+    InvalidRequest,
+    /// 424: the handle has to join a room first.
+    JoinFirst,
+    /// 425: the handle already joined a room.
+    AlreadyJoined,
+    /// 426: the room does not exist.
+    NoSuchRoom,
+    /// 427: a room with that id already exists.
+    RoomExists,
+    /// 428: the feed does not exist.
+    NoSuchFeed,
+    /// 429: a mandatory field was missing.
+    MissingElement,
+    /// 430: a field had the wrong type or value.
+    InvalidElement,
+    /// 431: the jsep was neither an offer nor an answer.
+    InvalidSdpType,
+    /// 432: the room has as many publishers as it allows.
+    PublishersFull,
+    /// 433: the secret or pin was wrong.
+    Unauthorized,
+    /// 434: the handle already publishes.
+    AlreadyPublished,
+    /// 435: the handle does not publish.
+    NotPublished,
+    /// 436: a participant with that id already exists.
+    IdExists,
+    /// 437: the sdp could not be parsed.
+    InvalidSdp,
+}
+
+impl JanusError {
+    /// The kind of an error reported by the core.
+    pub fn from_code(code: i64) -> JanusError {
+        match code {
+            403 => JanusError::Unauthorized,
+            405 => JanusError::UnauthorizedPlugin,
+            450 => JanusError::TransportSpecific,
+            452 => JanusError::MissingRequest,
+            453 => JanusError::UnknownRequest,
+            454 => JanusError::InvalidJson,
+            455 => JanusError::InvalidJsonObject,
+            456 => JanusError::MissingMandatoryElement,
+            457 => JanusError::InvalidRequestPath,
+            458 => JanusError::SessionNotFound,
+            459 => JanusError::HandleNotFound,
+            460 => JanusError::PluginNotFound,
+            461 => JanusError::PluginAttach,
+            462 => JanusError::PluginMessage,
+            463 => JanusError::PluginDetach,
+            464 => JanusError::JsepUnknownType,
+            465 => JanusError::JsepInvalidSdp,
+            466 => JanusError::TrickleInvalidStream,
+            467 => JanusError::InvalidElementType,
+            468 => JanusError::SessionConflict,
+            469 => JanusError::UnexpectedAnswer,
+            470 => JanusError::TokenNotFound,
+            471 => JanusError::WebrtcState,
+            472 => JanusError::NotAcceptingSessions,
+            490 => JanusError::Unknown,
+            code => JanusError::Other(code),
+        }
+    }
+
+    /// The kind of an `error_code` reported by `plugin`, such as
+    /// `janus.plugin.videoroom`.
+    pub fn from_plugin(plugin: &str, code: i64) -> JanusError {
+        let known = match plugin {
+            "janus.plugin.videoroom" => VideoRoomError::from_code(code).map(JanusError::VideoRoom),
+            _ => None,
+        };
+        known.unwrap_or_else(|| JanusError::Plugin {
+            plugin: plugin.to_string(),
+            code,
+        })
+    }
+
+    /// The code the gateway used.
+    pub fn code(&self) -> i64 {
+        match self {
+            JanusError::Unauthorized => 403,
+            JanusError::UnauthorizedPlugin => 405,
+            JanusError::TransportSpecific => 450,
+            JanusError::MissingRequest => 452,
+            JanusError::UnknownRequest => 453,
+            JanusError::InvalidJson => 454,
+            JanusError::InvalidJsonObject => 455,
+            JanusError::MissingMandatoryElement => 456,
+            JanusError::InvalidRequestPath => 457,
+            JanusError::SessionNotFound => 458,
+            JanusError::HandleNotFound => 459,
+            JanusError::PluginNotFound => 460,
+            JanusError::PluginAttach => 461,
+            JanusError::PluginMessage => 462,
+            JanusError::PluginDetach => 463,
+            JanusError::JsepUnknownType => 464,
+            JanusError::JsepInvalidSdp => 465,
+            JanusError::TrickleInvalidStream => 466,
+            JanusError::InvalidElementType => 467,
+            JanusError::SessionConflict => 468,
+            JanusError::UnexpectedAnswer => 469,
+            JanusError::TokenNotFound => 470,
+            JanusError::WebrtcState => 471,
+            JanusError::NotAcceptingSessions => 472,
+            JanusError::Unknown => 490,
+            JanusError::VideoRoom(e) => e.code(),
+            JanusError::Plugin { code, .. } | JanusError::Other(code) => *code,
+        }
+    }
+}
+
+impl VideoRoomError {
+    pub fn from_code(code: i64) -> Option<VideoRoomError> {
+        Some(match code {
+            499 => VideoRoomError::Unknown,
+            421 => VideoRoomError::NoMessage,
+            422 => VideoRoomError::InvalidJson,
+            423 => VideoRoomError::InvalidRequest,
+            424 => VideoRoomError::JoinFirst,
+            425 => VideoRoomError::AlreadyJoined,
+            426 => VideoRoomError::NoSuchRoom,
+            427 => VideoRoomError::RoomExists,
+            428 => VideoRoomError::NoSuchFeed,
+            429 => VideoRoomError::MissingElement,
+            430 => VideoRoomError::InvalidElement,
+            431 => VideoRoomError::InvalidSdpType,
+            432 => VideoRoomError::PublishersFull,
+            433 => VideoRoomError::Unauthorized,
+            434 => VideoRoomError::AlreadyPublished,
+            435 => VideoRoomError::NotPublished,
+            436 => VideoRoomError::IdExists,
+            437 => VideoRoomError::InvalidSdp,
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            VideoRoomError::Unknown => 499,
+            VideoRoomError::NoMessage => 421,
+            VideoRoomError::InvalidJson => 422,
+            VideoRoomError::InvalidRequest => 423,
+            VideoRoomError::JoinFirst => 424,
+            VideoRoomError::AlreadyJoined => 425,
+            VideoRoomError::NoSuchRoom => 426,
+            VideoRoomError::RoomExists => 427,
+            VideoRoomError::NoSuchFeed => 428,
+            VideoRoomError::MissingElement => 429,
+            VideoRoomError::InvalidElement => 430,
+            VideoRoomError::InvalidSdpType => 431,
+            VideoRoomError::PublishersFull => 432,
+            VideoRoomError::Unauthorized => 433,
+            VideoRoomError::AlreadyPublished => 434,
+            VideoRoomError::NotPublished => 435,
+            VideoRoomError::IdExists => 436,
+            VideoRoomError::InvalidSdp => 437,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Ws(e) => write!(f, "websocket error: {}", e),
+            Error::Tls(e) => write!(f, "tls error: {}", e),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Subprotocol(Some(protocol)) => write!(
+                f,
+                "gateway refused our subprotocol (answered {:?})",
+                protocol
+            ),
+            Error::Subprotocol(None) => f.write_str("gateway refused our subprotocol"),
+            Error::Json(e) => write!(f, "invalid json: {}", e),
+            Error::Janus { kind, reason } => write!(f, "janus error {}: {}", kind.code(), reason),
+            Error::Unexpected(msg) => write!(f, "unexpected reply: {}", msg),
+            Error::Closed => f.write_str("connection closed"),
+            Error::Timeout => f.write_str("request timed out"),
+            Error::Busy => f.write_str("too many pending janus requests"),
+            Error::RateLimited => f.write_str("janus request rate limit reached"),
+            Error::NoSession => f.write_str("no janus session"),
+            Error::NoHandle => f.write_str("no such janus handle"),
+            Error::NoPlugin(plugin) => write!(f, "janus has no {} plugin", plugin),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::Ws(e)
+    }
+}
+
+impl From<native_tls::Error> for Error {
+    fn from(e: native_tls::Error) -> Self {
+        Error::Tls(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_code_knows_the_core_codes() {
+        assert_eq!(JanusError::from_code(403), JanusError::Unauthorized);
+        assert_eq!(JanusError::from_code(458), JanusError::SessionNotFound);
+        assert_eq!(JanusError::from_code(490), JanusError::Unknown);
+    }
+
+    #[test]
+    fn from_code_keeps_unknown_codes() {
+        assert_eq!(JanusError::from_code(999), JanusError::Other(999));
+    }
+
+    #[test]
+    fn from_code_round_trips() {
+        for code in (403..=472).chain(vec![490, 999]) {
+            assert_eq!(JanusError::from_code(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn from_plugin_knows_the_videoroom_codes() {
+        assert_eq!(
+            JanusError::from_plugin("janus.plugin.videoroom", 426),
+            JanusError::VideoRoom(VideoRoomError::NoSuchRoom)
+        );
+        assert_eq!(
+            JanusError::from_plugin("janus.plugin.echotest", 426),
+            JanusError::Plugin {
+                plugin: "janus.plugin.echotest".to_string(),
+                code: 426,
+            }
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use super::protocol::PluginData;
+
+/// Something the gateway told us on its own, rather than in reply to one
+/// of our requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "janus", rename_all = "lowercase")]
+pub enum Event {
+    /// A plugin reported something, e.g. a new publisher in a room.
+    #[serde(rename = "event")]
+    Plugin(PluginEvent),
+    /// The PeerConnection of a handle is up.
+    #[serde(rename = "webrtcup")]
+    WebrtcUp { session_id: u64, sender: u64 },
+    /// The PeerConnection of a handle was closed.
+    Hangup {
+        session_id: u64,
+        sender: u64,
+        reason: Option<String>,
+    },
+    /// A handle was detached from its plugin, by the plugin itself.
+    Detached { session_id: u64, sender: u64 },
+    /// Media of the given kind started or stopped flowing to the gateway.
+    Media {
+        session_id: u64,
+        sender: u64,
+        #[serde(rename = "type")]
+        kind: MediaKind,
+        receiving: bool,
+    },
+    /// An ICE candidate of the gateway for the PeerConnection of a handle,
+    /// or `{"completed": true}` once it has no more.
+    Trickle {
+        session_id: u64,
+        sender: u64,
+        candidate: Value,
+    },
+    /// The gateway sees too many lost packets on a PeerConnection.
+    #[serde(rename = "slowlink")]
+    SlowLink {
+        session_id: u64,
+        sender: u64,
+        /// Whether the losses are on the way to the gateway.
+        uplink: bool,
+        lost: Option<u64>,
+    },
+    /// The session timed out and is gone.
+    Timeout { session_id: u64 },
+    /// Not from the gateway but from us: `missed` keepalives in a row went
+    /// unanswered, so the connection was given up and is being opened
+    /// again. Signaling is unavailable until the client is ready again.
+    #[serde(skip_deserializing)]
+    KeepaliveMissed { session_id: u64, missed: u32 },
+    /// Anything we have no model for yet, as it was received.
+    #[serde(skip_deserializing)]
+    Other(Value),
+}
+
+/// The kind of media a `media` event is about.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Audio,
+    Video,
+    #[serde(other)]
+    Other,
+}
+
+/// An event sent by the plugin behind one of our handles.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PluginEvent {
+    pub session_id: Option<u64>,
+    /// The handle the event is for.
+    pub sender: u64,
+    pub plugindata: PluginData,
+    pub jsep: Option<Value>,
+}
+
+impl Event {
+    /// Parses a message the gateway sent on its own.
+    pub fn parse(raw: Value) -> Event {
+        Event::deserialize(&raw).unwrap_or(Event::Other(raw))
+    }
+
+    /// The handle the event is for, if it is about one.
+    pub fn sender(&self) -> Option<u64> {
+        match self {
+            Event::Plugin(PluginEvent { sender, .. })
+            | Event::WebrtcUp { sender, .. }
+            | Event::Hangup { sender, .. }
+            | Event::Detached { sender, .. }
+            | Event::Media { sender, .. }
+            | Event::Trickle { sender, .. }
+            | Event::SlowLink { sender, .. } => Some(*sender),
+            Event::Timeout { .. } | Event::KeepaliveMissed { .. } => None,
+            Event::Other(raw) => raw["sender"].as_u64(),
+        }
+    }
+}
+
+/// Receives the events of the gateway.
+///
+/// Handlers are called from the connection task, so they should hand any
+/// slow work over to a task of their own.
+pub trait JanusEventHandler: Send + Sync {
+    /// Called for every event. The default implementation logs it.
+    fn on_event(&self, event: &Event) {
+        eprintln!("janus event: {:?}", event);
+    }
+}
+
+impl<H> JanusEventHandler for Arc<H>
+where
+    H: JanusEventHandler + ?Sized,
+{
+    fn on_event(&self, event: &Event) {
+        (**self).on_event(event)
+    }
+}
+
+/// The handler used while no other is registered, which only logs.
+pub struct DefaultEventHandler;
+
+impl JanusEventHandler for DefaultEventHandler {}
+
+/// Hands every event to all the registered handlers, and to the
+/// subscribers of the handle it is for.
+#[derive(Clone)]
+pub struct EventDispatcher {
+    handlers: Arc<RwLock<Vec<Arc<dyn JanusEventHandler>>>>,
+    handles: Arc<RwLock<HashMap<u64, broadcast::Sender<Event>>>>,
+    timeouts: broadcast::Sender<u64>,
+}
+
+impl Default for EventDispatcher {
+    fn default() -> Self {
+        EventDispatcher {
+            handlers: Arc::default(),
+            handles: Arc::default(),
+            timeouts: broadcast::channel(4).0,
+        }
+    }
+}
+
+impl EventDispatcher {
+    pub fn register(&self, handler: Arc<dyn JanusEventHandler>) {
+        self.handlers.write().unwrap().push(handler);
+    }
+
+    /// Sends the events for `handle_id` to `events` as well.
+    pub fn route(&self, handle_id: u64, events: broadcast::Sender<Event>) {
+        self.handles.write().unwrap().insert(handle_id, events);
+    }
+
+    /// Stops sending the events for `handle_id` anywhere but the handlers.
+    pub fn unroute(&self, handle_id: u64) {
+        self.handles.write().unwrap().remove(&handle_id);
+    }
+
+    /// The ids of the sessions the gateway reports as timed out, from now
+    /// on.
+    pub fn timeouts(&self) -> broadcast::Receiver<u64> {
+        self.timeouts.subscribe()
+    }
+
+    pub fn dispatch(&self, event: Event) {
+        if let Event::Timeout { session_id } = event {
+            // Nobody may be subscribed right now.
+            let _ = self.timeouts.send(session_id);
+        }
+        if let Some(sender) = event.sender() {
+            if let Some(events) = self.handles.read().unwrap().get(&sender) {
+                // Nobody may be subscribed right now.
+                let _ = events.send(event.clone());
+            }
+        }
+
+        let handlers = self.handlers.read().unwrap();
+        if handlers.is_empty() {
+            DefaultEventHandler.on_event(&event);
+        }
+        for handler in handlers.iter() {
+            handler.on_event(&event);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use super::engine::Engine;
+use super::event::{Event, EventDispatcher};
+use super::protocol::{Request, Response};
+use super::session::SessionManager;
+use super::{Error, JanusError, Result};
+
+/// A plugin handle attached to our session.
+#[derive(Clone, Debug)]
+pub struct Handle {
+    pub id: u64,
+    pub plugin: String,
+    events: broadcast::Sender<Event>,
+}
+
+impl Handle {
+    /// Subscribes to the events the gateway sends for this handle only.
+    ///
+    /// The subscription belongs to the key the handle is registered under,
+    /// so it carries on when the handle is attached again on a new session.
+    /// A subscriber falling more than `event_capacity` events behind misses
+    /// the oldest ones.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+}
+
+/// The answer of a plugin to a message.
+#[derive(Clone, Debug)]
+pub struct PluginReply {
+    /// What the plugin answered.
+    pub data: Value,
+    /// The offer or answer of the gateway, when the message was part of a
+    /// negotiation.
+    pub jsep: Option<Value>,
+}
+
+/// Keeps track of every plugin handle attached to our session.
+///
+/// Handles are registered under a key naming their purpose (for example
+/// the plugin name, or `"subscriber/1234"`), so the same plugin can be
+/// attached more than once.
+#[derive(Clone)]
+pub struct HandleManager {
+    engine: Engine,
+    sessions: SessionManager,
+    events: EventDispatcher,
+    handles: Arc<Mutex<HashMap<String, Handle>>>,
+}
+
+impl HandleManager {
+    pub fn new(engine: Engine, sessions: SessionManager, events: EventDispatcher) -> HandleManager {
+        HandleManager {
+            engine,
+            sessions,
+            events,
+            handles: Arc::default(),
+        }
+    }
+
+    /// The handle registered under `key`.
+    pub fn get(&self, key: &str) -> Option<Handle> {
+        self.handles.lock().unwrap().get(key).cloned()
+    }
+
+    /// The keys of every registered handle.
+    pub fn keys(&self) -> Vec<String> {
+        self.handles.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The key the handle with the given id is registered under.
+    pub fn key_of(&self, handle_id: u64) -> Option<String> {
+        self.handles
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, handle)| handle.id == handle_id)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Attaches a new handle to `plugin` and registers it under `key`,
+    /// replacing whatever was registered there. The subscribers of the
+    /// replaced handle's events get those of the new one.
+    pub async fn attach(&self, key: &str, plugin: &str) -> Result<Handle> {
+        let session_id = self.sessions.id().ok_or(Error::NoSession)?;
+        let reply = self
+            .engine
+            .request(Request::Attach {
+                session_id,
+                plugin: plugin.to_string(),
+            })
+            .await?;
+        let id = reply
+            .id()
+            .ok_or_else(|| Error::Unexpected(format!("{:?}", reply)))?;
+
+        let events = match self.get(key) {
+            Some(replaced) => {
+                self.events.unroute(replaced.id);
+                replaced.events
+            }
+            None => broadcast::channel(self.engine.config().event_capacity).0,
+        };
+        self.events.route(id, events.clone());
+
+        let handle = Handle {
+            id,
+            plugin: plugin.to_string(),
+            events,
+        };
+        self.handles
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Attaches every registered handle again, after their session was
+    /// replaced by a new one.
+    pub async fn reattach(&self) -> Result<()> {
+        let handles: Vec<(String, String)> = self
+            .handles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, handle)| (key.clone(), handle.plugin.clone()))
+            .collect();
+
+        for (key, plugin) in handles {
+            self.attach(&key, &plugin).await?;
+        }
+        Ok(())
+    }
+
+    /// Detaches the handle registered under `key` from its plugin.
+    pub async fn detach(&self, key: &str) -> Result<()> {
+        let handle = match self.handles.lock().unwrap().remove(key) {
+            Some(handle) => handle,
+            None => return Ok(()),
+        };
+        self.events.unroute(handle.id);
+        let session_id = self.sessions.id().ok_or(Error::NoSession)?;
+        self.engine
+            .request(Request::Detach {
+                session_id,
+                handle_id: handle.id,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Hands an ICE candidate of the peer to the handle registered under
+    /// `key`.
+    pub async fn trickle(&self, key: &str, candidate: Value) -> Result<()> {
+        let handle = self.get(key).ok_or(Error::NoHandle)?;
+        let session_id = self.sessions.id().ok_or(Error::NoSession)?;
+        self.engine
+            .request(Request::Trickle {
+                session_id,
+                handle_id: handle.id,
+                candidate,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends `body` to the plugin behind the handle registered under `key`
+    /// and returns the data of the plugin's answer, whether it comes right
+    /// away or in an event after an `ack`. An `error_code` in the answer
+    /// comes back as `Error::Janus`, e.g. `JanusError::VideoRoom(NoSuchRoom)`.
+    pub async fn message(&self, key: &str, body: Value) -> Result<Value> {
+        let reply = self.message_with_jsep(key, body, None).await?;
+        Ok(reply.data)
+    }
+
+    /// Like `message`, but hands the peer's `jsep` to the plugin along with
+    /// `body`, and returns the gateway's `jsep` along with the answer.
+    ///
+    /// This is how a PeerConnection is negotiated: an offer goes out with
+    /// e.g. a `publish` and the answer comes back in the plugin's event.
+    pub async fn message_with_jsep(
+        &self,
+        key: &str,
+        body: Value,
+        jsep: Option<Value>,
+    ) -> Result<PluginReply> {
+        let plugin = self.get(key).ok_or(Error::NoHandle)?.plugin;
+        let reply = self.message_raw(key, body, jsep).await?;
+        match reply.data["error_code"].as_i64() {
+            Some(code) => Err(Error::Janus {
+                kind: JanusError::from_plugin(&plugin, code),
+                reason: reply.data["error"].as_str().unwrap_or_default().to_string(),
+            }),
+            None => Ok(reply),
+        }
+    }
+
+    /// Like `message_with_jsep`, but hands back whatever the plugin
+    /// answered as it is, without looking for an `error_code` in it.
+    ///
+    /// This is for plugins we have no bindings for, such as custom Lua,
+    /// Duktape or native ones, whose answers may not follow the
+    /// conventions of the stock plugins.
+    pub async fn message_raw(
+        &self,
+        key: &str,
+        body: Value,
+        jsep: Option<Value>,
+    ) -> Result<PluginReply> {
+        let handle = self.get(key).ok_or(Error::NoHandle)?;
+        let session_id = self.sessions.id().ok_or(Error::NoSession)?;
+        let reply = self
+            .engine
+            .request_event(Request::Message {
+                session_id,
+                handle_id: handle.id,
+                body,
+                jsep,
+            })
+            .await?;
+
+        let (plugindata, jsep) = match reply {
+            Response::Event {
+                plugindata, jsep, ..
+            } => (plugindata, jsep),
+            Response::Success { plugindata, .. } => (plugindata, None),
+            _ => (None, None),
+        };
+        match plugindata {
+            Some(plugindata) => Ok(PluginReply {
+                data: plugindata.data,
+                jsep,
+            }),
+            None => Err(Error::Unexpected("reply without plugindata".to_string())),
+        }
+    }
+}
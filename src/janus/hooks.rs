@@ -0,0 +1,53 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use super::{JanusClient, Result};
+
+/// What a reconnect left us with.
+#[derive(Clone, Copy, Debug)]
+pub struct Reconnected {
+    /// The session we have now.
+    pub session_id: u64,
+    /// Whether the session of the previous connection was taken over, along
+    /// with its handles and whatever they joined. When it was not, a new
+    /// session was created and only the handles were attached again.
+    pub claimed: bool,
+}
+
+/// A registered hook, boxed so that hooks of any type fit in one list.
+type Hook = Arc<
+    dyn Fn(JanusClient, Reconnected) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// The callbacks run after every reconnect, and after our session expired
+/// on the gateway, to restore what the gateway lost: rooms, subscriptions
+/// and so on.
+#[derive(Clone, Default)]
+pub struct ReconnectHooks {
+    hooks: Arc<RwLock<Vec<Hook>>>,
+}
+
+impl ReconnectHooks {
+    pub fn register<F, Fut>(&self, hook: F)
+    where
+        F: Fn(JanusClient, Reconnected) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let hook: Hook = Arc::new(move |client, reconnected| Box::pin(hook(client, reconnected)));
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    /// Runs every hook in the order they were registered. A failing hook is
+    /// logged and does not keep the others from running.
+    pub async fn run(&self, client: JanusClient, reconnected: Reconnected) {
+        let hooks = self.hooks.read().unwrap().clone();
+        for hook in hooks {
+            if let Err(e) = hook(client.clone(), reconnected).await {
+                eprintln!("janus reconnect hook failed: {}", e);
+            }
+        }
+    }
+}
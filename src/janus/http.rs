@@ -0,0 +1,216 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::{Sink, Stream};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method};
+use hyper_tls::HttpsConnector;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use super::tls::TlsConfig;
+use super::Result;
+
+/// How many events one long poll may bring back.
+const MAX_EVENTS: u32 = 10;
+
+/// A connection to the gateway's REST API, for gateways whose websocket
+/// transport is disabled.
+///
+/// It looks like a websocket to `serve`: every request written to it is
+/// POSTed on its own, to the path of the session or handle it is for, and
+/// the reply comes back as an incoming message. The events of our session
+/// are long-polled and come back the same way. As soon as the gateway
+/// cannot be reached the stream yields an error, like a dropped websocket.
+pub struct HttpSocket {
+    shared: Arc<Shared>,
+    incoming: mpsc::UnboundedReceiver<tungstenite::Result<Message>>,
+}
+
+struct Shared {
+    client: Client<HttpsConnector<HttpConnector>>,
+    url: String,
+    incoming: mpsc::UnboundedSender<tungstenite::Result<Message>>,
+    /// The session whose events are being long-polled.
+    session: Mutex<Option<u64>>,
+    closed: AtomicBool,
+}
+
+/// Checks that the gateway answers on `url`, e.g. `http://127.0.0.1:8088/janus`,
+/// and returns a connection to it.
+///
+/// `https://` urls are secured according to `tls`.
+pub async fn connect(url: &str, tls: &TlsConfig) -> Result<HttpSocket> {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let connector = HttpsConnector::from((http, tls.connector()?.into()));
+
+    let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+    let shared = Arc::new(Shared {
+        client: Client::builder().build(connector),
+        url: url.trim_end_matches('/').to_string(),
+        incoming: incoming_tx,
+        session: Mutex::default(),
+        closed: AtomicBool::new(false),
+    });
+    shared.call(Method::GET, "/info", Body::empty()).await?;
+
+    Ok(HttpSocket {
+        shared,
+        incoming: incoming_rx,
+    })
+}
+
+impl Shared {
+    /// Sends a request to `path` below our url and returns the json reply.
+    async fn call(&self, method: Method, path: &str, body: Body) -> tungstenite::Result<Value> {
+        let request = hyper::Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.url, path))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(transport_error)?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(transport_error)?;
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(transport_error)?;
+
+        serde_json::from_slice(&body).map_err(transport_error)
+    }
+
+    fn receive(&self, msg: tungstenite::Result<Message>) {
+        // Nobody may be listening anymore.
+        let _ = self.incoming.send(msg);
+    }
+}
+
+/// Sends a request written to the socket and hands its reply back.
+async fn post(shared: Arc<Shared>, text: String) {
+    let mut request: Value = match serde_json::from_str(&text) {
+        Ok(request) => request,
+        Err(e) => return eprintln!("janus http request is invalid json ({}): {}", e, text),
+    };
+
+    // The session and handle go in the path rather than the body.
+    let session_id = request["session_id"].as_u64();
+    let path = match (session_id, request["handle_id"].as_u64()) {
+        (Some(session_id), Some(handle_id)) => format!("/{}/{}", session_id, handle_id),
+        (Some(session_id), None) => format!("/{}", session_id),
+        _ => String::new(),
+    };
+    if let Some(request) = request.as_object_mut() {
+        request.remove("session_id");
+        request.remove("handle_id");
+    }
+
+    let reply = match shared
+        .call(Method::POST, &path, Body::from(request.to_string()))
+        .await
+    {
+        Ok(reply) => reply,
+        Err(e) => return shared.receive(Err(e)),
+    };
+
+    // Follow the events of the session we have now.
+    if reply["janus"] == "success" {
+        match request["janus"].as_str() {
+            Some("create") => follow(&shared, reply["data"]["id"].as_u64()),
+            Some("claim") => follow(&shared, session_id),
+            Some("destroy") => follow(&shared, None),
+            _ => {}
+        }
+    }
+    shared.receive(Ok(Message::text(reply.to_string())));
+}
+
+/// Makes `session_id` the session whose events are long-polled.
+fn follow(shared: &Arc<Shared>, session_id: Option<u64>) {
+    *shared.session.lock().unwrap() = session_id;
+    if let Some(session_id) = session_id {
+        tokio::task::spawn(poll(shared.clone(), session_id));
+    }
+}
+
+/// Long-polls the events of `session_id` for as long as it is the session
+/// we have.
+async fn poll(shared: Arc<Shared>, session_id: u64) {
+    let path = format!("/{}?maxev={}", session_id, MAX_EVENTS);
+    while !shared.closed.load(Ordering::Relaxed)
+        && *shared.session.lock().unwrap() == Some(session_id)
+    {
+        let events = match shared.call(Method::GET, &path, Body::empty()).await {
+            Ok(Value::Array(events)) => events,
+            Ok(event) => vec![event],
+            Err(e) => return shared.receive(Err(e)),
+        };
+        for event in events {
+            // The gateway answers with a keepalive when nothing happened.
+            if event["janus"] == "keepalive" {
+                continue;
+            }
+            let gone = event["janus"] == "error";
+            shared.receive(Ok(Message::text(event.to_string())));
+            if gone {
+                return;
+            }
+        }
+    }
+}
+
+fn transport_error<E>(e: E) -> tungstenite::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    tungstenite::Error::Io(io::Error::other(e))
+}
+
+impl Stream for HttpSocket {
+    type Item = tungstenite::Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.shared.closed.load(Ordering::Relaxed) {
+            return Poll::Ready(None);
+        }
+        self.incoming.poll_recv(cx)
+    }
+}
+
+impl Sink<Message> for HttpSocket {
+    type Error = tungstenite::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<tungstenite::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> tungstenite::Result<()> {
+        if let Message::Text(text) = msg {
+            tokio::task::spawn(post(self.shared.clone(), text));
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<tungstenite::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<tungstenite::Result<()>> {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for HttpSocket {
+    fn drop(&mut self) {
+        // Stops the long poll.
+        self.shared.closed.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// How long the gateway took to reply, for every kind of request.
+#[derive(Default)]
+pub struct Metrics {
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+}
+
+/// A latency histogram, with the same buckets as Prometheus'.
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    /// How many replies took at most the bucket's bound, per bucket, plus
+    /// the ones that took longer than any bound.
+    pub counts: [u64; BUCKETS.len() + 1],
+    /// The latencies added up, in seconds.
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Metrics {
+    /// Records that a `request`, such as `attach`, took `latency` from
+    /// being written to the gateway until its reply came.
+    pub fn observe(&self, request: &str, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        let histogram = match latencies.get_mut(request) {
+            Some(histogram) => histogram,
+            None => latencies.entry(request.to_string()).or_default(),
+        };
+        histogram.observe(latency.as_secs_f64());
+    }
+
+    /// The histograms so far, by request.
+    pub fn latencies(&self) -> BTreeMap<String, Histogram> {
+        self.latencies.lock().unwrap().clone()
+    }
+
+    /// The histograms in the Prometheus text format, for a `/metrics`
+    /// endpoint.
+    pub fn render(&self) -> String {
+        let name = "janus_request_duration_seconds";
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP {} Time until the gateway replied.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (request, histogram) in self.latencies() {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{request=\"{}\",le=\"{}\"}} {}",
+                    name, request, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{request=\"{}\",le=\"+Inf\"}} {}",
+                name, request, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{request=\"{}\"}} {}",
+                name, request, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{request=\"{}\"}} {}",
+                name, request, histogram.count
+            );
+        }
+        out
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
@@ -0,0 +1,54 @@
+//! Client for the Janus gateway websocket API.
+//!
+//! A background task keeps one connection to the gateway alive, taking our
+//! session over (or creating a new one) every time the connection has to
+//! be established again. The rest of the program talks to it through
+//! [`JanusClient`], which pairs every request with its reply.
+//!
+//! [`AdminClient`] does the same for the gateway's Admin API, and
+//! [`videoroom`] has the requests of the plugin the chat uses, while
+//! [`textroom`], [`streaming`], [`sip`], [`recordplay`] and [`videocall`]
+//! have those of the plugins for chatting over data channels, broadcasts,
+//! phone calls, recordings and calls between browsers. [`nosip`] bridges
+//! browsers to RTP endpoints and [`echotest`] checks the signaling path to
+//! the gateway.
+
+mod admin;
+mod auth;
+pub mod client;
+pub mod echotest;
+mod engine;
+mod error;
+mod event;
+mod handle;
+mod hooks;
+mod http;
+mod metrics;
+pub mod nosip;
+mod pool;
+pub mod protocol;
+mod ratelimit;
+pub mod recordplay;
+mod session;
+pub mod sip;
+pub mod streaming;
+pub mod textroom;
+mod tls;
+mod transaction;
+pub mod videocall;
+pub mod videoroom;
+
+pub use admin::{AdminClient, AdminConfig};
+pub use auth::JanusAuth;
+pub use client::{Config, JanusClient, JanusClientBuilder};
+pub use engine::RequestOptions;
+pub use error::{Error, JanusError, Result, VideoRoomError};
+pub use event::{DefaultEventHandler, Event, JanusEventHandler, MediaKind, PluginEvent};
+pub use handle::{Handle, HandleManager, PluginReply};
+pub use hooks::Reconnected;
+pub use metrics::{Histogram, Metrics};
+pub use pool::JanusPool;
+pub use ratelimit::RateLimit;
+pub use session::SessionManager;
+pub use tls::{ClientCert, TlsConfig};
+pub use transaction::TransactionIds;
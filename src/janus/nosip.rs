@@ -0,0 +1,80 @@
+//! Requests of the NoSIP plugin, `janus.plugin.nosip`, which bridges the
+//! PeerConnection of a handle to plain RTP endpoints.
+//!
+//! The plugin does no signaling of its own: it turns the browser's offer or
+//! answer into a barebones SDP for the RTP endpoint with `generate`, and the
+//! endpoint's SDP into one for the browser with `process`. Getting those to
+//! and from the endpoint is up to whatever signaling it uses.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.nosip";
+
+/// A session description on the RTP side of the plugin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sdp {
+    /// `offer` or `answer`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub sdp: String,
+}
+
+/// Turns the browser's `jsep` into the SDP to hand to the RTP endpoint, on
+/// the handle registered under `key`, attaching one to the plugin first if
+/// there is none.
+///
+/// `srtp` is `sdes_optional` or `sdes_mandatory` to offer SRTP to the
+/// endpoint, otherwise the media goes as plain RTP.
+pub async fn generate(
+    janus: &JanusClient,
+    key: &str,
+    jsep: Value,
+    srtp: Option<&str>,
+) -> Result<Sdp> {
+    // The plugin replies with:
+    // {"nosip": "event", "result": {"event": "generated", "type": "offer", "sdp": "v=0..."}}
+    let mut body = json!({ "request": "generate" });
+    if let Some(srtp) = srtp {
+        body["srtp"] = Value::from(srtp);
+    }
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, Some(jsep)).await?;
+    result(&reply.data, "generated")?;
+    Ok(serde_json::from_value(reply.data["result"].clone())?)
+}
+
+/// Hands the SDP of the RTP endpoint to the handle registered under `key`,
+/// attaching one to the plugin first if there is none, and returns the
+/// gateway's offer or answer for the browser along with it.
+pub async fn process(janus: &JanusClient, key: &str, sdp: &Sdp) -> Result<Value> {
+    // The plugin replies with {"nosip": "event", "result": {"event": "processed"}}
+    let body = json!({ "request": "process", "type": sdp.kind, "sdp": sdp.sdp });
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    result(&reply.data, "processed")?;
+    match reply.jsep {
+        Some(jsep) => Ok(jsep),
+        None => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Tears down the media of the handle registered under `key`.
+pub async fn hangup(janus: &JanusClient, key: &str) -> Result<()> {
+    let reply = janus
+        .message_with_jsep(key, json!({ "request": "hangup" }), None)
+        .await?;
+    result(&reply.data, "hangingup")
+}
+
+/// Checks that the plugin answered with the `expected` result, as in
+/// {"nosip": "event", "result": {"event": "generated"}}.
+fn result(data: &Value, expected: &str) -> Result<()> {
+    if data["result"]["event"] == expected {
+        Ok(())
+    } else {
+        Err(Error::Unexpected(data.to_string()))
+    }
+}
@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::event::JanusEventHandler;
+use super::hooks::Reconnected;
+use super::{Config, JanusClient, Result};
+
+/// Several connections to the gateway, each with a session of its own,
+/// sharing the load of our commands.
+///
+/// Commands are handed out round-robin, so a slow reply on one connection
+/// does not hold up the commands queued behind it on the others. Anything
+/// tied to a handle (joining, publishing, ...) has to stay on the client
+/// that owns the handle, use `client()` once and keep that one.
+#[derive(Clone)]
+pub struct JanusPool {
+    clients: Arc<Vec<JanusClient>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl JanusPool {
+    /// Starts `size` connections with the same `config`.
+    pub fn spawn(config: Config, size: usize) -> JanusPool {
+        let clients = (0..size.max(1))
+            .map(|_| JanusClient::spawn(config.clone()))
+            .collect();
+
+        JanusPool {
+            clients: Arc::new(clients),
+            next: Arc::default(),
+        }
+    }
+
+    /// All the clients of the pool.
+    pub fn clients(&self) -> &[JanusClient] {
+        &self.clients
+    }
+
+    /// The client whose turn it is.
+    pub fn client(&self) -> &JanusClient {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.clients[next % self.clients.len()]
+    }
+
+    /// Registers `handler` on every connection of the pool.
+    pub fn register_handler<H>(&self, handler: H)
+    where
+        H: JanusEventHandler + 'static,
+    {
+        let handler = Arc::new(handler);
+        for client in self.clients.iter() {
+            client.register_handler(handler.clone());
+        }
+    }
+
+    /// Adds `hook` to the reconnect hooks of every connection of the pool,
+    /// see `JanusClient::on_reconnect`.
+    pub fn on_reconnect<F, Fut>(&self, hook: F)
+    where
+        F: Fn(JanusClient, Reconnected) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let hook = Arc::new(hook);
+        for client in self.clients.iter() {
+            let hook = hook.clone();
+            client.on_reconnect(move |client, reconnected| hook(client, reconnected));
+        }
+    }
+
+    /// Sends `body` to the main plugin handle of the next client.
+    pub async fn message(&self, body: Value) -> Result<Value> {
+        self.client().message(body).await
+    }
+
+    /// Shuts every connection of the pool down, see
+    /// `JanusClient::shutdown`.
+    pub async fn shutdown(&self) {
+        for client in self.clients.iter() {
+            client.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Messages of the Janus core protocol.
+//!
+//! Only the core envelope is typed here, plugin bodies and their replies
+//! stay plain json values since every plugin defines its own.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A request we send to the gateway.
+///
+/// The `transaction` and `apisecret` fields are added by the engine.
+#[derive(Debug, Serialize)]
+#[serde(tag = "janus", rename_all = "lowercase")]
+pub enum Request {
+    Info,
+    Create,
+    Claim {
+        session_id: u64,
+    },
+    Destroy {
+        session_id: u64,
+    },
+    Keepalive {
+        session_id: u64,
+    },
+    Attach {
+        session_id: u64,
+        plugin: String,
+    },
+    Detach {
+        session_id: u64,
+        handle_id: u64,
+    },
+    Message {
+        session_id: u64,
+        handle_id: u64,
+        body: Value,
+        /// The offer or answer of the peer, when the message starts or
+        /// completes a negotiation.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        jsep: Option<Value>,
+    },
+    /// An ICE candidate of the peer behind a handle, or `{"completed": true}`
+    /// once it has no more.
+    Trickle {
+        session_id: u64,
+        handle_id: u64,
+        candidate: Value,
+    },
+}
+
+/// A request we send to the gateway's Admin API.
+///
+/// The `transaction` and `admin_secret` fields are added by the admin
+/// client.
+#[derive(Debug, Serialize)]
+#[serde(tag = "janus", rename_all = "snake_case")]
+pub enum AdminRequest {
+    ListSessions,
+    ListHandles { session_id: u64 },
+    HandleInfo { session_id: u64, handle_id: u64 },
+    SetLogLevel { level: u8 },
+}
+
+/// A message we receive from the gateway, either a reply to one of our
+/// requests or an event.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "janus", rename_all = "lowercase")]
+pub enum Response {
+    Success {
+        session_id: Option<u64>,
+        sender: Option<u64>,
+        data: Option<Data>,
+        plugindata: Option<PluginData>,
+    },
+    Error {
+        session_id: Option<u64>,
+        error: ErrorInfo,
+    },
+    Ack {
+        session_id: Option<u64>,
+    },
+    Event {
+        session_id: Option<u64>,
+        sender: u64,
+        plugindata: Option<PluginData>,
+        jsep: Option<Value>,
+    },
+    /// Anything we have no model for yet.
+    #[serde(other)]
+    Other,
+}
+
+/// What the gateway tells about itself in reply to `info`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: u64,
+    pub version_string: String,
+    /// The transports enabled on the gateway, by package name, such as
+    /// `janus.transport.websockets`.
+    pub transports: BTreeMap<String, Value>,
+    /// The plugins available on the gateway, by package name, such as
+    /// `janus.plugin.videoroom`.
+    pub plugins: BTreeMap<String, Value>,
+}
+
+/// The `data` of a successful `create` or `attach`.
+#[derive(Debug, Deserialize)]
+pub struct Data {
+    pub id: u64,
+}
+
+/// The reply of a plugin, wrapped in a core message.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PluginData {
+    pub plugin: String,
+    pub data: Value,
+}
+
+/// The `error` of a failed request.
+#[derive(Debug, Deserialize)]
+pub struct ErrorInfo {
+    pub code: i64,
+    pub reason: String,
+}
+
+impl Response {
+    /// The id a successful `create` or `attach` returned.
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            Response::Success {
+                data: Some(data), ..
+            } => Some(data.id),
+            _ => None,
+        }
+    }
+
+    /// The plugin data carried by a reply or event.
+    pub fn plugindata(self) -> Option<PluginData> {
+        match self {
+            Response::Success { plugindata, .. } | Response::Event { plugindata, .. } => plugindata,
+            _ => None,
+        }
+    }
+}
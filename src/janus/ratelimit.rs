@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::{self, Instant};
+
+use super::{Error, Result};
+
+/// How many requests per second we allow ourselves towards the gateway.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// Requests per second in the long run.
+    pub per_second: u32,
+    /// Requests that may go out at once after a quiet period.
+    pub burst: u32,
+}
+
+/// A token bucket enforcing a `RateLimit`: every request takes a token,
+/// and tokens come back at `per_second`, up to `burst` of them.
+pub struct TokenBucket {
+    limit: RateLimit,
+    state: Mutex<State>,
+}
+
+struct State {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> TokenBucket {
+        TokenBucket {
+            limit,
+            state: Mutex::new(State {
+                tokens: f64::from(limit.burst),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token if there is one, or tells how long until there is.
+    pub fn try_take(&self) -> std::result::Result<(), Duration> {
+        let rate = f64::from(self.limit.per_second.max(1));
+        let mut state = self.state.lock().unwrap();
+
+        let now = Instant::now();
+        let refilled = now.duration_since(state.updated).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refilled).min(f64::from(self.limit.burst.max(1)));
+        state.updated = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - state.tokens) / rate))
+        }
+    }
+
+    /// Takes a token, waiting for one until `deadline` at most.
+    pub async fn take_until(&self, deadline: Instant) -> Result<()> {
+        loop {
+            match self.try_take() {
+                Ok(()) => return Ok(()),
+                Err(wait) if Instant::now() + wait <= deadline => time::delay_for(wait).await,
+                Err(_) => return Err(Error::Timeout),
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Requests and events of the Record&Play plugin, `janus.plugin.recordplay`,
+//! which records what a browser sends and plays recordings back to it.
+//!
+//! The recordings are listed on our own handle, while every browser
+//! records or watches on a handle of its own.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, Event, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.recordplay";
+
+/// The key our own handle to the plugin is registered under.
+pub const CONTROLLER: &str = "recordplay";
+
+/// A recording as `list_recordings` tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Recording {
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+    /// When it was recorded, e.g. `2020-05-04 14:02:11`.
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub audio: bool,
+    #[serde(default)]
+    pub video: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+}
+
+/// The recordings the plugin can play back.
+pub async fn list_recordings(janus: &JanusClient) -> Result<Vec<Recording>> {
+    // The plugin replies with {"recordplay": "list", "list": [{"id": 1, "name": "...", ...}, ...]}
+    let data = control(janus, json!({ "request": "list" })).await?;
+    Ok(serde_json::from_value(data["list"].clone())?)
+}
+
+/// Has the plugin look for recordings in its folder again, e.g. after some
+/// were copied there.
+pub async fn update(janus: &JanusClient) -> Result<()> {
+    // The plugin replies with {"recordplay": "ok"}
+    control(janus, json!({ "request": "update" })).await?;
+    Ok(())
+}
+
+/// Records the browser's `offer` under `name` on the handle registered
+/// under `key`, attaching one to the plugin first if there is none, and
+/// returns the id of the recording along with the gateway's answer.
+pub async fn record(
+    janus: &JanusClient,
+    key: &str,
+    name: &str,
+    offer: Value,
+) -> Result<(u64, Value)> {
+    // The plugin replies with:
+    // {"recordplay": "event", "result": {"status": "recording", "id": 1}}
+    // along with its answer.
+    let body = json!({ "request": "record", "name": name });
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, Some(offer)).await?;
+    status(&reply.data, "recording")?;
+    match (reply.data["result"]["id"].as_u64(), reply.jsep) {
+        (Some(id), Some(answer)) => Ok((id, answer)),
+        _ => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Prepares the playback of recording `id` on the handle registered under
+/// `key`, attaching one to the plugin first if there is none, and returns
+/// the gateway's offer of it.
+///
+/// The browser's answer goes back with `start`.
+pub async fn play(janus: &JanusClient, key: &str, id: u64) -> Result<Value> {
+    // The plugin replies with:
+    // {"recordplay": "event", "result": {"status": "preparing", "id": 1}}
+    // along with its offer.
+    let body = json!({ "request": "play", "id": id });
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    status(&reply.data, "preparing")?;
+    match reply.jsep {
+        Some(offer) => Ok(offer),
+        None => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Hands the browser's `answer` to the offer of `play` to the handle
+/// registered under `key`, which starts the playback.
+///
+/// The plugin tells when the recording is over with `PlaybackDone`.
+pub async fn start(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    let body = json!({ "request": "start" });
+    let reply = janus.message_with_jsep(key, body, Some(answer)).await?;
+    status(&reply.data, "playing")
+}
+
+/// Stops the recording or the playback of the handle registered under
+/// `key`.
+pub async fn stop(janus: &JanusClient, key: &str) -> Result<()> {
+    let body = json!({ "request": "stop" });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    status(&reply.data, "stopped")
+}
+
+/// A playback that reached the end of its recording.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlaybackDone {
+    /// The recording, when the plugin tells.
+    pub id: Option<u64>,
+}
+
+impl PlaybackDone {
+    /// Reads a `done` event of the plugin, such as
+    /// {"recordplay": "event", "result": {"status": "done", "id": 1}}
+    pub fn parse(event: &Event) -> Option<PlaybackDone> {
+        let data = match event {
+            Event::Plugin(event) if event.plugindata.plugin == PLUGIN => &event.plugindata.data,
+            _ => return None,
+        };
+        if data["result"]["status"] != "done" {
+            return None;
+        }
+        Some(PlaybackDone {
+            id: data["result"]["id"].as_u64(),
+        })
+    }
+}
+
+/// Checks that the plugin answered with `expected`, as in
+/// {"recordplay": "event", "result": {"status": "playing"}}.
+fn status(data: &Value, expected: &str) -> Result<()> {
+    if data["result"]["status"] == expected {
+        Ok(())
+    } else {
+        Err(Error::Unexpected(data.to_string()))
+    }
+}
+
+/// Sends `body` to the plugin on our own handle, attaching it first if it
+/// is not yet.
+async fn control(janus: &JanusClient, body: Value) -> Result<Value> {
+    janus.plugin_handle(CONTROLLER, PLUGIN).await?;
+    let reply = janus.message_with_jsep(CONTROLLER, body, None).await?;
+    Ok(reply.data)
+}
@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::time;
+
+use super::engine::{Engine, RequestOptions};
+use super::protocol::Request;
+use super::{Error, JanusError, Result};
+
+/// Owns one Janus session: creates it, keeps it alive and destroys it.
+///
+/// The session id outlives the connection it was created on, so after a
+/// reconnect the same session can be taken over again with `claim`.
+#[derive(Clone)]
+pub struct SessionManager {
+    engine: Engine,
+    id: Arc<Mutex<Option<u64>>>,
+}
+
+/// Why `keepalive` stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeepaliveEnd {
+    /// There is no session anymore.
+    Destroyed,
+    /// The keepalives for the session went unanswered.
+    Unanswered(u64),
+    /// The gateway no longer knows the session.
+    Expired(u64),
+}
+
+impl SessionManager {
+    pub fn new(engine: Engine) -> SessionManager {
+        SessionManager {
+            engine,
+            id: Arc::default(),
+        }
+    }
+
+    /// The id of the current session, if there is one.
+    pub fn id(&self) -> Option<u64> {
+        *self.id.lock().unwrap()
+    }
+
+    /// Creates a new session, replacing the one we had.
+    pub async fn create(&self) -> Result<u64> {
+        let reply = self.engine.request(Request::Create).await?;
+        let id = reply
+            .id()
+            .ok_or_else(|| Error::Unexpected(format!("{:?}", reply)))?;
+
+        *self.id.lock().unwrap() = Some(id);
+        Ok(id)
+    }
+
+    /// Takes over our session on the current connection, after the one it
+    /// was created on dropped.
+    pub async fn claim(&self) -> Result<u64> {
+        let id = self.id().ok_or(Error::NoSession)?;
+        let options = RequestOptions {
+            priority: true,
+            ..self.engine.options()
+        };
+        self.engine
+            .request_with(Request::Claim { session_id: id }, options)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Drops our session without telling the gateway, e.g. because it lives
+    /// on a server we no longer talk to.
+    pub fn forget(&self) {
+        self.id.lock().unwrap().take();
+    }
+
+    /// Destroys the session on the gateway, along with all its handles.
+    pub async fn destroy(&self) -> Result<()> {
+        let id = match self.id.lock().unwrap().take() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        self.engine
+            .request(Request::Destroy { session_id: id })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends a keepalive every `keepalive_interval` until the session is
+    /// destroyed, until the gateway no longer knows it, or until
+    /// `keepalive_misses` keepalives in a row went unanswered, at which
+    /// point the connection is no good anymore.
+    ///
+    /// After a miss the next keepalive goes out right away, so a dead
+    /// connection is noticed within a few request timeouts.
+    pub async fn keepalive(&self) -> KeepaliveEnd {
+        let config = self.engine.config();
+        let period = config.keepalive_interval;
+        let options = RequestOptions {
+            priority: true,
+            ..self.engine.options()
+        };
+        let mut interval = time::interval_at(time::Instant::now() + period, period);
+        let mut missed = 0;
+
+        loop {
+            if missed == 0 {
+                interval.tick().await;
+            }
+            let id = match self.id() {
+                Some(id) => id,
+                None => return KeepaliveEnd::Destroyed,
+            };
+
+            let request = Request::Keepalive { session_id: id };
+            match self.engine.request_with(request, options).await {
+                Ok(_) => missed = 0,
+                Err(Error::Timeout) => {
+                    missed += 1;
+                    eprintln!(
+                        "janus keepalive for session {} got no ack ({} in a row)",
+                        id, missed
+                    );
+                    if missed >= config.keepalive_misses {
+                        return KeepaliveEnd::Unanswered(id);
+                    }
+                }
+                Err(Error::Janus {
+                    kind: JanusError::SessionNotFound,
+                    ..
+                }) => return KeepaliveEnd::Expired(id),
+                Err(e) => eprintln!("janus keepalive failed: {}", e),
+            }
+        }
+    }
+}
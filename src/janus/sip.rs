@@ -0,0 +1,190 @@
+//! Requests and events of the SIP plugin, `janus.plugin.sip`, which turns
+//! the PeerConnection of a handle into a SIP user agent.
+//!
+//! A handle registers with a SIP server first, then calls or gets called.
+//! What the plugin answers right away only says it is on it: how the
+//! registration or the call turns out comes later, as the events parsed by
+//! `SipEvent::parse`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, Event, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.sip";
+
+/// How to register with a SIP server, see `register`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Register {
+    /// Our SIP URI, e.g. `sip:alice@example.com`.
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// The user to authenticate as, when it is not the one of `username`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authuser: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The SIP server to register with, e.g. `sip:example.com:5060`,
+    /// otherwise the one of `username`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// `guest` to call without registering at all.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+/// Registers the handle registered under `key` with a SIP server,
+/// attaching one to the plugin first if there is none.
+///
+/// The outcome comes later as `SipEvent::Registered` or
+/// `SipEvent::RegistrationFailed`.
+pub async fn register(janus: &JanusClient, key: &str, register: &Register) -> Result<()> {
+    // The plugin replies with {"sip": "event", "result": {"event": "registering"}}
+    // or, for guests, right away with "registered".
+    let mut body = serde_json::to_value(register)?;
+    body["request"] = Value::from("register");
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    result(&reply.data, &["registering", "registered"])
+}
+
+/// Calls `uri` with the browser's `offer` from the handle registered under
+/// `key`.
+///
+/// The call rings with `SipEvent::Ringing` and is answered with
+/// `SipEvent::Accepted`, carrying the answer for the browser, or ends with
+/// `SipEvent::Hangup`.
+pub async fn call(janus: &JanusClient, key: &str, uri: &str, offer: Value) -> Result<()> {
+    // The plugin replies with {"sip": "event", "result": {"event": "calling", "call_id": "..."}}
+    let body = json!({ "request": "call", "uri": uri });
+    let reply = janus.message_with_jsep(key, body, Some(offer)).await?;
+    result(&reply.data, &["calling"])
+}
+
+/// Accepts the incoming call of the handle registered under `key`, with the
+/// browser's `answer` to the offer of `SipEvent::IncomingCall`.
+pub async fn accept(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    let body = json!({ "request": "accept" });
+    let reply = janus.message_with_jsep(key, body, Some(answer)).await?;
+    result(&reply.data, &["accepting", "accepted"])
+}
+
+/// Declines the incoming call of the handle registered under `key`.
+pub async fn decline(janus: &JanusClient, key: &str) -> Result<()> {
+    let reply = janus
+        .message_with_jsep(key, json!({ "request": "decline" }), None)
+        .await?;
+    result(&reply.data, &["declining"])
+}
+
+/// Hangs up the call of the handle registered under `key`. The call is
+/// over with `SipEvent::Hangup`.
+pub async fn hangup(janus: &JanusClient, key: &str) -> Result<()> {
+    let reply = janus
+        .message_with_jsep(key, json!({ "request": "hangup" }), None)
+        .await?;
+    result(&reply.data, &["hangingup"])
+}
+
+/// Sends DTMF `digits` in the call of the handle registered under `key`,
+/// with SIP INFO.
+pub async fn dtmf(janus: &JanusClient, key: &str, digits: &str) -> Result<()> {
+    for digit in digits.chars() {
+        let body = json!({ "request": "dtmf_info", "digit": digit.to_string() });
+        janus.message_with_jsep(key, body, None).await?;
+    }
+    Ok(())
+}
+
+/// Checks that the plugin answered with one of the `expected` results, as
+/// in {"sip": "event", "result": {"event": "calling"}}.
+fn result(data: &Value, expected: &[&str]) -> Result<()> {
+    match data["result"]["event"].as_str() {
+        Some(event) if expected.contains(&event) => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// What the plugin tells about the registration and calls of a handle.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum SipEvent {
+    Registered {
+        username: String,
+    },
+    #[serde(rename = "registration_failed")]
+    RegistrationFailed {
+        code: u64,
+        reason: String,
+    },
+    /// Someone calls, with their offer.
+    #[serde(rename = "incomingcall")]
+    IncomingCall {
+        username: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        display_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        call_id: Option<String>,
+        jsep: Option<Value>,
+    },
+    Ringing,
+    /// The callee sent early media, with the answer to our offer.
+    Progress {
+        jsep: Option<Value>,
+    },
+    /// The call is up, with the answer to our offer when we called.
+    Accepted {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        jsep: Option<Value>,
+    },
+    Hangup {
+        code: u64,
+        reason: String,
+    },
+}
+
+impl SipEvent {
+    /// Reads an event of the plugin such as
+    /// {"sip": "event", "result": {"event": "incomingcall", "username": "sip:bob@example.com"}}
+    /// along with the offer of the caller.
+    pub fn parse(event: &Event) -> Option<SipEvent> {
+        let (data, jsep) = match event {
+            Event::Plugin(event) if event.plugindata.plugin == PLUGIN => {
+                (&event.plugindata.data, event.jsep.clone())
+            }
+            _ => return None,
+        };
+        let result = &data["result"];
+        let text = |field: &str| result[field].as_str().map(String::from);
+        let sip_event = match result["event"].as_str()? {
+            "registered" => SipEvent::Registered {
+                username: text("username")?,
+            },
+            "registration_failed" => SipEvent::RegistrationFailed {
+                code: result["code"].as_u64().unwrap_or_default(),
+                reason: text("reason").unwrap_or_default(),
+            },
+            "incomingcall" => SipEvent::IncomingCall {
+                username: text("username")?,
+                display_name: text("displayname"),
+                call_id: text("call_id"),
+                jsep,
+            },
+            "ringing" => SipEvent::Ringing,
+            "progress" => SipEvent::Progress { jsep },
+            "accepted" => SipEvent::Accepted {
+                username: text("username"),
+                jsep,
+            },
+            "hangup" => SipEvent::Hangup {
+                code: result["code"].as_u64().unwrap_or_default(),
+                reason: text("reason").unwrap_or_default(),
+            },
+            _ => return None,
+        };
+        Some(sip_event)
+    }
+}
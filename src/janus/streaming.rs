@@ -0,0 +1,235 @@
+//! Requests of the Streaming plugin, `janus.plugin.streaming`, whose
+//! mountpoints relay one source, such as an RTP feed or an RTSP camera, to
+//! any number of viewers.
+//!
+//! Mountpoints are managed on our own handle, while every viewer watches on
+//! a handle of their own.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.streaming";
+
+/// The key our own handle to the plugin is registered under.
+pub const CONTROLLER: &str = "streaming";
+
+/// A stream of a mountpoint, see `CreateMountpoint`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MountpointMedia {
+    /// `audio`, `video` or `data`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub mid: String,
+    /// The port the plugin listens on for the RTP of the stream, picked by
+    /// the plugin when 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pt: Option<u8>,
+    /// The codec of the stream as in an SDP rtpmap, e.g. `opus/48000/2`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fmtp: Option<String>,
+}
+
+/// The settings of a new mountpoint, see `create_mountpoint`. Whatever is
+/// left out gets the plugin's default.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CreateMountpoint {
+    /// `rtp`, `live`, `ondemand` or `rtsp`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The id of the mountpoint, picked by the plugin when left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// What editing or destroying the mountpoint takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// What watching the mountpoint takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// Whether the mountpoint is left out of `list_mountpoints`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_private: Option<bool>,
+    /// The streams of an `rtp` mountpoint.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub media: Vec<MountpointMedia>,
+    /// The file of a `live` or `ondemand` mountpoint, or the URL of an
+    /// `rtsp` one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Whether the plugin saves the mountpoint to its config file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent: Option<bool>,
+    /// The `admin_key` of the plugin, if creating mountpoints requires it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+}
+
+/// Creates a mountpoint and returns its id.
+pub async fn create_mountpoint(janus: &JanusClient, mountpoint: &CreateMountpoint) -> Result<u64> {
+    // The plugin replies with:
+    // {"streaming": "created", "created": "name", "stream": {"id": 99, "type": "live", ...}}
+    let mut body = serde_json::to_value(mountpoint)?;
+    body["request"] = Value::from("create");
+    let data = control(janus, body).await?;
+
+    data["stream"]["id"]
+        .as_u64()
+        .ok_or_else(|| Error::Unexpected(data.to_string()))
+}
+
+/// The changes to an existing mountpoint, see `edit_mountpoint`. Whatever
+/// is left out stays as it is.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EditMountpoint {
+    pub id: u64,
+    /// The current secret of the mountpoint, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_pin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_is_private: Option<bool>,
+    /// Whether the changes go to the plugin's config file as well.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent: Option<bool>,
+}
+
+/// Changes the settings of a mountpoint.
+pub async fn edit_mountpoint(janus: &JanusClient, edit: &EditMountpoint) -> Result<()> {
+    // The plugin replies with {"streaming": "edited", "id": 99}
+    let mut body = serde_json::to_value(edit)?;
+    body["request"] = Value::from("edit");
+    let data = control(janus, body).await?;
+
+    match data["streaming"].as_str() {
+        Some("edited") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Destroys mountpoint `id`, whose `secret` it takes if it has one.
+///
+/// Its viewers are told by the plugin that the stream stopped.
+pub async fn destroy_mountpoint(
+    janus: &JanusClient,
+    id: u64,
+    secret: Option<&str>,
+    permanent: bool,
+) -> Result<()> {
+    // The plugin replies with {"streaming": "destroyed", "id": 99} or an
+    // error such as {"streaming": "event", "error_code": 455, "error": "No such mountpoint/stream 99"}
+    let mut body = json!({
+        "request": "destroy",
+        "id": id,
+        "permanent": permanent,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = control(janus, body).await?;
+
+    match data["streaming"].as_str() {
+        Some("destroyed") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// A mountpoint as `list_mountpoints` tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MountpointInfo {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub description: String,
+    /// Whether the mountpoint can be watched, when the plugin tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+/// The public mountpoints of the plugin.
+pub async fn list_mountpoints(janus: &JanusClient) -> Result<Vec<MountpointInfo>> {
+    // The plugin replies with {"streaming": "list", "list": [{"id": 1, "type": "rtp", ...}, ...]}
+    let data = control(janus, json!({ "request": "list" })).await?;
+    Ok(serde_json::from_value(data["list"].clone())?)
+}
+
+/// Starts watching mountpoint `id`, with its `pin` if it has one, on the
+/// handle registered under `key`, attaching one to the plugin first if
+/// there is none, and returns the gateway's offer of the stream.
+///
+/// The browser's answer goes back with `start`.
+pub async fn watch(janus: &JanusClient, key: &str, id: u64, pin: Option<&str>) -> Result<Value> {
+    // The plugin replies with:
+    // {"streaming": "event", "result": {"status": "preparing"}}
+    // along with its offer.
+    let mut body = json!({ "request": "watch", "id": id });
+    if let Some(pin) = pin {
+        body["pin"] = Value::from(pin);
+    }
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    match reply.jsep {
+        Some(offer) => Ok(offer),
+        None => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Hands the browser's `answer` to the offer of `watch` to the handle
+/// registered under `key`, which starts the stream.
+pub async fn start(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    let body = json!({ "request": "start" });
+    let reply = janus.message_with_jsep(key, body, Some(answer)).await?;
+    status(reply.data, "starting")
+}
+
+/// Pauses the stream of the handle registered under `key`, until `start`
+/// is sent again.
+pub async fn pause(janus: &JanusClient, key: &str) -> Result<()> {
+    let body = json!({ "request": "pause" });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    status(reply.data, "pausing")
+}
+
+/// Stops the stream of the handle registered under `key`.
+pub async fn stop(janus: &JanusClient, key: &str) -> Result<()> {
+    let body = json!({ "request": "stop" });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    status(reply.data, "stopping")
+}
+
+/// Checks that the plugin answered a viewer's request with `expected`, as
+/// in {"streaming": "event", "result": {"status": "starting"}}.
+fn status(data: Value, expected: &str) -> Result<()> {
+    if data["result"]["status"] == expected {
+        Ok(())
+    } else {
+        Err(Error::Unexpected(data.to_string()))
+    }
+}
+
+/// Sends `body` to the plugin on our own handle, attaching it first if it
+/// is not yet.
+async fn control(janus: &JanusClient, body: Value) -> Result<Value> {
+    janus.plugin_handle(CONTROLLER, PLUGIN).await?;
+    let reply = janus.message_with_jsep(CONTROLLER, body, None).await?;
+    Ok(reply.data)
+}
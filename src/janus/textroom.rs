@@ -0,0 +1,273 @@
+//! Requests of the TextRoom plugin, `janus.plugin.textroom`, whose rooms
+//! relay text between their participants over WebRTC data channels.
+//!
+//! Participants talk to the plugin over their data channel once it is set
+//! up, but the same requests work through the Janus API as well. That is
+//! how we control the rooms: creating them on our own handle, and joining
+//! chat users and posting their messages on the handles of their data
+//! channels.
+
+use std::iter;
+
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.textroom";
+
+/// The key our own handle to the plugin is registered under.
+pub const CONTROLLER: &str = "textroom";
+
+/// The settings of a new room, see `create_room`. Whatever is left out
+/// gets the plugin's default.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CreateRoom {
+    /// The id of the room, picked by the plugin when left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// What editing or destroying the room, and announcements, take.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// What joining the room takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// Whether the room is left out of `list_rooms`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_private: Option<bool>,
+    /// How many of the last messages new participants get.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<u32>,
+    /// An HTTP backend the plugin posts every message to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post: Option<String>,
+    /// Whether the plugin saves the room to its config file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent: Option<bool>,
+    /// The `admin_key` of the plugin, if creating rooms requires it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+}
+
+/// Creates a room and returns its id.
+pub async fn create_room(janus: &JanusClient, room: &CreateRoom) -> Result<u64> {
+    // The plugin replies with {"textroom": "created", "room": 5555, "permanent": false}
+    let mut body = serde_json::to_value(room)?;
+    body["request"] = Value::from("create");
+    let data = control(janus, body).await?;
+
+    data["room"]
+        .as_u64()
+        .ok_or_else(|| Error::Unexpected(data.to_string()))
+}
+
+/// Whether room `room_id` exists.
+pub async fn exists(janus: &JanusClient, room_id: u64) -> Result<bool> {
+    // The plugin replies with {"textroom": "success", "room": 1234, "exists": true}
+    let body = json!({ "request": "exists", "room": room_id });
+    let data = control(janus, body).await?;
+
+    match data["exists"].as_bool() {
+        Some(exists) => Ok(exists),
+        None => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Destroys room `room_id`, whose `secret` it takes if it has one.
+///
+/// The participants of the room are told by the plugin over their data
+/// channel.
+pub async fn destroy_room(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    permanent: bool,
+) -> Result<()> {
+    // The plugin replies with {"textroom": "destroyed", "room": 5555} or an
+    // error such as {"textroom": "error", "error_code": 417, "error": "No such room (5555)"}
+    let mut body = json!({
+        "request": "destroy",
+        "room": room_id,
+        "permanent": permanent,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = control(janus, body).await?;
+
+    match data["textroom"].as_str() {
+        Some("destroyed") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// A room as `list_rooms` tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoomInfo {
+    pub room: u64,
+    #[serde(default)]
+    pub description: String,
+    /// Whether joining the room takes a pin.
+    pub pin_required: bool,
+    pub num_participants: u32,
+}
+
+/// The public rooms of the plugin.
+pub async fn list_rooms(janus: &JanusClient) -> Result<Vec<RoomInfo>> {
+    // The plugin replies with {"textroom": "success", "list": [{"room": 1234, ...}, ...]}
+    let data = control(janus, json!({ "request": "list" })).await?;
+    Ok(serde_json::from_value(data["list"].clone())?)
+}
+
+/// A participant of a room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Participant {
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+/// The participants of room `room_id`.
+pub async fn list_participants(janus: &JanusClient, room_id: u64) -> Result<Vec<Participant>> {
+    // The plugin replies with {"textroom": "success", "participants": [{"username": "bob"}, ...]}
+    let body = json!({ "request": "listparticipants", "room": room_id });
+    let data = control(janus, body).await?;
+    Ok(serde_json::from_value(data["participants"].clone())?)
+}
+
+/// Sends `text` to every participant of room `room_id`, from the room
+/// itself rather than from one of them.
+pub async fn announcement(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    text: &str,
+) -> Result<()> {
+    let mut body = json!({
+        "request": "announcement",
+        "room": room_id,
+        "text": text,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    control(janus, body).await?;
+    Ok(())
+}
+
+/// Kicks participant `username` out of room `room_id`.
+pub async fn kick(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    username: &str,
+) -> Result<()> {
+    let mut body = json!({
+        "request": "kick",
+        "room": room_id,
+        "username": username,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    control(janus, body).await?;
+    Ok(())
+}
+
+/// Attaches a handle to the plugin under `key`, for the data channel of a
+/// browser, and returns the gateway's offer of it.
+///
+/// The browser's answer goes back with `ack`.
+pub async fn setup(janus: &JanusClient, key: &str) -> Result<Value> {
+    // The plugin replies with {"textroom": "event", "result": "ok"} along
+    // with its offer.
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus
+        .message_with_jsep(key, json!({ "request": "setup" }), None)
+        .await?;
+    match reply.jsep {
+        Some(offer) => Ok(offer),
+        None => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Hands the browser's `answer` to the gateway's offer from `setup` to the
+/// handle registered under `key`.
+pub async fn ack(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    let body = json!({ "request": "ack" });
+    janus.message_with_jsep(key, body, Some(answer)).await?;
+    Ok(())
+}
+
+/// How to join a room, see `join`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Join {
+    pub room: u64,
+    /// The unique name of the participant in the room.
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Joins the handle registered under `key` to a room, and returns who is
+/// in it already. The messages of the room then go out on its data channel.
+pub async fn join(janus: &JanusClient, key: &str, join: &Join) -> Result<Vec<Participant>> {
+    // The plugin replies with {"textroom": "success", "participants": [...]}
+    let mut body = serde_json::to_value(join)?;
+    body["request"] = Value::from("join");
+    let data = participate(janus, key, body).await?;
+    Ok(serde_json::from_value(data["participants"].clone()).unwrap_or_default())
+}
+
+/// Leaves room `room_id` with the handle registered under `key`.
+pub async fn leave(janus: &JanusClient, key: &str, room_id: u64) -> Result<()> {
+    let body = json!({ "request": "leave", "room": room_id });
+    participate(janus, key, body).await?;
+    Ok(())
+}
+
+/// Sends `text` to room `room_id` as the participant behind the handle
+/// registered under `key`.
+pub async fn message(janus: &JanusClient, key: &str, room_id: u64, text: &str) -> Result<()> {
+    let body = json!({
+        "request": "message",
+        "room": room_id,
+        "text": text,
+        "ack": false,
+    });
+    participate(janus, key, body).await?;
+    Ok(())
+}
+
+/// Sends `body` to the plugin on our own handle, attaching it first if it
+/// is not yet.
+async fn control(janus: &JanusClient, body: Value) -> Result<Value> {
+    janus.plugin_handle(CONTROLLER, PLUGIN).await?;
+    let reply = janus.message_with_jsep(CONTROLLER, body, None).await?;
+    Ok(reply.data)
+}
+
+/// Sends `body` to the plugin as a participant would on their data
+/// channel, which takes a transaction of its own.
+async fn participate(janus: &JanusClient, key: &str, mut body: Value) -> Result<Value> {
+    body["textroom"] = body["request"].clone();
+    body["transaction"] = Value::from(transaction());
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    Ok(reply.data)
+}
+
+fn transaction() -> String {
+    iter::repeat(())
+        .map(|()| OsRng.sample(Alphanumeric))
+        .take(12)
+        .collect()
+}
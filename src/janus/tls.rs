@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::PathBuf;
+
+use native_tls::{Certificate, Identity, TlsConnector};
+
+use super::Result;
+
+/// How `wss://` connections to the gateway are secured.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// CA certificates (PEM files) to trust on top of the system ones.
+    pub ca_certs: Vec<PathBuf>,
+    /// Certificate we present to the gateway, if it asks for one.
+    pub client_cert: Option<ClientCert>,
+    /// Accept any certificate, self-signed or not. Only ever for
+    /// development.
+    pub accept_invalid_certs: bool,
+}
+
+/// A client certificate with its private key, both PEM files.
+#[derive(Clone, Debug)]
+pub struct ClientCert {
+    pub cert: PathBuf,
+    /// The key, in PKCS#8 format.
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    /// Builds the connector for `wss://` connections.
+    pub fn connector(&self) -> Result<TlsConnector> {
+        let mut builder = TlsConnector::builder();
+
+        for path in &self.ca_certs {
+            builder.add_root_certificate(Certificate::from_pem(&fs::read(path)?)?);
+        }
+        if let Some(client_cert) = &self.client_cert {
+            let cert = fs::read(&client_cert.cert)?;
+            let key = fs::read(&client_cert.key)?;
+            builder.identity(Identity::from_pkcs8(&cert, &key)?);
+        }
+        if self.accept_invalid_certs {
+            builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder.build()?)
+    }
+}
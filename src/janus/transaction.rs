@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+use std::sync::Arc;
+
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+/// Generates the transaction strings that pair our requests with their
+/// replies.
+#[derive(Clone)]
+pub struct TransactionIds {
+    generate: Arc<dyn Fn() -> String + Send + Sync>,
+}
+
+impl TransactionIds {
+    /// Random alphanumeric strings of `len` characters, drawn from the
+    /// operating system's secure random source.
+    pub fn random(len: usize) -> TransactionIds {
+        TransactionIds::from_fn(move || {
+            iter::repeat(())
+                .map(|()| OsRng.sample(Alphanumeric))
+                .take(len)
+                .collect()
+        })
+    }
+
+    /// Strings made by `generate`, so tests can get predictable ids.
+    ///
+    /// `generate` must not keep returning an id that is still pending.
+    pub fn from_fn<F>(generate: F) -> TransactionIds
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        TransactionIds {
+            generate: Arc::new(generate),
+        }
+    }
+
+    /// Returns a new id that none of the `pending` transactions uses.
+    pub fn next<V>(&self, pending: &HashMap<String, V>) -> String {
+        loop {
+            let id = (self.generate)();
+            if !pending.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+impl Default for TransactionIds {
+    /// Twelve characters, like the ids the Janus demos use.
+    fn default() -> Self {
+        TransactionIds::random(12)
+    }
+}
+
+impl fmt::Debug for TransactionIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TransactionIds")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[test]
+    fn next_skips_pending_ids() {
+        let ids = Mutex::new(vec!["b", "a", "a"]);
+        let transactions =
+            TransactionIds::from_fn(move || ids.lock().unwrap().pop().unwrap().to_string());
+        let mut pending = HashMap::new();
+        pending.insert("a".to_string(), ());
+
+        assert_eq!(transactions.next(&pending), "b");
+    }
+
+    #[test]
+    fn random_ids_have_the_length_asked_for() {
+        let id = TransactionIds::random(12).next::<()>(&HashMap::new());
+
+        assert_eq!(id.len(), 12);
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+}
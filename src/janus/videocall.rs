@@ -0,0 +1,122 @@
+//! Requests and events of the VideoCall plugin, `janus.plugin.videocall`,
+//! for calls between two browsers through the gateway.
+//!
+//! Every browser registers a username on a handle of its own, which other
+//! browsers then call. The offers and answers of the calls are relayed by
+//! the plugin, as the events parsed by `VideoCallEvent::parse`.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{Error, Event, JanusClient, Result};
+
+pub const PLUGIN: &str = "janus.plugin.videocall";
+
+/// Registers `username` on the handle registered under `key`, attaching
+/// one to the plugin first if there is none.
+pub async fn register(janus: &JanusClient, key: &str, username: &str) -> Result<()> {
+    // The plugin replies with:
+    // {"videocall": "event", "result": {"event": "registered", "username": "alice"}}
+    // or an error such as
+    // {"videocall": "event", "error_code": 476, "error": "Username 'alice' already taken"}
+    let body = json!({ "request": "register", "username": username });
+    janus.plugin_handle(key, PLUGIN).await?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    result(&reply.data, "registered")
+}
+
+/// Calls `username` with the browser's `offer` from the handle registered
+/// under `key`.
+///
+/// The callee gets `VideoCallEvent::IncomingCall` with the offer, and once
+/// they accept we get `VideoCallEvent::Accepted` with their answer.
+pub async fn call(janus: &JanusClient, key: &str, username: &str, offer: Value) -> Result<()> {
+    // The plugin replies with {"videocall": "event", "result": {"event": "calling"}}
+    let body = json!({ "request": "call", "username": username });
+    let reply = janus.message_with_jsep(key, body, Some(offer)).await?;
+    result(&reply.data, "calling")
+}
+
+/// Accepts the incoming call of the handle registered under `key`, with the
+/// browser's `answer` to the offer of `VideoCallEvent::IncomingCall`.
+pub async fn accept(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    let body = json!({ "request": "accept" });
+    let reply = janus.message_with_jsep(key, body, Some(answer)).await?;
+    result(&reply.data, "accepted")
+}
+
+/// Hangs up the call of the handle registered under `key`, or declines the
+/// incoming one. The other side gets `VideoCallEvent::Hangup`.
+pub async fn hangup(janus: &JanusClient, key: &str) -> Result<()> {
+    let reply = janus
+        .message_with_jsep(key, json!({ "request": "hangup" }), None)
+        .await?;
+    result(&reply.data, "hangup")
+}
+
+/// Checks that the plugin answered with the `expected` result, as in
+/// {"videocall": "event", "result": {"event": "calling"}}.
+fn result(data: &Value, expected: &str) -> Result<()> {
+    if data["result"]["event"] == expected {
+        Ok(())
+    } else {
+        Err(Error::Unexpected(data.to_string()))
+    }
+}
+
+/// What the plugin tells about the calls of a handle.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum VideoCallEvent {
+    /// Someone calls, with their offer.
+    #[serde(rename = "incomingcall")]
+    IncomingCall {
+        username: String,
+        jsep: Option<Value>,
+    },
+    /// The callee accepted our call, with the answer to our offer.
+    Accepted {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        jsep: Option<Value>,
+    },
+    /// The call is over, or was declined.
+    Hangup {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+impl VideoCallEvent {
+    /// Reads an event of the plugin such as
+    /// {"videocall": "event", "result": {"event": "incomingcall", "username": "alice"}}
+    /// along with the offer of the caller.
+    pub fn parse(event: &Event) -> Option<VideoCallEvent> {
+        let (data, jsep) = match event {
+            Event::Plugin(event) if event.plugindata.plugin == PLUGIN => {
+                (&event.plugindata.data, event.jsep.clone())
+            }
+            _ => return None,
+        };
+        let result = &data["result"];
+        let text = |field: &str| result[field].as_str().map(String::from);
+        let videocall_event = match result["event"].as_str()? {
+            "incomingcall" => VideoCallEvent::IncomingCall {
+                username: text("username")?,
+                jsep,
+            },
+            "accepted" => VideoCallEvent::Accepted {
+                username: text("username"),
+                jsep,
+            },
+            "hangup" => VideoCallEvent::Hangup {
+                username: text("username"),
+                reason: text("reason"),
+            },
+            _ => return None,
+        };
+        Some(videocall_event)
+    }
+}
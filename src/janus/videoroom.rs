@@ -0,0 +1,1222 @@
+//! Requests and events of the VideoRoom plugin, `janus.plugin.videoroom`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Error, Event, JanusClient, JanusEventHandler, PluginReply, Result};
+
+/// The settings of a new room, see `create_room`. Whatever is left out
+/// gets the plugin's default.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CreateRoom {
+    /// The id of the room, picked by the plugin when left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// How many participants may publish at the same time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publishers: Option<u32>,
+    /// The bitrate cap of every publisher, in bits per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    /// How often to ask publishers for a keyframe, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fir_freq: Option<u32>,
+    /// The audio codecs publishers may use, by preference, e.g. `opus`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audiocodec: Option<String>,
+    /// The video codecs publishers may use, by preference, e.g. `vp8,h264`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub videocodec: Option<String>,
+    /// What editing or destroying the room takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// What joining the room takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// Whether the plugin saves the room to its config file, to have it
+    /// again after a restart.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent: Option<bool>,
+    /// The `admin_key` of the plugin, if creating rooms requires it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+}
+
+/// Creates a room and returns its id.
+///
+/// A plugin error comes back as `Error::Janus`, e.g. with
+/// `VideoRoomError::RoomExists` or, without the right `admin_key`,
+/// `VideoRoomError::MissingElement` or `VideoRoomError::Unauthorized`.
+pub async fn create_room(janus: &JanusClient, room: &CreateRoom) -> Result<u64> {
+    // The plugin replies with:
+    // {"videoroom": "created", "room": 5555, "permanent": false}
+    // or an error such as:
+    // {"videoroom": "event", "error_code": 429, "error": "Missing mandatory element (admin_key)"}
+    let mut body = serde_json::to_value(room)?;
+    body["request"] = Value::from("create");
+    let data = janus.message(body).await?;
+
+    data["room"]
+        .as_u64()
+        .ok_or_else(|| Error::Unexpected(data.to_string()))
+}
+
+/// Whether room `room_id` exists.
+pub async fn exists(janus: &JanusClient, room_id: u64) -> Result<bool> {
+    // The plugin replies with {"videoroom": "success", "room": 1234, "exists": true}.
+    let body = json!({ "request": "exists", "room": room_id });
+    let data = janus.message(body).await?;
+
+    match data["exists"].as_bool() {
+        Some(exists) => Ok(exists),
+        None => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// The changes to an existing room, see `edit_room`. Whatever is left out
+/// stays as it is.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EditRoom {
+    pub room: u64,
+    /// The current secret of the room, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_bitrate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_publishers: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_pin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_secret: Option<String>,
+    /// Whether the changes go to the plugin's config file as well.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent: Option<bool>,
+}
+
+/// Changes the settings of a room.
+pub async fn edit_room(janus: &JanusClient, edit: &EditRoom) -> Result<()> {
+    // The plugin replies with {"videoroom": "edited", "room": 5555} or an
+    // error such as {"videoroom": "event", "error_code": 433, "error": "Unauthorized (wrong secret)"}
+    let mut body = serde_json::to_value(edit)?;
+    body["request"] = Value::from("edit");
+    let data = janus.message(body).await?;
+
+    match data["videoroom"].as_str() {
+        Some("edited") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Destroys room `room_id`, whose `secret` it takes if it has one. A
+/// `permanent` destroy removes the room from the plugin's config file as
+/// well.
+///
+/// The participants of the room are told by the plugin with a `destroyed`
+/// event.
+pub async fn destroy_room(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    permanent: bool,
+) -> Result<()> {
+    // The plugin replies with {"videoroom": "destroyed", "room": 5555} or an
+    // error such as {"videoroom": "event", "error_code": 426, "error": "No such room (5555)"}
+    let mut body = json!({
+        "request": "destroy",
+        "room": room_id,
+        "permanent": permanent,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = janus.message(body).await?;
+
+    match data["videoroom"].as_str() {
+        Some("destroyed") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// A room as `list_rooms` tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoomInfo {
+    pub room: u64,
+    #[serde(default)]
+    pub description: String,
+    /// Whether joining the room takes a pin.
+    pub pin_required: bool,
+    pub max_publishers: u32,
+    pub bitrate: u64,
+    pub num_participants: u32,
+}
+
+/// Which rooms `list_rooms` keeps. The default keeps them all.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RoomFilter {
+    /// Only rooms whose description starts with this.
+    pub prefix: Option<String>,
+    /// Only rooms whose description contains this, ignoring case.
+    pub description: Option<String>,
+    /// Leave out the rooms that take a pin.
+    pub hide_pin_required: bool,
+}
+
+impl RoomFilter {
+    pub fn matches(&self, room: &RoomInfo) -> bool {
+        if self.hide_pin_required && room.pin_required {
+            return false;
+        }
+        if let Some(prefix) = &self.prefix {
+            if !room.description.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(description) = &self.description {
+            let description = description.to_lowercase();
+            if !room.description.to_lowercase().contains(&description) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The public rooms of the plugin that pass `filter`.
+pub async fn list_rooms(janus: &JanusClient, filter: &RoomFilter) -> Result<Vec<RoomInfo>> {
+    // The plugin replies with {"videoroom": "success", "list": [{"room": 1234, ...}, ...]}
+    let data = janus.message(json!({ "request": "list" })).await?;
+    let rooms: Vec<RoomInfo> = match data.get("list") {
+        Some(list) => serde_json::from_value(list.clone())?,
+        None => return Err(Error::Unexpected(data.to_string())),
+    };
+
+    Ok(rooms
+        .into_iter()
+        .filter(|room| filter.matches(room))
+        .collect())
+}
+
+/// A participant of a room as `list_participants` tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Participant {
+    pub id: u64,
+    pub display: Option<String>,
+    /// Whether the participant publishes media.
+    pub publisher: bool,
+    /// Whether the participant is talking, in rooms that detect it.
+    pub talking: Option<bool>,
+}
+
+/// The participants of room `room_id`.
+pub async fn list_participants(janus: &JanusClient, room_id: u64) -> Result<Vec<Participant>> {
+    // The plugin replies with
+    // {"videoroom": "participants", "room": 1234, "participants": [{"id": 42, "display": "bob", ...}]}
+    let data = janus
+        .message(json!({
+            "request": "listparticipants",
+            "room": room_id,
+        }))
+        .await?;
+
+    match data.get("participants") {
+        Some(participants) => Ok(serde_json::from_value(participants.clone())?),
+        None => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Kicks participant `user_id` out of room `room_id`, with the room's
+/// `secret` if it has one.
+pub async fn kick(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    user_id: u64,
+) -> Result<()> {
+    // The plugin replies with {"videoroom": "success"} or an error such as
+    // {"videoroom": "event", "error_code": 428, "error": "No such user 42 in room 1234"}
+    let mut body = json!({
+        "request": "kick",
+        "room": room_id,
+        "id": user_id,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    janus.message(body).await?;
+
+    Ok(())
+}
+
+/// What `moderate` mutes or unmutes. Whatever is left out stays as it is.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Moderate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute_audio: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute_video: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute_data: Option<bool>,
+}
+
+/// Mutes or unmutes the media participant `user_id` publishes in room
+/// `room_id`, with the room's `secret` if it has one. The participant
+/// keeps publishing, but the plugin stops relaying what was muted.
+pub async fn moderate(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    user_id: u64,
+    moderate: Moderate,
+) -> Result<()> {
+    // The plugin replies with {"videoroom": "success"} or an error.
+    let mut body = serde_json::to_value(moderate)?;
+    body["request"] = Value::from("moderate");
+    body["room"] = Value::from(room_id);
+    body["id"] = Value::from(user_id);
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    janus.message(body).await?;
+
+    Ok(())
+}
+
+/// A publisher of a room, as the plugin lists them to those who join and
+/// in its events.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublisherInfo {
+    pub id: u64,
+    pub display: Option<String>,
+    pub audio_codec: Option<String>,
+    pub video_codec: Option<String>,
+    pub talking: Option<bool>,
+}
+
+/// How the media of a publisher is set up, see `publish` and `configure`.
+/// Whatever is left out gets the plugin's default, or stays as it is.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Publish {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<bool>,
+    /// The bitrate cap of the publisher, in bits per second, below the one
+    /// of the room.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    /// A new display name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    /// Whether to record the publisher, whatever the room does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<bool>,
+    /// Where the plugin saves the recording, without the extension, see
+    /// `recording_filename`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+/// How to join a room as a publisher, see `join_publisher`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Join {
+    pub room: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    /// What joining the room takes, if it has a pin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// What joining the room takes while it only lets in the tokens it
+    /// allows, see `allowed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// What joining a room as a publisher leaves us with.
+#[derive(Clone, Debug, Serialize)]
+pub struct Joined {
+    pub room: u64,
+    /// Our id in the room.
+    pub id: u64,
+    /// Our id towards the plugin only, for subscribing to the feeds of the
+    /// room on our behalf.
+    pub private_id: Option<u64>,
+    /// Who already publishes in the room.
+    pub publishers: Vec<PublisherInfo>,
+    /// The gateway's answer, when we published along with joining.
+    pub jsep: Option<Value>,
+}
+
+/// Joins a room as a publisher with the handle registered under `key`,
+/// without publishing anything yet.
+pub async fn join_publisher(janus: &JanusClient, key: &str, join: &Join) -> Result<Joined> {
+    let body = join_body("join", join)?;
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    joined(reply)
+}
+
+/// Joins a room as a publisher with the handle registered under `key` and
+/// publishes what the browser `offer`s in one go, with the plugin's
+/// `joinandconfigure`.
+///
+/// The gateway's answer is in the `jsep` of what we get back.
+pub async fn join_and_publish(
+    janus: &JanusClient,
+    key: &str,
+    join: &Join,
+    offer: Value,
+    publish: &Publish,
+) -> Result<Joined> {
+    let mut body = join_body("joinandconfigure", join)?;
+    if let Value::Object(settings) = serde_json::to_value(publish)? {
+        body.as_object_mut().unwrap().extend(settings);
+    }
+    let reply = janus.message_with_jsep(key, body, Some(offer)).await?;
+    joined(reply)
+}
+
+/// Publishes what the browser `offer`s with the handle registered under
+/// `key`, which joined a room as a publisher already, and returns the
+/// gateway's answer.
+pub async fn publish(
+    janus: &JanusClient,
+    key: &str,
+    offer: Value,
+    publish: &Publish,
+) -> Result<Value> {
+    // The plugin answers with {"videoroom": "event", "configured": "ok"}
+    // and the answer in the jsep.
+    let mut body = serde_json::to_value(publish)?;
+    body["request"] = Value::from("publish");
+    let reply = janus.message_with_jsep(key, body, Some(offer)).await?;
+
+    match reply.jsep {
+        Some(answer) => Ok(answer),
+        None => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Stops publishing with the handle registered under `key`, which stays in
+/// its room.
+pub async fn unpublish(janus: &JanusClient, key: &str) -> Result<()> {
+    // The plugin replies with {"videoroom": "event", "unpublished": "ok"}.
+    let body = json!({ "request": "unpublish" });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+
+    match reply.data["unpublished"].as_str() {
+        Some("ok") => Ok(()),
+        _ => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Leaves the room the handle registered under `key` joined, as a
+/// publisher or a subscriber. The handle may join a room again.
+pub async fn leave(janus: &JanusClient, key: &str) -> Result<()> {
+    // The plugin replies with {"videoroom": "event", "leaving": "ok"} to a
+    // publisher, and with {"videoroom": "event", "left": "ok"} to a
+    // subscriber.
+    let body = json!({ "request": "leave" });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+
+    let data = reply.data;
+    match (data["leaving"].as_str(), data["left"].as_str()) {
+        (Some("ok"), _) | (_, Some("ok")) => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Changes the setup of the publisher of the handle registered under
+/// `key`, e.g. to start or stop recording it, without negotiating again.
+pub async fn configure(janus: &JanusClient, key: &str, publish: &Publish) -> Result<()> {
+    // The plugin replies with {"videoroom": "event", "configured": "ok"}.
+    let mut body = serde_json::to_value(publish)?;
+    body["request"] = Value::from("configure");
+    let reply = janus.message_with_jsep(key, body, None).await?;
+
+    match reply.data["configured"].as_str() {
+        Some("ok") => Ok(()),
+        _ => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// The name of a recording of publisher `user_id` in room `room_id` after
+/// `template`, where `{room}`, `{user}` and `{timestamp}` stand for the
+/// room id, the user id and the seconds since the epoch.
+pub fn recording_filename(template: &str, room_id: u64, user_id: impl Display) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    template
+        .replace("{room}", &room_id.to_string())
+        .replace("{user}", &user_id.to_string())
+        .replace("{timestamp}", &timestamp.to_string())
+}
+
+/// The body of a `join` of a publisher, or of one of its variants.
+fn join_body(request: &str, join: &Join) -> Result<Value> {
+    let mut body = serde_json::to_value(join)?;
+    body["request"] = Value::from(request);
+    body["ptype"] = Value::from("publisher");
+    Ok(body)
+}
+
+/// Reads the `joined` event of the plugin.
+fn joined(reply: PluginReply) -> Result<Joined> {
+    // {"videoroom": "joined", "room": 1234, "id": 42, "private_id": 4242, "publishers": [...]}
+    let data = reply.data;
+    let (room, id) = match (
+        data["videoroom"].as_str(),
+        data["room"].as_u64(),
+        data["id"].as_u64(),
+    ) {
+        (Some("joined"), Some(room), Some(id)) => (room, id),
+        _ => return Err(Error::Unexpected(data.to_string())),
+    };
+    let publishers = match data.get("publishers") {
+        Some(publishers) => serde_json::from_value(publishers.clone())?,
+        None => Vec::new(),
+    };
+
+    Ok(Joined {
+        room,
+        id,
+        private_id: data["private_id"].as_u64(),
+        publishers,
+        jsep: reply.jsep,
+    })
+}
+
+/// A feed to subscribe to, see `join_subscriber`: all the streams of
+/// publisher `feed`, or only the one with `mid`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Feed {
+    pub feed: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mid: Option<String>,
+}
+
+/// A stream of a subscription, as the plugin lists them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SubscribedStream {
+    /// The mid of the stream in our PeerConnection.
+    pub mid: String,
+    /// `audio`, `video` or `data`.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// The publisher the stream comes from, and its mid there.
+    pub feed_id: Option<u64>,
+    pub feed_mid: Option<String>,
+    pub feed_display: Option<String>,
+}
+
+/// A subscription after it was set up or changed.
+#[derive(Clone, Debug, Serialize)]
+pub struct Subscription {
+    pub room: u64,
+    pub streams: Vec<SubscribedStream>,
+    /// The gateway's offer for the streams, to be answered with `start`.
+    pub jsep: Option<Value>,
+}
+
+/// Joins room `room_id` as a subscriber with the handle registered under
+/// `key`, receiving the streams of `feeds` in a single PeerConnection.
+///
+/// The `private_id` we got as a publisher in the room, if any, ties the
+/// subscription to us. The gateway's offer is in the `jsep` of what we get
+/// back, and the streams flow once it is answered with `start`.
+pub async fn join_subscriber(
+    janus: &JanusClient,
+    key: &str,
+    room_id: u64,
+    feeds: &[Feed],
+    private_id: Option<u64>,
+    pin: Option<&str>,
+) -> Result<Subscription> {
+    // The plugin replies with:
+    // {"videoroom": "attached", "room": 1234, "streams": [...]}
+    // along with its offer.
+    let mut body = json!({
+        "request": "join",
+        "ptype": "subscriber",
+        "room": room_id,
+        "streams": feeds,
+    });
+    if let Some(private_id) = private_id {
+        body["private_id"] = Value::from(private_id);
+    }
+    if let Some(pin) = pin {
+        body["pin"] = Value::from(pin);
+    }
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    subscription("attached", reply)
+}
+
+/// Adds `feeds` to the subscription of the handle registered under `key`.
+///
+/// The gateway offers the changed PeerConnection anew, which is answered
+/// with `start` again.
+pub async fn subscribe(janus: &JanusClient, key: &str, feeds: &[Feed]) -> Result<Subscription> {
+    update(janus, key, "subscribe", feeds).await
+}
+
+/// Removes `feeds` from the subscription of the handle registered under
+/// `key`, see `subscribe`.
+pub async fn unsubscribe(janus: &JanusClient, key: &str, feeds: &[Feed]) -> Result<Subscription> {
+    update(janus, key, "unsubscribe", feeds).await
+}
+
+async fn update(
+    janus: &JanusClient,
+    key: &str,
+    request: &str,
+    feeds: &[Feed],
+) -> Result<Subscription> {
+    // The plugin replies with:
+    // {"videoroom": "updated", "room": 1234, "streams": [...]}
+    // along with its new offer, if anything changed.
+    let body = json!({ "request": request, "streams": feeds });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+    subscription("updated", reply)
+}
+
+/// Hands the browser's `answer` to the offer of a subscription to the
+/// handle registered under `key`, which starts the streams.
+pub async fn start(janus: &JanusClient, key: &str, answer: Value) -> Result<()> {
+    // The plugin replies with:
+    // {"videoroom": "event", "room": 1234, "started": "ok"}
+    let body = json!({ "request": "start" });
+    let reply = janus.message_with_jsep(key, body, Some(answer)).await?;
+    match reply.data["started"].as_str() {
+        Some("ok") => Ok(()),
+        _ => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// A stream of a subscription to switch over to another publisher, see
+/// `switch`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Switch {
+    /// The publisher to switch to, and the mid of its stream.
+    pub feed: u64,
+    pub mid: String,
+    /// The mid of the stream in the subscriber's PeerConnection.
+    pub sub_mid: String,
+}
+
+/// Switches streams of the subscription of the handle registered under
+/// `key` to other publishers, without negotiating again, and returns the
+/// streams of the subscription. The new stream must be of the same kind
+/// and codec as the old one.
+pub async fn switch(
+    janus: &JanusClient,
+    key: &str,
+    switches: &[Switch],
+) -> Result<Vec<SubscribedStream>> {
+    // The plugin replies with:
+    // {"videoroom": "event", "switched": "ok", "room": 1234, "changes": 1, "streams": [...]}
+    let body = json!({ "request": "switch", "streams": switches });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+
+    let data = reply.data;
+    match (data["switched"].as_str(), data.get("streams")) {
+        (Some("ok"), Some(streams)) => Ok(serde_json::from_value(streams.clone())?),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Which simulcast substream and temporal layer, or SVC spatial and
+/// temporal layer, to receive of a stream of a subscription, see
+/// `set_layers`. Whatever is left out stays as it is.
+///
+/// Lower layers take less bandwidth, for subscribers on a poor connection.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Layers {
+    /// The mid of the stream in the subscriber's PeerConnection.
+    pub mid: String,
+    /// The simulcast substream, 0 being the lowest quality.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub substream: Option<u8>,
+    /// The simulcast temporal layer, 0 being the lowest frame rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temporal: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spatial_layer: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temporal_layer: Option<u8>,
+}
+
+/// Changes the layers the subscription of the handle registered under
+/// `key` receives, without negotiating again.
+pub async fn set_layers(janus: &JanusClient, key: &str, layers: &[Layers]) -> Result<()> {
+    // The plugin replies with {"videoroom": "event", "configured": "ok"}.
+    let body = json!({ "request": "configure", "streams": layers });
+    let reply = janus.message_with_jsep(key, body, None).await?;
+
+    match reply.data["configured"].as_str() {
+        Some("ok") => Ok(()),
+        _ => Err(Error::Unexpected(reply.data.to_string())),
+    }
+}
+
+/// Reads the `attached` or `updated` event of the plugin.
+fn subscription(expected: &str, reply: PluginReply) -> Result<Subscription> {
+    let data = reply.data;
+    let room = match (data["videoroom"].as_str(), data["room"].as_u64()) {
+        (Some(videoroom), Some(room)) if videoroom == expected => room,
+        _ => return Err(Error::Unexpected(data.to_string())),
+    };
+    let streams = match data.get("streams") {
+        Some(streams) => serde_json::from_value(streams.clone())?,
+        None => Vec::new(),
+    };
+
+    Ok(Subscription {
+        room,
+        streams,
+        jsep: reply.jsep,
+    })
+}
+
+/// The forwarding of what a publisher sends to an external RTP
+/// destination, e.g. a recorder or transcoder, see `rtp_forward`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RtpForward {
+    pub room: u64,
+    pub publisher_id: u64,
+    /// Where to forward to, unless a stream says otherwise.
+    pub host: String,
+    /// `ipv4` or `ipv6`, for a `host` that resolves to both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_family: Option<String>,
+    pub streams: Vec<ForwardStream>,
+    /// The SRTP suite to encrypt with, 32 or 80, along with the base64
+    /// `srtp_crypto` key. Plain RTP when left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srtp_suite: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srtp_crypto: Option<String>,
+    /// The secret of the room, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// The `admin_key` of the plugin, if forwarding requires it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+}
+
+/// A stream of a publisher to forward, see `RtpForward`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ForwardStream {
+    /// The mid of the stream in the publisher's PeerConnection.
+    pub mid: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// The payload type to forward with, for the codec the receiving end
+    /// expects, instead of the one of the publisher.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pt: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssrc: Option<u32>,
+    /// Where the receiving end may send RTCP feedback to, e.g. to ask for
+    /// keyframes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtcp_port: Option<u16>,
+}
+
+/// A forwarder of a stream, as the plugin tells about it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Forwarder {
+    pub stream_id: u64,
+    /// `audio`, `video` or `data`.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub ssrc: Option<u32>,
+    pub pt: Option<u8>,
+    pub srtp: Option<bool>,
+}
+
+/// Starts forwarding the streams of a publisher as `forward` says, and
+/// returns a forwarder for every stream.
+pub async fn rtp_forward(janus: &JanusClient, forward: &RtpForward) -> Result<Vec<Forwarder>> {
+    // The plugin replies with:
+    // {"videoroom": "rtp_forward", "room": 1234, "publisher_id": 42, "forwarders": [{"stream_id": 7, ...}]}
+    let mut body = serde_json::to_value(forward)?;
+    body["request"] = Value::from("rtp_forward");
+    let data = janus.message(body).await?;
+
+    match (data["videoroom"].as_str(), data.get("forwarders")) {
+        (Some("rtp_forward"), Some(forwarders)) => Ok(serde_json::from_value(forwarders.clone())?),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// Stops forwarder `stream_id` of publisher `publisher_id` in room
+/// `room_id`, with the room's `secret` if it has one.
+pub async fn stop_rtp_forward(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    publisher_id: u64,
+    stream_id: u64,
+) -> Result<()> {
+    // The plugin replies with:
+    // {"videoroom": "stop_rtp_forward", "room": 1234, "publisher_id": 42, "stream_id": 7}
+    let mut body = json!({
+        "request": "stop_rtp_forward",
+        "room": room_id,
+        "publisher_id": publisher_id,
+        "stream_id": stream_id,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = janus.message(body).await?;
+
+    match data["videoroom"].as_str() {
+        Some("stop_rtp_forward") => Ok(()),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// The forwarders of a publisher, as `list_forwarders` tells about them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublisherForwarders {
+    pub publisher_id: u64,
+    pub display: Option<String>,
+    pub forwarders: Vec<Forwarder>,
+}
+
+/// The forwarders of every publisher in room `room_id`, with the room's
+/// `secret` if it has one.
+pub async fn list_forwarders(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+) -> Result<Vec<PublisherForwarders>> {
+    // The plugin replies with:
+    // {"videoroom": "forwarders", "room": 1234, "publishers": [{"publisher_id": 42, "forwarders": [...]}]}
+    let mut body = json!({ "request": "listforwarders", "room": room_id });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = janus.message(body).await?;
+
+    match (data["videoroom"].as_str(), data.get("publishers")) {
+        (Some("forwarders"), Some(publishers)) => Ok(serde_json::from_value(publishers.clone())?),
+        _ => Err(Error::Unexpected(data.to_string())),
+    }
+}
+
+/// A forwarder by the id of its publisher and its stream id.
+pub type ForwarderId = (u64, u64);
+
+/// The forwarders we started, by room, so that they are stopped along with
+/// their room rather than left behind.
+#[derive(Clone, Default)]
+pub struct Forwarders {
+    rooms: Arc<Mutex<HashMap<u64, Vec<ForwarderId>>>>,
+}
+
+impl Forwarders {
+    /// Starts forwarding as `forward` says, see `rtp_forward`, and keeps
+    /// track of the forwarders.
+    pub async fn forward(
+        &self,
+        janus: &JanusClient,
+        forward: &RtpForward,
+    ) -> Result<Vec<Forwarder>> {
+        let forwarders = rtp_forward(janus, forward).await?;
+        let mut rooms = self.rooms.lock().unwrap();
+        let active = rooms.entry(forward.room).or_default();
+        for forwarder in &forwarders {
+            active.push((forward.publisher_id, forwarder.stream_id));
+        }
+        Ok(forwarders)
+    }
+
+    /// Stops a forwarder, see `stop_rtp_forward`, and forgets about it.
+    pub async fn stop(
+        &self,
+        janus: &JanusClient,
+        room_id: u64,
+        secret: Option<&str>,
+        publisher_id: u64,
+        stream_id: u64,
+    ) -> Result<()> {
+        stop_rtp_forward(janus, room_id, secret, publisher_id, stream_id).await?;
+        if let Some(active) = self.rooms.lock().unwrap().get_mut(&room_id) {
+            active.retain(|&forwarder| forwarder != (publisher_id, stream_id));
+        }
+        Ok(())
+    }
+
+    /// The forwarders we started in room `room_id`.
+    pub fn active(&self, room_id: u64) -> Vec<ForwarderId> {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(&room_id).cloned().unwrap_or_default()
+    }
+
+    /// Stops all the forwarders we started in room `room_id`, e.g. before
+    /// destroying it. The forwarders that fail to stop are forgotten all
+    /// the same, as they most likely are gone already.
+    pub async fn stop_room(&self, janus: &JanusClient, room_id: u64, secret: Option<&str>) {
+        let active = self.rooms.lock().unwrap().remove(&room_id);
+        for (publisher_id, stream_id) in active.unwrap_or_default() {
+            if let Err(e) = stop_rtp_forward(janus, room_id, secret, publisher_id, stream_id).await
+            {
+                eprintln!(
+                    "forwarder {} of room {} could not be stopped: {}",
+                    stream_id, room_id, e
+                );
+            }
+        }
+    }
+}
+
+/// Starts or stops recording every publisher of room `room_id`, with the
+/// room's `secret` if it has one. Publishers joining later are recorded as
+/// well while it is on.
+pub async fn enable_recording(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    record: bool,
+) -> Result<()> {
+    // The plugin replies with {"videoroom": "success", "record": true} or
+    // an error.
+    let mut body = json!({
+        "request": "enable_recording",
+        "room": room_id,
+        "record": record,
+    });
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    janus.message(body).await?;
+
+    Ok(())
+}
+
+/// Which rooms we turned recording on for, see `enable_recording`.
+#[derive(Clone, Default)]
+pub struct Recordings {
+    rooms: Arc<Mutex<HashMap<u64, bool>>>,
+}
+
+impl Recordings {
+    /// Starts or stops recording room `room_id`, see `enable_recording`,
+    /// and remembers it.
+    pub async fn set(
+        &self,
+        janus: &JanusClient,
+        room_id: u64,
+        secret: Option<&str>,
+        record: bool,
+    ) -> Result<()> {
+        enable_recording(janus, room_id, secret, record).await?;
+        self.rooms.lock().unwrap().insert(room_id, record);
+        Ok(())
+    }
+
+    /// Whether we turned recording on for room `room_id`.
+    pub fn is_recording(&self, room_id: u64) -> bool {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(&room_id).copied().unwrap_or(false)
+    }
+
+    /// Forgets about room `room_id`, once it is destroyed.
+    pub fn forget(&self, room_id: u64) {
+        self.rooms.lock().unwrap().remove(&room_id);
+    }
+}
+
+/// What `allowed` does with the tokens a room lets in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AllowedAction {
+    /// Only let in participants with one of the tokens.
+    Enable,
+    /// Let anyone in again.
+    Disable,
+    Add,
+    Remove,
+}
+
+impl AllowedAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            AllowedAction::Enable => "enable",
+            AllowedAction::Disable => "disable",
+            AllowedAction::Add => "add",
+            AllowedAction::Remove => "remove",
+        }
+    }
+}
+
+/// Changes which tokens room `room_id` lets in, with the room's `secret`
+/// if it has one, and returns the tokens it lets in now. Participants
+/// give their token when joining, see `Join`.
+pub async fn allowed(
+    janus: &JanusClient,
+    room_id: u64,
+    secret: Option<&str>,
+    action: AllowedAction,
+    tokens: &[String],
+) -> Result<Vec<String>> {
+    // The plugin replies with:
+    // {"videoroom": "success", "room": 1234, "allowed": ["token", ...]}
+    let mut body = json!({
+        "request": "allowed",
+        "room": room_id,
+        "action": action.as_str(),
+    });
+    if !tokens.is_empty() {
+        body["allowed"] = json!(tokens);
+    }
+    if let Some(secret) = secret {
+        body["secret"] = Value::from(secret);
+    }
+    let data = janus.message(body).await?;
+
+    // Without an `allowed` list when the room lets anyone in.
+    match data.get("allowed") {
+        Some(allowed) if !allowed.is_null() => Ok(serde_json::from_value(allowed.clone())?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The secret and pin of a room we created.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoomSecret {
+    pub secret: String,
+    pub pin: Option<String>,
+}
+
+/// The secrets and pins of the rooms we created, saved to a JSON file
+/// after every change so that they are still known after a restart.
+#[derive(Clone)]
+pub struct RoomSecrets {
+    path: PathBuf,
+    rooms: Arc<Mutex<HashMap<u64, RoomSecret>>>,
+}
+
+impl RoomSecrets {
+    /// The secrets saved to `path`, if any.
+    pub fn load(path: impl Into<PathBuf>) -> RoomSecrets {
+        let path = path.into();
+        let rooms = match fs::read(&path) {
+            Ok(saved) => serde_json::from_slice(&saved).unwrap_or_else(|e| {
+                eprintln!("room secrets in {} ignored: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        RoomSecrets {
+            path,
+            rooms: Arc::new(Mutex::new(rooms)),
+        }
+    }
+
+    /// The secret and pin of room `room_id`, if we know them.
+    pub fn get(&self, room_id: u64) -> Option<RoomSecret> {
+        self.rooms.lock().unwrap().get(&room_id).cloned()
+    }
+
+    pub fn insert(&self, room_id: u64, secret: RoomSecret) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.insert(room_id, secret);
+        self.save(&rooms);
+    }
+
+    pub fn remove(&self, room_id: u64) {
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.remove(&room_id).is_some() {
+            self.save(&rooms);
+        }
+    }
+
+    fn save(&self, rooms: &HashMap<u64, RoomSecret>) {
+        let saved = fs::write(&self.path, json!(rooms).to_string());
+        if let Err(e) = saved {
+            eprintln!(
+                "room secrets could not be saved to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// A participant of a room started or stopped talking, as the plugin tells
+/// every participant when the room detects talking.
+#[derive(Clone, Debug, Serialize)]
+pub struct Talking {
+    pub room: u64,
+    /// The publisher who is talking.
+    pub id: u64,
+    /// Whether they started talking, rather than stopped.
+    pub talking: bool,
+    /// The average audio level of the publisher, in -dBov.
+    pub audio_level: Option<f64>,
+}
+
+impl Talking {
+    /// Reads a `talking` or `stopped-talking` event of the plugin, such as
+    /// {"videoroom": "talking", "room": 1234, "id": 42, "audio-level-dBov-avg": 37.5}
+    pub fn parse(event: &Event) -> Option<Talking> {
+        let data = match event {
+            Event::Plugin(event) => &event.plugindata.data,
+            _ => return None,
+        };
+        let talking = match data["videoroom"].as_str() {
+            Some("talking") => true,
+            Some("stopped-talking") => false,
+            _ => return None,
+        };
+        Some(Talking {
+            room: data["room"].as_u64()?,
+            id: data["id"].as_u64()?,
+            talking,
+            audio_level: data["audio-level-dBov-avg"].as_f64(),
+        })
+    }
+}
+
+/// Who comes and goes among the publishers of a room, as the plugin tells
+/// every participant of the room.
+#[derive(Clone, Debug)]
+pub enum Presence {
+    /// Publishers that started publishing, or that already were when we
+    /// joined.
+    Publishers {
+        room: u64,
+        publishers: Vec<PublisherInfo>,
+    },
+    /// A publisher stopped publishing, but is still in the room.
+    Unpublished { room: u64, id: u64 },
+    /// A participant left the room, or was kicked out of it.
+    Leaving { room: u64, id: u64 },
+}
+
+impl Presence {
+    /// Reads an event of the plugin about the publishers of a room, such as
+    /// {"videoroom": "event", "room": 1234, "leaving": 42}
+    ///
+    /// Our own unpublishing and leaving, `"ok"` instead of an id, are left
+    /// out.
+    pub fn parse(event: &Event) -> Option<Presence> {
+        let data = match event {
+            Event::Plugin(event) => &event.plugindata.data,
+            _ => return None,
+        };
+        let room = data["room"].as_u64()?;
+        if let Some(id) = data["unpublished"].as_u64() {
+            return Some(Presence::Unpublished { room, id });
+        }
+        if let Some(id) = data["leaving"].as_u64() {
+            return Some(Presence::Leaving { room, id });
+        }
+        let publishers = serde_json::from_value(data.get("publishers")?.clone()).ok()?;
+        Some(Presence::Publishers { room, publishers })
+    }
+}
+
+/// The display names of the publishers of a room, by id.
+type Publishers = HashMap<u64, Option<String>>;
+
+/// The publishers of every room we heard about, kept up to date with the
+/// `Presence` events of the plugin.
+#[derive(Clone, Default)]
+pub struct Roster {
+    rooms: Arc<Mutex<HashMap<u64, Publishers>>>,
+}
+
+impl Roster {
+    /// Updates the roster with `presence`, and returns the publishers it
+    /// changed with their display name, if any.
+    ///
+    /// Every participant of a room hears of the same presence, so the
+    /// second time around nothing changes.
+    pub fn apply(&self, presence: &Presence) -> Vec<(u64, Option<String>)> {
+        let mut rooms = self.rooms.lock().unwrap();
+        match presence {
+            Presence::Publishers { room, publishers } => {
+                let known = rooms.entry(*room).or_default();
+                let mut new = Vec::new();
+                for publisher in publishers {
+                    if known
+                        .insert(publisher.id, publisher.display.clone())
+                        .is_none()
+                    {
+                        new.push((publisher.id, publisher.display.clone()));
+                    }
+                }
+                new
+            }
+            Presence::Unpublished { room, id } | Presence::Leaving { room, id } => {
+                let known = match rooms.get_mut(room) {
+                    Some(known) => known,
+                    None => return Vec::new(),
+                };
+                let gone = known.remove(id).map(|display| (*id, display));
+                if known.is_empty() {
+                    rooms.remove(room);
+                }
+                gone.into_iter().collect()
+            }
+        }
+    }
+
+    /// The publishers of room `room_id`, with their display name.
+    pub fn publishers(&self, room_id: u64) -> Vec<(u64, Option<String>)> {
+        let rooms = self.rooms.lock().unwrap();
+        match rooms.get(&room_id) {
+            Some(known) => known
+                .iter()
+                .map(|(id, display)| (*id, display.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Logs the publishers the videoroom plugin tells us about, such as:
+///
+/// {"videoroom": "event", "room": 1234, "publishers": [{"id": 6450855227982898, "display": "aluno/3", ...}]}
+pub struct PublisherLog;
+
+impl JanusEventHandler for PublisherLog {
+    fn on_event(&self, event: &Event) {
+        let data = match event {
+            Event::Plugin(event) => &event.plugindata.data,
+            _ => return,
+        };
+        if let Some(publishers) = data["publishers"].as_array() {
+            for publisher in publishers {
+                eprintln!(
+                    "new publisher in room {}: {} ({})",
+                    data["room"], publisher["id"], publisher["display"]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! A warp chat server whose users get WebRTC media through a Janus gateway.
+//!
+//! [`janus`] is the client for the gateway and can be used on its own,
+//! [`server`] has the routes of the chat server built on top of it, and
+//! [`config`] the settings they run with.
+
+pub mod config;
+pub mod janus;
+pub mod server;
@@ -25,304 +25,286 @@ sent: {"janus":"create", "apisecret":"api_secret4321", "transaction":"Qs6uJ7jODo
 received: {    "janus": "success",    "transaction": "Qs6uJ7jODoJR",    "data": {       "id": 2311473582179730    } }
 
 
-  ** please search for "HELP 1" and "HELP 2" for further details **
-
 */
 
-
 // #![deny(warnings)]
-use std::collections::HashMap;
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
-};
-
-use futures::{FutureExt, StreamExt};
-use tokio::sync::{mpsc, RwLock};
-use warp::ws::{Message, WebSocket};
-use warp::Filter;
 
-/// Our global unique user id counter.
-static NEXT_USER_ID: AtomicUsize = AtomicUsize::new(1);
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use tracing_subscriber::EnvFilter;
+use ws::config::{self, Config};
+use ws::janus::{self, videoroom};
+use ws::server::{self, chat};
+
+/// A chat server whose users get WebRTC media through a Janus gateway.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// The config file, which may not exist.
+    #[arg(long, short, global = true, value_name = "FILE", default_value = config::DEFAULT_PATH)]
+    config: PathBuf,
+    /// The address to serve on, over `listen` of the config and the
+    /// environment.
+    #[arg(long, global = true, value_name = "ADDR")]
+    listen: Option<SocketAddr>,
+    /// The address of the gateway API, over `url` of the config and the
+    /// environment.
+    #[arg(long, global = true, value_name = "URL")]
+    janus_url: Option<String>,
+    /// What to log, such as `info` or `ws=debug,warp=warn`, over `RUST_LOG`.
+    #[arg(long, global = true, value_name = "FILTER")]
+    log_level: Option<String>,
+    /// Serve the chat without connecting to the gateway, so without media.
+    #[arg(long, global = true)]
+    no_janus: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-/// Our state of currently connected users.
-///
-/// - Key is their id
-/// - Value is a sender of `warp::ws::Message`
-type Users = Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<Result<Message, warp::Error>>>>>;
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the chat, which is done without a command.
+    Serve,
+    /// Check the settings the server would run with, from the config, the
+    /// environment and these options, and print them, secrets left out.
+    CheckConfig,
+    /// Connect to the gateway and print what it tells about itself.
+    JanusInfo,
+}
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
-
-    // Keep track of all connected users, key is usize, value
-    // is a websocket sender.
-    let users = Users::default();
-    // Turn our "state" into a new Filter...
-    let users = warp::any().map(move || users.clone());
-
-    // GET /chat -> websocket upgrade
-    let chat = warp::path("chat")
-        // The `ws()` filter will prepare Websocket handshake...
-        .and(warp::ws())
-        .and(users)
-        .map(|ws: warp::ws::Ws, users| {
-            // This will call our function if the handshake succeeds.
-            ws.on_upgrade(move |socket| user_connected(socket, users))
-        });
-
-    // GET / -> index html
-    let index = warp::path::end().map(|| warp::reply::html(INDEX_HTML));
-
-    let routes = index.or(chat);
-
-    //
-    // HELP 1 - instead of only starting the warp server, we also need
-    //          to start concurrently our websocket client connection around here
-    //
-    //          example: 
-    //          wsclient_connect();
-    //          
-    warp::serve(routes).run(([167,99,189,30], 8080)).await;
-}
-
-async fn user_connected(ws: WebSocket, users: Users) {
-    // Use a counter to assign a new unique ID for this user.
-    let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
-
-    eprintln!("new chat user: {}", my_id);
-
-    // Split the socket into a sender and receive of messages.
-    let (user_ws_tx, mut user_ws_rx) = ws.split();
-
-    // Use an unbounded channel to handle buffering and flushing of messages
-    // to the websocket...
-    let (tx, rx) = mpsc::unbounded_channel();
-    tokio::task::spawn(rx.forward(user_ws_tx).map(|result| {
-        if let Err(e) = result {
-            eprintln!("websocket send error: {}", e);
-        }
-    }));
-
-    // Save the sender in our list of connected users.
-    users.write().await.insert(my_id, tx);
+    let cli = Cli::parse();
 
-    // Return a `Future` that is basically a state machine managing
-    // this specific user's connection.
-
-    // Make an extra clone to give to our disconnection handler...
-    let users2 = users.clone();
-
-    // Every time the user sends a message, broadcast it to
-    // all other users...
-    while let Some(result) = user_ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
+    // Logs of warp and of the Janus client, along with the spans of the
+    // Janus requests, filtered by `--log-level` or else `RUST_LOG`.
+    match &cli.log_level {
+        Some(level) => match EnvFilter::try_new(level) {
+            Ok(filter) => tracing_subscriber::fmt().with_env_filter(filter).init(),
             Err(e) => {
-                eprintln!("websocket error(uid={}): {}", my_id, e);
-                break;
+                eprintln!("bad --log-level {}: {}", level, e);
+                std::process::exit(2);
             }
-        };
-        user_message(my_id, msg, &users).await;
+        },
+        None => tracing_subscriber::fmt::init(),
     }
 
-    // user_ws_rx stream will keep processing as long as the user stays
-    // connected. Once they disconnect, then...
-    user_disconnected(my_id, &users2).await;
-}
-
-async fn user_message(my_id: usize, msg: Message, users: &Users) {
-    // Skip any non-Text messages...
-    let msg = if let Ok(s) = msg.to_str() {
-        s
-    } else {
-        return;
+    // The options of the command line win over everything else, and every
+    // command runs with the one config they end up in.
+    let loaded = Config::load_with(&cli.config, |config| {
+        if let Some(listen) = cli.listen {
+            config.server.listen = listen;
+        }
+        if let Some(url) = &cli.janus_url {
+            config.janus.url = url.clone();
+        }
+    });
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("bad config: {}", e);
+            std::process::exit(2);
+        }
     };
 
-    let new_msg = format!("<User#{}>: {}", my_id, msg);
-
-    //
-    // HELP 2 - here I need to send commands to the ws API based on users' commands
-    //          and process it's result, for example a command to create a room:
-    //
-    //          // example:
-    //          if msg == "createroom/room_id" {
-    //              result = wsclient_createroom(room_id);
-    //          }
-    //
-    //          // or a command to kick another user, example:
-    //          if msg == "kick/user_id" {
-    //              result = wsclient_kick(user_id);
-    //          }
-    // 
-
-
-    // New message from this user, send it to everyone else (except same uid)...
-    for (&uid, tx) in users.read().await.iter() {
-        if my_id != uid {
-            if let Err(_disconnected) = tx.send(Ok(Message::text(new_msg.clone()))) {
-                // The tx is disconnected, our `user_disconnected` code
-                // should be happening in another task, nothing more to
-                // do here.
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config, cli.no_janus).await,
+        Command::CheckConfig => println!("{:#?}", config),
+        Command::JanusInfo => {
+            if !janus_info(&config).await {
+                std::process::exit(1);
             }
         }
     }
 }
 
-async fn user_disconnected(my_id: usize, users: &Users) {
-    eprintln!("good bye user: {}", my_id);
-
-    // Stream closed up, so remove from the user list
-    users.write().await.remove(&my_id);
+/// The Janus client of `config`, which does not connect with `no_janus`.
+fn janus_client(config: &Config, no_janus: bool) -> janus::JanusClient {
+    let builder = janus::JanusClient::builder()
+        .url(config.janus.url.clone())
+        .auth(config.janus.auth())
+        // Chat users spamming commands must not flood the gateway.
+        .rate_limit(config.janus.rate_limit())
+        .configure(|client| {
+            client.keepalive_interval = config.janus.keepalive_interval();
+            client.reconnect_delay = config.janus.reconnect_delay();
+        });
+    if no_janus {
+        builder.offline()
+    } else {
+        builder.spawn()
+    }
 }
 
-static INDEX_HTML: &str = r#"<!DOCTYPE html>
-<html lang="en">
-    <head>
-        <title>Warp Chat</title>
-    </head>
-    <body>
-        <h1>Warp chat</h1>
-        <div id="chat">
-            <p><em>Connecting...</em></p>
-        </div>
-        <input type="text" id="text" />
-        <button type="button" id="send">Send</button>
-        <script type="text/javascript">
-        const chat = document.getElementById('chat');
-        const text = document.getElementById('text');
-        const uri = 'ws://' + location.host + '/chat';
-        const ws = new WebSocket(uri);
-
-        function message(data) {
-            const line = document.createElement('p');
-            line.innerText = data;
-            chat.appendChild(line);
+/// Prints what the gateway of `config` tells about itself, and tells
+/// whether it could be reached.
+async fn janus_info(config: &Config) -> bool {
+    let janus = janus_client(config, false);
+    let timeout = std::time::Duration::from_secs(10);
+    let ready = tokio::time::timeout(timeout, janus.ready()).await;
+    let reached = match (ready, janus.info()) {
+        (Ok(Ok(())), Some(info)) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&info).unwrap_or_default()
+            );
+            true
         }
-
-        ws.onopen = function() {
-            chat.innerHTML = '<p><em>Connected!</em></p>';
-        };
-
-        ws.onmessage = function(msg) {
-            message(msg.data);
-        };
-
-        ws.onclose = function() {
-            chat.getElementsByTagName('em')[0].innerText = 'Disconnected!';
-        };
-
-        send.onclick = function() {
-            const msg = text.value;
-            ws.send(msg);
-            text.value = '';
-
-            message('<You>: ' + msg);
-        };
-        </script>
-    </body>
-</html>
-"#;
-
-
-
-fn _wsclient_connect() {
-
-    // 1. we need to connect to the websocket API at ws://127.0.0.1:8188/janus (with header "Sec-WebSocket-Protocol: janus-protocol")
-    //    and create some kind of queue to process commands sent by the program
-    //
-    // 2. after we connect, we need to create a session
-    //
-    //    session_id = wsclient_createsession();
-    //
-    // 3. after getting the session_id, we need to create a handle
-    //
-    //    handle_id = wsclient_createhandle();
-    //
-    // 4. every 30 seconds, we need to send a keepalive command so our connection to API ws won't be dropped
-    //
-    //    wsclient_keepalive();
-    //
-    // 5. sometimes the ws API sends events (json messages) so we need a function to process these events
-    //
-    //    wsclient_processevent(event);
-    //
-    // 6. if the connection to the ws API fails or drops, we should repeat steps 1-2-3 again
-    //
-
-
-}
-
-// example createsession
-fn _wsclient_createsession() {
-
-    // we need to send this command:
-    // {"janus":"create", "apisecret":"api_secret4321", "transaction":"Qs6uJ7jODoJR"}
-
-    // API should reply a json with success such as:
-    // {    "janus": "success",    "transaction": "Qs6uJ7jODoJR",    "data": {       "id": 2147901755134278    } }
-    // or an error:
-    // {    "janus": "error",    "transaction": "Qs6uJ7jODoJR",    "error": {       "code": 403,       "reason": "Unauthorized request (wrong or missing secret/token)"    } }
-
-    // we return the id
-    // return id;
-
+        (Ok(Err(e)), _) => {
+            eprintln!("janus at {} could not be reached: {}", config.janus.url, e);
+            false
+        }
+        _ => {
+            eprintln!("janus at {} could not be reached in time", config.janus.url);
+            false
+        }
+    };
+    janus.shutdown().await;
+    reached
 }
 
-// example createhandle
-fn _wsclient_createhandle() {
-
-    // we need to send this command (with stored session_id):
-    // {"janus":"attach", "apisecret":"api_secret4321", "plugin":"janus.plugin.videoroom", "transaction":"tfycla3QP7IR", "session_id": 2147901755134278 }
-
-    // API should reply a json with success such as: (or an error)
-    // {    "janus": "success",    "session_id": 2147901755134278,    "transaction": "tfycla3QP7IR",    "data": {       "id": 5256079589400739    } }
-
-    // we return the id
-    // return id;
-}
+/// Serves the chat with `config` until we are asked to stop, without
+/// connecting to the gateway with `no_janus`.
+async fn serve(config: Config, no_janus: bool) {
+    // Keep track of all connected users, key is usize, value
+    // is a websocket sender.
+    let users = chat::Users::default();
+
+    // Keep our connection to the Janus API running next to the warp server.
+    let janus = janus_client(&config, no_janus);
+    janus.register_handler(videoroom::PublisherLog);
+    janus.register_handler(chat::TrickleRelay::new(users.clone(), janus.clone()));
+    janus.register_handler(chat::SipRelay::new(users.clone(), janus.clone()));
+    janus.register_handler(chat::RecordPlayRelay::new(users.clone(), janus.clone()));
+    janus.register_handler(chat::VideoCallRelay::new(users.clone(), janus.clone()));
+    janus.register_handler(chat::PluginEventRelay::new(users.clone(), janus.clone()));
+    let identities = server::identities::Identities::default();
+    janus.register_handler(chat::TalkingRelay::new(
+        users.clone(),
+        janus.clone(),
+        identities.clone(),
+    ));
+
+    // Let operators look into the gateway through its Admin API.
+    let admin_config = janus::AdminConfig {
+        url: config.janus.admin_url.clone(),
+        admin_secret: config.janus.admin_secret.clone(),
+        reconnect_delay: config.janus.reconnect_delay(),
+        ..janus::AdminConfig::default()
+    };
+    let admin = if no_janus {
+        janus::AdminClient::offline(admin_config)
+    } else {
+        janus::AdminClient::spawn(admin_config)
+    };
 
-// example keepalive
-fn _wsclient_keepalive() {
-    
-    // we need to send this command (with stored session_id)
-    // {"janus":"keepalive","apisecret":"api_secret4321", "session_id":2147901755134278, "transaction":"N7vgphoNxsNv"}
+    let secrets = videoroom::RoomSecrets::load(&config.janus.room_secrets);
+    let state = server::State {
+        users,
+        janus: janus.clone(),
+        forwarders: videoroom::Forwarders::default(),
+        recordings: videoroom::Recordings::default(),
+        secrets: secrets.clone(),
+        subscriptions: chat::Subscriptions::default(),
+        router: server::router::RoomRouter::default(),
+        identities,
+        textroom_users: chat::TextRoomUsers::default(),
+        provisioner: server::provision::Provisioner::new(
+            janus.clone(),
+            secrets,
+            config.rooms.capacity,
+            config.janus.admin_key.clone(),
+        ),
+        room_id: config.janus.room,
+        admin_key: config.janus.admin_key.clone(),
+        room_secret: config.janus.room_secret.clone(),
+        textroom_secret: config.janus.textroom_secret.clone(),
+        custom_plugins: config.janus.custom_plugins.clone(),
+        recording_filename: config.janus.recording_filename.clone(),
+        ban_minutes: config.chat.ban_minutes,
+        history: server::history::History::new(config.rooms.history),
+        default_role: config.auth.default_role,
+        auth: config.auth.chat_auth(),
+        bans: server::bans::Bans::load(&config.chat.bans),
+        mutes: server::mutes::Mutes::load(&config.chat.mutes),
+        invites: server::invites::Invites::default(),
+        rate_limit: config.limits.rate_limit(),
+        signal_rate_limit: config.limits.signal_rate_limit(),
+        size_limits: config.limits.size_limits(),
+        filters: config.chat.filters(),
+        files: config.limits.files(),
+        keepalive: config.chat.keepalive(),
+        sessions: server::sessions::Sessions::new(config.chat.resume_window()),
+        #[cfg(feature = "sqlite")]
+        store: open_store(&config.store),
+    };
+    janus.register_handler(chat::RosterRelay::new(
+        state.users.clone(),
+        videoroom::Roster::default(),
+        state.router.clone(),
+        state.identities.clone(),
+    ));
+    let users = state.users.clone();
+    let routes = server::routes(state, admin.clone());
+
+    // Chat users are told the server goes away, instead of losing it.
+    let shutdown = async move {
+        shutdown_signal().await;
+        chat::close_all(&users, chat::GOING_AWAY, "server shutting down").await;
+    };
 
-    // API should reply a json with ACK
-    // {    "janus": "ack",    "session_id": 2147901755134278,    "transaction": "N7vgphoNxsNv" }
+    let (_, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(config.server.listen, shutdown);
+    server.await;
 
+    // Leave no orphan sessions behind on the gateway.
+    janus.shutdown().await;
+    admin.shutdown().await;
 }
 
-// example createroom
-fn _wsclient_createroom(_room_id: usize) {
-    
-    // we need to send this command (with stored session_id, handle_id and provided room_id)
-    // {"janus":"message", "apisecret":"api_secret4321", "body":{"request":"create", "room": 5555, "admin_key":"admin_key4321"}, "transaction":"zbNqFi0VxiWu", "session_id": 2147901755134278, "handle_id": 5256079589400739 }
-
-    // API should reply a json with success
-    // {    "janus": "success",    "session_id": 2147901755134278,    "transaction": "zbNqFi0VxiWu",    "sender": 1574010579734643,    "plugindata": {       "plugin": "janus.plugin.videoroom",       "data": {          "videoroom": "created",          "room": 5555,          "permanent": false       }    } }
-    //
-    // or an error:
-    // {    "janus": "success",    "session_id": 2147901755134278,    "transaction": "zbNqFi0VxiWu",    "sender": 1574010579734643,    "plugindata": {       "plugin": "janus.plugin.videoroom",       "data": {          "videoroom": "event",          "error_code": 429,          "error": "Missing mandatory element (admin_key)"       }    } }
+/// The database of `db` of `config` keeping every chat message, if it is
+/// set, which forgets the messages older than `retention_days` every hour.
+#[cfg(feature = "sqlite")]
+fn open_store(config: &config::StoreConfig) -> Option<server::store::Store> {
+    let path = config.db.as_ref()?;
+    let store = match server::store::Store::open(path, config.retention()) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("chat store {} could not be opened: {}", path.display(), e);
+            return None;
+        }
+    };
 
+    let pruned = store.clone();
+    tokio::spawn(async move {
+        let mut hourly = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            hourly.tick().await;
+            if let Err(e) = pruned.prune().await {
+                eprintln!("chat store could not be pruned: {}", e);
+            }
+        }
+    });
+    Some(store)
 }
 
-// example kick command
-fn _wsclient_kick(_user_id: usize) {
-    
-    // we need to send this command (with stored session_id, handle_id and provided user_id)
-    // {"janus":"message","apisecret":"api_secret4321", "body":{"request":"kick", "room": 5555, "secret": "adminpwd", "id": 7295162779679030},"transaction":"MdPPmzvt2HQA","session_id": 2147901755134278 ,"handle_id": 5256079589400739 }
+/// Resolves once we are asked to stop, with Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
 
-    // API should reply a json with success (or error)
-    // {    "janus": "success",    "session_id": 2175572209542756,    "transaction": "MdPPmzvt2HQA",    "sender": 813487213683777,    "plugindata": {       "plugin": "janus.plugin.videoroom",       "data": {          "videoroom": "success"       }    } }
-    
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+    eprintln!("shutting down");
 }
-
-// example processevent
-fn _wsclient_processevent(_event: String) {
-    
-    // we need to process this received event from the ws API
-
-    // {   "janus": "event",   "session_id": 4875230564143493,   "sender": 6705816660265647,   "plugindata": {      "plugin": "janus.plugin.videoroom",      "data": {         "videoroom": "event",         "room": 1234,         "publishers": [            {               "id": 6450855227982898,               "display": "aluno/3",               "audio_codec": "opus",               "video_codec": "vp8",               "talking": false            }         ]      }   }}
-
-}
\ No newline at end of file
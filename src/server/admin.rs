@@ -0,0 +1,247 @@
+//! The routes that let operators look into the gateway through its Admin
+//! API.
+
+use std::convert::Infallible;
+
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::Filter;
+
+use super::chat::{self, Users};
+use super::protocol::Outbound;
+use crate::janus::streaming::{self, CreateMountpoint, EditMountpoint};
+use crate::janus::videoroom::{self, EditRoom, Forwarders, RtpForward};
+use crate::janus::{self, AdminClient, JanusClient};
+
+/// The routes under `/admin`. They only exist for callers sending the admin
+/// secret of `admin` in `x-admin-secret`.
+pub fn routes(
+    admin: AdminClient,
+    janus: JanusClient,
+    forwarders: Forwarders,
+    users: Users,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let admin = warp::path("admin")
+        .and(warp::header::optional::<String>("x-admin-secret"))
+        .and(warp::any().map(move || admin.clone()))
+        .and_then(|secret: Option<String>, admin: AdminClient| async move {
+            match &admin.config().admin_secret {
+                Some(admin_secret) if secret.as_ref() == Some(admin_secret) => Ok(admin),
+                _ => Err(warp::reject::not_found()),
+            }
+        });
+
+    // GET /admin/sessions -> ids of the sessions on the gateway
+    let admin_sessions = admin
+        .clone()
+        .and(warp::path!("sessions"))
+        .and(warp::get())
+        .and_then(|admin: AdminClient| async move { admin_reply(admin.list_sessions().await) });
+    // GET /admin/sessions/:session_id/handles -> ids of the session's handles
+    let admin_handles = admin
+        .clone()
+        .and(warp::path!("sessions" / u64 / "handles"))
+        .and(warp::get())
+        .and_then(|admin: AdminClient, session_id| async move {
+            admin_reply(admin.list_handles(session_id).await)
+        });
+    // GET /admin/sessions/:session_id/handles/:handle_id -> handle info
+    let admin_handle_info = admin
+        .clone()
+        .and(warp::path!("sessions" / u64 / "handles" / u64))
+        .and(warp::get())
+        .and_then(|admin: AdminClient, session_id, handle_id| async move {
+            admin_reply(admin.handle_info(session_id, handle_id).await)
+        });
+    // POST /admin/log_level/:level -> changes the gateway's log level
+    let admin_log_level = admin
+        .clone()
+        .and(warp::path!("log_level" / u8))
+        .and(warp::post())
+        .and_then(|admin: AdminClient, level| async move {
+            admin_reply(admin.set_log_level(level).await)
+        });
+
+    // POST /admin/rooms/:room_id -> edits a videoroom, with the settings of
+    // `EditRoom` such as {"secret": "...", "new_description": "..."}
+    let janus = warp::any().map(move || janus.clone());
+    let admin_edit_room = admin
+        .clone()
+        .and(warp::path!("rooms" / u64))
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(janus.clone())
+        .and_then(
+            |_: AdminClient, room_id, mut edit: EditRoom, janus: JanusClient| async move {
+                edit.room = room_id;
+                admin_reply(videoroom::edit_room(&janus, &edit).await.map(|()| room_id))
+            },
+        );
+
+    // POST /admin/rooms/:room_id/forwarders -> forwards the streams of a
+    // publisher over RTP, with the settings of `RtpForward` such as
+    // {"publisher_id": 42, "host": "10.0.0.5", "streams": [{"mid": "0", "port": 5004}]}
+    let forwarders = warp::any().map(move || forwarders.clone());
+    let admin_rtp_forward = admin
+        .clone()
+        .and(warp::path!("rooms" / u64 / "forwarders"))
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(janus.clone())
+        .and(forwarders.clone())
+        .and_then(
+            |_: AdminClient,
+             room_id,
+             mut forward: RtpForward,
+             janus: JanusClient,
+             forwarders: Forwarders| async move {
+                forward.room = room_id;
+                admin_reply(forwarders.forward(&janus, &forward).await)
+            },
+        );
+    // GET /admin/rooms/:room_id/forwarders -> the forwarders of every
+    // publisher in a videoroom, with ?secret=... for a room that has one
+    let admin_list_forwarders = admin
+        .clone()
+        .and(warp::path!("rooms" / u64 / "forwarders"))
+        .and(warp::get())
+        .and(warp::query::<RoomSecret>())
+        .and(janus.clone())
+        .and_then(
+            |_: AdminClient, room_id, query: RoomSecret, janus: JanusClient| async move {
+                let secret = query.secret.as_deref();
+                admin_reply(videoroom::list_forwarders(&janus, room_id, secret).await)
+            },
+        );
+    // DELETE /admin/rooms/:room_id/forwarders/:publisher_id/:stream_id ->
+    // stops a forwarder, with ?secret=... for a room that has one
+    let admin_stop_rtp_forward = admin
+        .clone()
+        .and(warp::path!("rooms" / u64 / "forwarders" / u64 / u64))
+        .and(warp::delete())
+        .and(warp::query::<RoomSecret>())
+        .and(janus.clone())
+        .and(forwarders)
+        .and_then(
+            |_: AdminClient,
+             room_id,
+             publisher_id,
+             stream_id,
+             query: RoomSecret,
+             janus: JanusClient,
+             forwarders: Forwarders| async move {
+                let secret = query.secret.as_deref();
+                let stopped = forwarders
+                    .stop(&janus, room_id, secret, publisher_id, stream_id)
+                    .await;
+                admin_reply(stopped.map(|()| stream_id))
+            },
+        );
+
+    // POST /admin/mountpoints -> creates a mountpoint of the Streaming
+    // plugin, with the settings of `CreateMountpoint` such as
+    // {"type": "rtp", "description": "...", "media": [{"type": "video", "mid": "v", "port": 5004, "pt": 96, "codec": "vp8"}]}
+    let admin_create_mountpoint = admin
+        .clone()
+        .and(warp::path!("mountpoints"))
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(janus.clone())
+        .and_then(
+            |_: AdminClient, mountpoint: CreateMountpoint, janus: JanusClient| async move {
+                admin_reply(streaming::create_mountpoint(&janus, &mountpoint).await)
+            },
+        );
+    // POST /admin/mountpoints/:id -> edits a mountpoint, with the settings
+    // of `EditMountpoint` such as {"secret": "...", "new_description": "..."}
+    let admin_edit_mountpoint = admin
+        .clone()
+        .and(warp::path!("mountpoints" / u64))
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(janus.clone())
+        .and_then(
+            |_: AdminClient, id, mut edit: EditMountpoint, janus: JanusClient| async move {
+                edit.id = id;
+                admin_reply(streaming::edit_mountpoint(&janus, &edit).await.map(|()| id))
+            },
+        );
+    // DELETE /admin/mountpoints/:id -> destroys a mountpoint, with
+    // ?secret=... for one that has one
+    let admin_destroy_mountpoint = admin
+        .clone()
+        .and(warp::path!("mountpoints" / u64))
+        .and(warp::delete())
+        .and(warp::query::<RoomSecret>())
+        .and(janus)
+        .and_then(
+            |_: AdminClient, id, query: RoomSecret, janus: JanusClient| async move {
+                let secret = query.secret.as_deref();
+                let destroyed = streaming::destroy_mountpoint(&janus, id, secret, false).await;
+                admin_reply(destroyed.map(|()| id))
+            },
+        );
+
+    // POST /admin/announce -> sends an announcement to every chat user, or
+    // to those of one chat room, such as
+    // {"text": "restarting at noon", "room": "lobby"}, and tells how many
+    // got it
+    let users = warp::any().map(move || users.clone());
+    let admin_announce = admin
+        .and(warp::path!("announce"))
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(users)
+        .and_then(|_: AdminClient, body: Announce, users: Users| async move {
+            let announcement = Outbound::Announcement {
+                text: body.text,
+                room: body.room.clone(),
+                from: None,
+            };
+            let told = chat::announce(&users, body.room.as_deref(), &announcement).await;
+            Ok::<_, Infallible>(warp::reply::json(&json!({ "delivered": told })))
+        });
+
+    admin_sessions
+        .or(admin_handles)
+        .or(admin_handle_info)
+        .or(admin_log_level)
+        .or(admin_edit_room)
+        .or(admin_rtp_forward)
+        .or(admin_list_forwarders)
+        .or(admin_stop_rtp_forward)
+        .or(admin_create_mountpoint)
+        .or(admin_edit_mountpoint)
+        .or(admin_destroy_mountpoint)
+        .or(admin_announce)
+}
+
+/// What `POST /admin/announce` sends, to everyone without a `room`.
+#[derive(Deserialize)]
+struct Announce {
+    text: String,
+    room: Option<String>,
+}
+
+/// The secret of a videoroom or mountpoint, for the routes that take it in
+/// the query string.
+#[derive(Deserialize)]
+struct RoomSecret {
+    secret: Option<String>,
+}
+
+/// Answers an admin route with what the Admin API returned, or with the
+/// error it failed with.
+fn admin_reply<T: serde::Serialize>(
+    result: janus::Result<T>,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, Infallible> {
+    let reply = match result {
+        Ok(value) => warp::reply::with_status(warp::reply::json(&value), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&json!({ "error": e.to_string() })),
+            StatusCode::BAD_GATEWAY,
+        ),
+    };
+    Ok(reply)
+}
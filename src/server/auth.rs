@@ -0,0 +1,134 @@
+//! Who may chat: with the `tokens` of `[auth]` in the config, a websocket
+//! only gets into its chat room once it showed one of them, either in the
+//! query string of the upgrade, as `?token=...`, or in its first message,
+//! as {"type": "auth", "token": "..."}. Those that do not within `GRACE`
+//! are closed with `AUTH_FAILED`.
+//!
+//! With its `jwt_secret` or `jwt_public_key` instead, the token is a JWT
+//! signed with HS256 or RS256, whose claims tell who the user is: `sub`,
+//! `name` and `roles`, see `Credentials`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// How long a websocket has to show its token.
+pub const GRACE: Duration = Duration::from_secs(10);
+
+/// The close code of the websockets that failed to show a token.
+pub const AUTH_FAILED: u16 = 4001;
+
+/// How chat users authenticate.
+///
+/// The tokens are never printed, `Debug` only shows which kind of
+/// authentication is used.
+#[derive(Clone, Default)]
+pub enum ChatAuth {
+    /// Anyone may chat.
+    #[default]
+    None,
+    /// Those with one of the tokens.
+    Tokens(HashSet<String>),
+    /// JWTs, checked with a shared secret or a public key.
+    Jwt(JwtKey),
+}
+
+/// How JWTs are checked.
+#[derive(Clone)]
+pub struct JwtKey {
+    key: DecodingKey<'static>,
+    validation: Validation,
+}
+
+/// Who a chat user is, as their JWT tells. Users of the other kinds of
+/// authentication have none of it.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Credentials {
+    /// Their id with whoever issued the token.
+    #[serde(default, rename = "sub")]
+    pub subject: Option<String>,
+    /// Their display name.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl ChatAuth {
+    /// With the shared secret of HS256 JWTs `jwt_secret`, the file with the
+    /// PEM public key of RS256 JWTs `jwt_public_key`, or else `tokens`,
+    /// letting anyone in without any of them.
+    ///
+    /// Nobody gets in when the public key cannot be read.
+    pub fn new(
+        jwt_secret: Option<&str>,
+        jwt_public_key: Option<&Path>,
+        tokens: &[String],
+    ) -> ChatAuth {
+        if let Some(secret) = jwt_secret {
+            return ChatAuth::Jwt(JwtKey {
+                key: DecodingKey::from_secret(secret.as_bytes()).into_static(),
+                validation: Validation::new(Algorithm::HS256),
+            });
+        }
+        if let Some(path) = jwt_public_key {
+            let key = fs::read(path).map_err(|e| e.to_string()).and_then(|pem| {
+                DecodingKey::from_rsa_pem(&pem)
+                    .map(DecodingKey::into_static)
+                    .map_err(|e| e.to_string())
+            });
+            return match key {
+                Ok(key) => ChatAuth::Jwt(JwtKey {
+                    key,
+                    validation: Validation::new(Algorithm::RS256),
+                }),
+                Err(e) => {
+                    eprintln!("JWT public key {} could not be read: {}", path.display(), e);
+                    ChatAuth::Tokens(HashSet::new())
+                }
+            };
+        }
+        if tokens.is_empty() {
+            ChatAuth::None
+        } else {
+            ChatAuth::Tokens(tokens.iter().cloned().collect())
+        }
+    }
+
+    /// Who `token` tells its websocket is, if it lets it in.
+    pub fn check(&self, token: Option<&str>) -> Option<Credentials> {
+        match self {
+            ChatAuth::None => Some(Credentials::default()),
+            ChatAuth::Tokens(tokens) => token
+                .filter(|token| tokens.contains(*token))
+                .map(|_| Credentials::default()),
+            ChatAuth::Jwt(JwtKey { key, validation }) => {
+                let decoded = jsonwebtoken::decode::<Credentials>(token?, key, validation);
+                match decoded {
+                    Ok(decoded) => Some(decoded.claims),
+                    Err(e) => {
+                        eprintln!("JWT refused: {}", e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ChatAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatAuth::None => f.write_str("None"),
+            ChatAuth::Tokens(_) => f.write_str("Tokens(..)"),
+            ChatAuth::Jwt(JwtKey { validation, .. }) => {
+                write!(f, "Jwt({:?})", validation.algorithms)
+            }
+        }
+    }
+}
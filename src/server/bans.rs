@@ -0,0 +1,102 @@
+//! The chat users who may not come back for a while: their address, and
+//! the subject of their JWT if they have one, are banned until a given
+//! time, saved to a JSON file so that the bans outlive a restart.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+/// The close code of the websockets of banned users.
+pub const BANNED: u16 = 4003;
+
+/// What a ban of address `addr` is kept under.
+pub fn of_addr(addr: IpAddr) -> String {
+    format!("ip:{}", addr)
+}
+
+/// What a ban of the JWT subject `subject` is kept under.
+pub fn of_subject(subject: &str) -> String {
+    format!("sub:{}", subject)
+}
+
+/// The bans, saved to a JSON file after every change.
+#[derive(Clone)]
+pub struct Bans {
+    path: PathBuf,
+    /// When the ban of every address or subject ends, in seconds since the
+    /// Unix epoch.
+    banned: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl Bans {
+    /// The bans saved to `path`, if any.
+    pub fn load(path: impl Into<PathBuf>) -> Bans {
+        let path = path.into();
+        let banned = match fs::read(&path) {
+            Ok(saved) => serde_json::from_slice(&saved).unwrap_or_else(|e| {
+                eprintln!("chat bans in {} ignored: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Bans {
+            path,
+            banned: Arc::new(Mutex::new(banned)),
+        }
+    }
+
+    /// Bans `who`, of `of_addr` or `of_subject`, for `duration`.
+    pub fn ban(&self, who: &str, duration: Duration) {
+        let until = now_secs().saturating_add(duration.as_secs());
+        let mut banned = self.banned.lock().unwrap();
+        banned.insert(who.to_string(), until);
+        self.save(&banned);
+    }
+
+    /// Lifts the ban of `who`, and tells whether there was one.
+    pub fn unban(&self, who: &str) -> bool {
+        let mut banned = self.banned.lock().unwrap();
+        let lifted = banned.remove(who).is_some();
+        if lifted {
+            self.save(&banned);
+        }
+        lifted
+    }
+
+    /// Whether `who` is banned, forgetting the bans that are over.
+    pub fn is_banned(&self, who: &str) -> bool {
+        let mut banned = self.banned.lock().unwrap();
+        match banned.get(who) {
+            Some(&until) if until > now_secs() => true,
+            Some(_) => {
+                banned.remove(who);
+                self.save(&banned);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn save(&self, banned: &HashMap<String, u64>) {
+        let saved = fs::write(&self.path, json!(banned).to_string());
+        if let Err(e) = saved {
+            eprintln!(
+                "chat bans could not be saved to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
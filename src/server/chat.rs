@@ -0,0 +1,2401 @@
+//! The chat: every text a user sends goes to all the other users in their
+//! chat room, while the WebRTC signaling of their browser goes to a Janus
+//! handle of their own.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::{FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::{mpsc, RwLock};
+use tracing::Instrument;
+use warp::ws::{Message, WebSocket};
+use warp::Filter;
+
+use super::identities::{Identities, Identity};
+use super::invites::NOT_INVITED;
+use super::limits;
+use super::mutes::{self, Mutes};
+use super::profiles::{Field, Profile};
+use super::protocol::{self, Inbound, Outbound, Protocol};
+use super::provision::ROOM_FULL;
+use super::ratelimit::{self, Verdict};
+use super::roles::{self, Role};
+use super::router::RoomRouter;
+use super::sessions::Parked;
+use super::{auth, bans};
+use super::{commands, State};
+use crate::janus::videoroom::AllowedAction;
+use crate::janus::{self, nosip, recordplay, sip, streaming, textroom, videocall, videoroom};
+
+/// The close code of the websockets closed as the server shuts down, or
+/// as they stopped answering pings.
+///
+/// The others are `auth::AUTH_FAILED`, `bans::BANNED`, the one of kicks,
+/// `provision::ROOM_FULL`, `invites::NOT_INVITED`,
+/// `protocol::UNSUPPORTED_VERSION`, `ratelimit::POLICY_VIOLATION` and
+/// `limits::MESSAGE_TOO_BIG`, each with a reason such as "banned" along.
+pub const GOING_AWAY: u16 = 1001;
+
+/// Our global unique user id counter.
+static NEXT_USER_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The sender of `warp::ws::Message` to the websocket of a user.
+pub type UserTx = mpsc::UnboundedSender<Result<Message, warp::Error>>;
+
+/// A user connected to a chat room.
+#[derive(Clone)]
+pub struct ChatUser {
+    pub tx: UserTx,
+    /// The nickname they go by, unique in their room.
+    pub nick: Option<String>,
+    /// When they connected.
+    pub since: SystemTime,
+    /// Who they proved to be.
+    pub credentials: auth::Credentials,
+    /// What they may do.
+    pub role: Role,
+    /// Where they connected from, if warp knows.
+    pub addr: Option<IpAddr>,
+    /// How the messages to them are written.
+    pub protocol: Protocol,
+    /// The sequence number of the last message of their room they read.
+    pub read: u64,
+    /// What they tell about themselves.
+    pub profile: Profile,
+}
+
+impl ChatUser {
+    /// Sends them `message`, written their way, unless it is of no use to
+    /// them.
+    pub fn deliver(&self, message: &Outbound) {
+        if let Some(text) = message.render(self.protocol) {
+            let _ = self.tx.send(Ok(Message::text(text)));
+        }
+    }
+}
+
+/// A chat user as the roster of their room tells about them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Member {
+    pub id: usize,
+    pub nick: Option<String>,
+    /// When they connected, in milliseconds since the Unix epoch.
+    pub connected_since: u64,
+    pub role: Role,
+    /// The sequence number of the last message of the room they read.
+    pub read: u64,
+    #[serde(skip_serializing_if = "Profile::is_empty")]
+    pub profile: Profile,
+}
+
+impl Member {
+    fn new(id: usize, user: &ChatUser) -> Member {
+        let since = user.since.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Member {
+            id,
+            nick: user.nick.clone(),
+            connected_since: since.as_millis() as u64,
+            role: user.role,
+            read: user.read,
+            profile: user.profile.clone(),
+        }
+    }
+}
+
+/// Our state of currently connected users, by chat room.
+///
+/// - Key is the name of the room
+/// - Value is the users in it, by id
+pub type Users = Arc<RwLock<HashMap<String, HashMap<usize, ChatUser>>>>;
+
+/// What a websocket upgrade into a chat room may ask for, such as
+/// `?nick=bob`, along with the `token` of `auth`, the version `v` of
+/// `protocol`, the sequence number of the last message seen `since`, the
+/// `resume` token of `sessions` and the `invite` of a private chat room.
+#[derive(Debug, Default, Deserialize)]
+struct JoinQuery {
+    nick: Option<String>,
+    token: Option<String>,
+    v: Option<u32>,
+    since: Option<u64>,
+    resume: Option<String>,
+    invite: Option<String>,
+}
+
+/// The streams of the subscription of every chat user who subscribed to
+/// videoroom feeds, by user id.
+pub type Subscriptions = Arc<RwLock<HashMap<usize, Vec<videoroom::SubscribedStream>>>>;
+
+/// The TextRoom every chat user who set up a data channel joined, by user
+/// id. Their chat goes over the data channel instead of the websocket.
+pub type TextRoomUsers = Arc<RwLock<HashMap<usize, u64>>>;
+
+/// `GET /` with the chat page and `GET /chat/<room>` with its websocket.
+pub fn routes(
+    state: State,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // GET /chat/<room> -> websocket upgrade into chat room `room`, and
+    // GET /chat into the one named after the videoroom of `janus.room`,
+    // with ?nick=bob for a nickname
+    let default_room = state.room_id.to_string();
+    let room = warp::path::param::<String>()
+        .and(warp::path::end())
+        .or(warp::path::end().map(move || default_room.clone()))
+        .unify();
+
+    // Turn our "state" into a new Filter...
+    let state = warp::any().map(move || state.clone());
+    let chat = warp::path("chat")
+        .and(room)
+        .and(warp::query::<JoinQuery>())
+        .and(warp::addr::remote())
+        // The `ws()` filter will prepare Websocket handshake...
+        .and(warp::ws())
+        .and(state)
+        .map(
+            |room: String,
+             query: JoinQuery,
+             addr: Option<SocketAddr>,
+             ws: warp::ws::Ws,
+             state: State| {
+                // Messages over the limit are not even read.
+                let limit = state.size_limits.message.max(state.files.max_size);
+                let ws = ws.max_message_size(limit).max_frame_size(limit);
+                // This will call our function if the handshake succeeds.
+                let addr = addr.map(|addr| addr.ip());
+                ws.on_upgrade(move |socket| user_connected(socket, room, query, addr, state))
+            },
+        );
+
+    // GET / -> index html
+    let index = warp::path::end().map(|| warp::reply::html(INDEX_HTML));
+
+    index.or(chat)
+}
+
+async fn user_connected(
+    ws: WebSocket,
+    room: String,
+    query: JoinQuery,
+    addr: Option<IpAddr>,
+    state: State,
+) {
+    // Split the socket into a sender and receive of messages.
+    let (user_ws_tx, mut user_ws_rx) = ws.split();
+
+    // Use an unbounded channel to handle buffering and flushing of messages
+    // to the websocket...
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::task::spawn(rx.forward(user_ws_tx).map(|result| {
+        if let Err(e) = result {
+            eprintln!("websocket send error: {}", e);
+        }
+    }));
+
+    // Clients asking for a version of the protocol we do not speak are
+    // told so, in the one we do.
+    let protocol = match Protocol::of(query.v) {
+        Ok(protocol) => protocol,
+        Err(refusal) => {
+            eprintln!("chat client refused: {}", refusal);
+            if let Some(text) = Outbound::error(refusal).render(Protocol::Json) {
+                let _ = tx.send(Ok(Message::text(text)));
+            }
+            let close = Message::close_with(protocol::UNSUPPORTED_VERSION, "unsupported version");
+            let _ = tx.send(Ok(close));
+            return;
+        }
+    };
+
+    // Users coming back with their resume token are who they were, others
+    // get a new unique ID from our counter.
+    let resumed = query
+        .resume
+        .as_deref()
+        .and_then(|token| state.sessions.resume(token, &room));
+    let my_id = match &resumed {
+        Some(parked) => parked.user_id,
+        None => NEXT_USER_ID.fetch_add(1, Ordering::Relaxed),
+    };
+
+    match resumed {
+        Some(_) => eprintln!("chat user {} is back in room {}", my_id, room),
+        None => eprintln!("new chat user: {} in room {}", my_id, room),
+    }
+
+    // Banned users do not get in, whether they come back from the same
+    // address or with the same identity.
+    let banned = |credentials: Option<&auth::Credentials>| {
+        let by_addr = addr.map(bans::of_addr);
+        let by_subject = credentials
+            .and_then(|credentials| credentials.subject.as_deref())
+            .map(bans::of_subject);
+        by_addr
+            .into_iter()
+            .chain(by_subject)
+            .any(|who| state.bans.is_banned(&who))
+    };
+    if banned(None) {
+        eprintln!("chat user {} is banned", my_id);
+        let _ = tx.send(Ok(Message::close_with(bans::BANNED, "banned")));
+        return;
+    }
+
+    // Nobody gets in without a token, when they are required, unless they
+    // proved who they are before.
+    let mut credentials = match &resumed {
+        Some(parked) => Some(parked.credentials.clone()),
+        None => state.auth.check(query.token.as_deref()),
+    };
+    if credentials.is_none() {
+        credentials = match tokio::time::timeout(auth::GRACE, user_ws_rx.next()).await {
+            Ok(Some(Ok(msg))) => {
+                let token = msg
+                    .to_str()
+                    .ok()
+                    .and_then(|msg| serde_json::from_str::<serde_json::Value>(msg).ok())
+                    .filter(|signal| signal["type"] == "auth")
+                    .and_then(|signal| signal["token"].as_str().map(String::from));
+                state.auth.check(token.as_deref())
+            }
+            _ => None,
+        };
+    }
+    let credentials = match credentials {
+        Some(credentials) => credentials,
+        None => {
+            eprintln!("chat user {} failed to authenticate", my_id);
+            let close = Message::close_with(auth::AUTH_FAILED, "authentication failed");
+            let _ = tx.send(Ok(close));
+            return;
+        }
+    };
+    if banned(Some(&credentials)) {
+        eprintln!("chat user {} is banned", my_id);
+        let _ = tx.send(Ok(Message::close_with(bans::BANNED, "banned")));
+        return;
+    }
+
+    // Private chat rooms only let in their owner, those with an invite and
+    // those who were in.
+    let identity = mutes::identity(my_id, &credentials);
+    if resumed.is_none()
+        && !state
+            .invites
+            .admit(&room, &identity, query.invite.as_deref())
+    {
+        eprintln!("chat user {} is not invited to room {}", my_id, room);
+        let refusal = Outbound::error(format!("room {} is invite only", room));
+        if let Some(text) = refusal.render(protocol) {
+            let _ = tx.send(Ok(Message::text(text)));
+        }
+        let _ = tx.send(Ok(Message::close_with(NOT_INVITED, "not invited")));
+        return;
+    }
+
+    // Save the sender in our list of connected users, with the nickname
+    // they asked for or had, or else the name of their token, unless it is
+    // taken or the room is full. They get the last messages of the room
+    // first, before any new one can come in, and the others of the room
+    // hear of them.
+    let pinger = tx.clone();
+    let (first, refused) = {
+        let mut users = state.users.write().await;
+        let capacity = state.provisioner.capacity(&room);
+        let present = users.get(&room).map_or(0, |members| members.len());
+        if let Some(capacity) = capacity.filter(|&capacity| present >= capacity) {
+            eprintln!("chat user {} turned away, room {} is full", my_id, room);
+            let full = format!("room {} is full (capacity {})", room, capacity);
+            if let Some(text) = Outbound::error(full).render(protocol) {
+                let _ = tx.send(Ok(Message::text(text)));
+            }
+            let _ = tx.send(Ok(Message::close_with(ROOM_FULL, "room full")));
+            return;
+        }
+        for message in state.history.replay(&room, query.since) {
+            if let Some(text) = message.render(protocol) {
+                let _ = tx.send(Ok(Message::text(text)));
+            }
+        }
+        let members = users.entry(room.clone()).or_default();
+        let nick = query
+            .nick
+            .or_else(|| resumed.as_ref().and_then(|parked| parked.nick.clone()))
+            .or_else(|| credentials.name.clone().filter(|name| valid_nick(name)));
+        let refused = nick
+            .clone()
+            .filter(|nick| !valid_nick(nick) || nick_taken(members, nick, my_id));
+        let nick = nick.filter(|_| refused.is_none());
+        let (role, read, profile) = match &resumed {
+            Some(parked) => (parked.role, parked.read, parked.profile.clone()),
+            None => (
+                Role::of(&credentials, state.default_role),
+                0,
+                Profile::default(),
+            ),
+        };
+        let user = ChatUser {
+            tx,
+            nick,
+            since: SystemTime::now(),
+            credentials,
+            role,
+            addr,
+            protocol,
+            read,
+            profile,
+        };
+        let event = if resumed.is_some() {
+            "resumed"
+        } else {
+            "joined"
+        };
+        announce_presence(members, &room, event, &Member::new(my_id, &user));
+        members.insert(my_id, user);
+        (members.len() == 1, refused)
+    };
+    if let Some(nick) = refused {
+        let refusal = Outbound::error(format!("you cannot go by {} here", nick));
+        deliver(&state.users, my_id, &refusal).await;
+    }
+
+    // They can come back as who they are with a token of their own.
+    let resume_token = commands::random_token(24);
+    if state.sessions.enabled() {
+        let text = format!(
+            "you are user {}, come back with ?resume={}",
+            my_id, resume_token
+        );
+        let session = Outbound::event("session", text).with_data(json!({
+            "user": my_id,
+            "resume": resume_token,
+            "seq": state.history.last_seq(&room),
+        }));
+        deliver(&state.users, my_id, &session).await;
+    }
+
+    // The first user of a chat room opens its videoroom, which only lets
+    // in the members of a private one.
+    if first {
+        if let Err(e) = state.provisioner.open(&room).await {
+            eprintln!("videoroom of chat room {} could not be opened: {}", room, e);
+        }
+        if state.invites.is_private(&room) {
+            room_acl(&state, &room, AllowedAction::Enable, &[]).await;
+        }
+    }
+    if let Some(token) = state.invites.join(&room, my_id) {
+        room_acl(&state, &room, AllowedAction::Add, &[token]).await;
+    }
+
+    // Return a `Future` that is basically a state machine managing
+    // this specific user's connection.
+
+    // Every time the user sends a message, broadcast it to
+    // all other users...
+    let mut messages = state.rate_limit.map(ratelimit::RateLimit::bucket);
+    let mut signals = state.signal_rate_limit.map(ratelimit::RateLimit::bucket);
+    let mut pings = tokio::time::interval(state.keepalive.interval);
+    let mut missed = 0;
+    loop {
+        // They are pinged now and then, and dropped when they stop
+        // answering, as their browser may be gone without a word.
+        let result = tokio::select! {
+            result = user_ws_rx.next() => result,
+            _ = pings.tick() => {
+                if missed >= state.keepalive.misses {
+                    eprintln!("chat user {} missed {} pongs", my_id, missed);
+                    close(&state.users, my_id, GOING_AWAY, "ping timeout").await;
+                    break;
+                }
+                missed += 1;
+                let _ = pinger.send(Ok(Message::ping(Vec::new())));
+                continue;
+            }
+        };
+        let result = match result {
+            Some(result) => result,
+            None => break,
+        };
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("websocket error(uid={}): {}", my_id, e);
+                // Such as a message over `limits::SizeLimits::message`.
+                if e.to_string().contains("Space limit exceeded") {
+                    let reason = "message too big";
+                    close(&state.users, my_id, limits::MESSAGE_TOO_BIG, reason).await;
+                }
+                break;
+            }
+        };
+        // Whatever they send shows they are still there.
+        missed = 0;
+        if msg.is_pong() {
+            continue;
+        }
+        // Kicked users are gone from the room already, whatever they
+        // send before their websocket is closed.
+        if !is_connected(&state.users, my_id).await {
+            break;
+        }
+        // Chat messages and commands too long are refused, and those sent
+        // too fast are dropped, with a warning until their sender is thrown
+        // out. So are files, and signaling, which has a bucket of its own.
+        let text = msg.to_str().ok();
+        let signal =
+            text.is_some_and(|text| matches!(roles::Action::of(text), roles::Action::Signal(_)));
+        if let Some(text) = text {
+            let limit = if signal {
+                state.size_limits.message
+            } else {
+                state.size_limits.text
+            };
+            if text.len() > limit {
+                let refusal = Outbound::error(format!(
+                    "your message is {} bytes long, at most {} are allowed",
+                    text.len(),
+                    limit
+                ))
+                .in_reply_to(Inbound::id_of(text));
+                deliver(&state.users, my_id, &refusal).await;
+                continue;
+            }
+        }
+        let bucket = if signal {
+            signals.as_mut()
+        } else {
+            messages.as_mut()
+        };
+        let limited = text.is_some() || msg.is_binary();
+        if let Some(bucket) = bucket.filter(|_| limited) {
+            match bucket.take() {
+                Verdict::Allowed => {}
+                Verdict::Dropped(left) => {
+                    let warning = Outbound::error(format!(
+                        "slow down, you may send {}; your message was dropped, {} more and you are out",
+                        bucket.limit(),
+                        left
+                    ))
+                    .in_reply_to(text.and_then(Inbound::id_of));
+                    deliver(&state.users, my_id, &warning).await;
+                    continue;
+                }
+                Verdict::Exceeded => {
+                    eprintln!("chat user {} sent too many messages", my_id);
+                    let reason = "too many messages";
+                    kick(&state.users, my_id, ratelimit::POLICY_VIOLATION, reason).await;
+                    break;
+                }
+            }
+        }
+        user_message(my_id, &room, msg, &state).await;
+    }
+
+    // user_ws_rx stream will keep processing as long as the user stays
+    // connected. Once they disconnect, then they may come back for a
+    // while, unless they were kicked, as they were when they left...
+    let parked = {
+        let users = state.users.read().await;
+        users
+            .get(&room)
+            .and_then(|members| members.get(&my_id))
+            .map(|user| Parked::new(my_id, room.clone(), user))
+    };
+    state.subscriptions.write().await.remove(&my_id);
+    state.router.leave(my_id);
+    state.identities.user_left(my_id);
+    state.textroom_users.write().await.remove(&my_id);
+    state.files.user_left(my_id);
+    if let Some(token) = state.invites.leave(&room, my_id) {
+        room_acl(&state, &room, AllowedAction::Remove, &[token]).await;
+    }
+    user_disconnected(my_id, &room, &state.users, &state.janus).await;
+
+    // ...once they and their handles are gone, so that whoever comes back
+    // with their token does not find them still there.
+    if let Some(parked) = parked {
+        state.sessions.park(resume_token, parked);
+    }
+
+    // The last user of a chat room closes its videoroom.
+    if !state.users.read().await.contains_key(&room) {
+        if let Err(e) = state.provisioner.close(&room, &state.users).await {
+            eprintln!("videoroom of chat room {} could not be closed: {}", room, e);
+        }
+    }
+}
+
+async fn user_message(my_id: usize, room: &str, msg: Message, state: &State) {
+    let users = &state.users;
+    let janus = &state.janus;
+
+    // Everyone may only do what their role lets them.
+    let role = role_of(users, my_id).await.unwrap_or(Role::Guest);
+
+    // Binary messages are files for the room, see `files`, and any other
+    // non-Text ones are skipped...
+    if msg.is_binary() {
+        if role < roles::BROADCAST {
+            let refusal = format!("you may not send files as a {}", role);
+            deliver(users, my_id, &Outbound::error(refusal)).await;
+            return;
+        }
+        file_message(my_id, room, msg.into_bytes(), state).await;
+        return;
+    }
+    let msg = if let Ok(s) = msg.to_str() {
+        s
+    } else {
+        return;
+    };
+
+    let action = roles::Action::of(msg);
+    if role < action.required() {
+        let refusal = format!("you may not {} as a {}", action, role);
+        match action {
+            roles::Action::Signal(_) => {
+                let refusal = json!({ "type": "error", "error": refusal });
+                send_to(users, my_id, refusal.to_string()).await;
+            }
+            _ => {
+                let refusal = Outbound::error(refusal).in_reply_to(Inbound::id_of(msg));
+                deliver(users, my_id, &refusal).await;
+            }
+        }
+        return;
+    }
+
+    // Chat messages and commands of the JSON protocol, see `protocol`.
+    match Inbound::parse(msg) {
+        Some(Inbound::Chat {
+            room: Some(other),
+            id,
+            ..
+        }) if other != room => {
+            let refusal = format!("you are in chat room {}, not {}", room, other);
+            deliver(users, my_id, &Outbound::error(refusal).in_reply_to(id)).await;
+            return;
+        }
+        Some(Inbound::Chat { body, id, .. }) => {
+            chat_message(my_id, room, &body, state).await;
+            if let Some(id) = id {
+                deliver(users, my_id, &Outbound::Ack { id }).await;
+            }
+            return;
+        }
+        Some(Inbound::Command { name, args, id }) => {
+            let reply = match commands::run(&name, &args, my_id, state).await {
+                Some(text) => Outbound::Reply {
+                    command: name,
+                    text,
+                    id: None,
+                },
+                None => Outbound::error(format!("no such command: {}", name)),
+            };
+            deliver(users, my_id, &reply.in_reply_to(id)).await;
+            return;
+        }
+        Some(Inbound::Read { seq }) => {
+            let seq = seq.min(state.history.last_seq(room));
+            if mark_read(users, my_id, seq).await {
+                let read = Outbound::Read {
+                    room: room.to_string(),
+                    user: my_id,
+                    seq,
+                };
+                broadcast_room(users, room, my_id, &read).await;
+            }
+            return;
+        }
+        Some(Inbound::History { since, id }) => {
+            let history = Outbound::History {
+                room: room.to_string(),
+                messages: state.history.replay(room, since),
+                id,
+            };
+            deliver(users, my_id, &history).await;
+            return;
+        }
+        None => {}
+    }
+
+    // The roster of the chat room is asked for with {"type": "users"},
+    // which is answered with
+    // {"type": "users", "room": "lobby", "users": [{"id": 3, "nick": "bob", "connected_since": 1588600931000, "role": "user", "read": 42, "profile": {...}}]}
+    //
+    // WebRTC signalling of the user's browser goes to the user's Janus
+    // handle instead of the other users:
+    //
+    // - plugin messages, with an offer or answer when negotiating, such as
+    //   {"type": "message", "body": {"request": "publish"}, "jsep": {"type": "offer", "sdp": "..."}}
+    //   which are answered with
+    //   {"type": "message", "data": {"videoroom": "event", ...}, "jsep": {"type": "answer", "sdp": "..."}}
+    //   or, with "plugin": "janus.plugin.custom", to a handle of one of the
+    //   plugins of `janus.custom_plugins`, whose events come back as they
+    //   are through `PluginEventRelay`
+    // - joining a videoroom and publishing in it, such as
+    //   {"type": "publish", "room": 1234, "display": "bob", "jsep": {"type": "offer", "sdp": "..."}}
+    //   with the "pin" or invitation "token" the room may take, the
+    //   videoroom of the chat room without a "room" and the chat name of
+    //   the user without a "display"
+    //   which is answered with
+    //   {"type": "published", "room": 1234, "id": 42, "publishers": [...], "jsep": {"type": "answer", "sdp": "..."}}
+    // - subscribing to the feeds of a videoroom, such as
+    //   {"type": "subscribe", "room": 1234, "feeds": [{"feed": 42}]}
+    //   and {"type": "unsubscribe", "feeds": [{"feed": 42}]}
+    //   which are answered with an offer to answer with "start"
+    //   {"type": "subscribed", "room": 1234, "streams": [...], "jsep": {"type": "offer", "sdp": "..."}}
+    //   {"type": "start", "jsep": {"type": "answer", "sdp": "..."}}
+    // - ICE candidates, such as
+    //   {"type": "trickle", "candidate": {"sdpMid": "0", "sdpMLineIndex": 0, "candidate": "..."}}
+    //   with "subscriber": true for the PeerConnection of the subscription,
+    //   "textroom": true for the one of the data channel, "stream": true
+    //   for the one of the mountpoint watched, "sip": true for the one of
+    //   SIP calls, "recordplay": true for the one of recordings,
+    //   "videocall": true for the one of calls between browsers and
+    //   "nosip": true for the one bridged to RTP, or the "plugin" of a
+    //   custom plugin
+    // - setting up a data channel for the chat, through the TextRoom plugin:
+    //   {"type": "textroom"}
+    //   which is answered with the gateway's offer
+    //   {"type": "textroom", "jsep": {"type": "offer", "sdp": "..."}}
+    //   and joining a room of the plugin with the answer to it
+    //   {"type": "textroom_join", "room": 1234, "display": "bob", "jsep": {"type": "answer", "sdp": "..."}}
+    //   which is answered with
+    //   {"type": "textroom_joined", "room": 1234, "username": "3", "participants": [...]}
+    //   after which chat messages come over the data channel
+    // - watching a mountpoint of the Streaming plugin, such as
+    //   {"type": "watch", "id": 1, "pin": "..."}
+    //   which is answered with the gateway's offer
+    //   {"type": "watching", "id": 1, "jsep": {"type": "offer", "sdp": "..."}}
+    //   to answer with "start" and "stream": true, and then
+    //   {"type": "pause"} and {"type": "unwatch"}
+    // - calling out through the SIP plugin, once registered with
+    //   {"type": "sip_register", "username": "sip:alice@example.com", "secret": "...", "proxy": "sip:example.com"}
+    //   such as
+    //   {"type": "sip_call", "uri": "sip:bob@example.com", "jsep": {"type": "offer", "sdp": "..."}}
+    //   and answering the calls it tells about with
+    //   {"type": "sip_accept", "jsep": {"type": "answer", "sdp": "..."}}
+    //   which are answered with {"type": "sip", "event": "calling"} and so
+    //   on, while how they turn out comes as the events of `SipRelay`
+    // - recording through the Record&Play plugin, such as
+    //   {"type": "record", "name": "my talk", "jsep": {"type": "offer", "sdp": "..."}}
+    //   which is answered with
+    //   {"type": "recording", "id": 1, "jsep": {"type": "answer", "sdp": "..."}}
+    //   and playing a recording back, such as
+    //   {"type": "play", "id": 1}
+    //   which is answered with the gateway's offer
+    //   {"type": "playing", "id": 1, "jsep": {"type": "offer", "sdp": "..."}}
+    //   to answer with "start" and "recordplay": true; both end with
+    //   {"type": "stop", "recordplay": true}
+    // - calls between two browsers through the VideoCall plugin, with a
+    //   username registered as
+    //   {"type": "videocall_register", "username": "alice"}
+    //   or else the user id, such as
+    //   {"type": "call", "username": "bob", "jsep": {"type": "offer", "sdp": "..."}}
+    //   which the `call/bob` command asks the browser for, and answering
+    //   the calls it tells about with
+    //   {"type": "videocall_accept", "jsep": {"type": "answer", "sdp": "..."}}
+    //   which are answered with {"type": "videocall", "event": "calling"}
+    //   and so on, while the other side comes as the events of
+    //   `VideoCallRelay`
+    // - bridging to an RTP endpoint through the NoSIP plugin, turning the
+    //   browser's offer or answer into the SDP for the endpoint with
+    //   {"type": "nosip_generate", "jsep": {"type": "offer", "sdp": "..."}, "srtp": "sdes_optional"}
+    //   which is answered with
+    //   {"type": "nosip", "event": "generated", "sdp": {"type": "offer", "sdp": "..."}}
+    //   and the endpoint's SDP into the gateway's one for the browser with
+    //   {"type": "nosip_process", "sdp": {"type": "answer", "sdp": "..."}}
+    //   which is answered with
+    //   {"type": "nosip", "event": "processed", "jsep": {"type": "answer", "sdp": "..."}}
+    //   until {"type": "nosip_hangup"}
+    if let Ok(mut signal) = serde_json::from_str::<serde_json::Value>(msg) {
+        if signal["room"].is_null()
+            && (signal["type"] == "publish" || signal["type"] == "subscribe")
+        {
+            if let Some(room_id) = state.provisioner.room_id(room) {
+                signal["room"] = json!(room_id);
+            }
+        }
+        if signal["token"].is_null()
+            && (signal["type"] == "publish" || signal["type"] == "subscribe")
+            && signal["room"].as_u64() == state.provisioner.room_id(room)
+        {
+            if let Some(token) = state.invites.media_token(room, my_id) {
+                signal["token"] = json!(token);
+            }
+        }
+        if signal["display"].is_null()
+            && (signal["type"] == "publish" || signal["type"] == "textroom_join")
+        {
+            signal["display"] = json!(name_of(users, my_id).await);
+        }
+        let span = tracing::info_span!("chat_signal", user = my_id, signal = %signal["type"]);
+        if signal["type"] == "users" {
+            let roster = roster(users, room).await;
+            let reply = json!({ "type": "users", "room": room, "users": roster });
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "message" {
+            let body = signal["body"].clone();
+            let jsep = signal.get("jsep").cloned();
+            let reply = match signal["plugin"].as_str() {
+                Some(plugin) => janus_custom_message(janus, &state.custom_plugins, my_id, plugin, body, jsep)
+                    .instrument(span)
+                    .await
+                    .map(|reply| json!({ "type": "message", "plugin": plugin, "data": reply.data, "jsep": reply.jsep })),
+                None => janus_message(janus, my_id, body, jsep)
+                    .instrument(span)
+                    .await
+                    .map(|reply| json!({ "type": "message", "data": reply.data, "jsep": reply.jsep })),
+            };
+            let reply =
+                reply.unwrap_or_else(|e| json!({ "type": "error", "error": e.to_string() }));
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "publish" {
+            let reply = janus_publish(janus, my_id, &signal).instrument(span).await;
+            let reply = match reply {
+                Ok(joined) => {
+                    state.router.join(joined.room, my_id);
+                    state.identities.joined(Identity {
+                        user_id: my_id,
+                        room: joined.room,
+                        janus_id: joined.id,
+                        display: signal["display"].as_str().map(str::to_string),
+                    });
+                    let mut reply = json!(joined);
+                    reply["type"] = json!("published");
+                    reply
+                }
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "subscribe" || signal["type"] == "unsubscribe" {
+            let reply = janus_subscribe(janus, my_id, &signal)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(subscription) => {
+                    state.router.join(subscription.room, my_id);
+                    let mut subscriptions = state.subscriptions.write().await;
+                    subscriptions.insert(my_id, subscription.streams.clone());
+                    let mut reply = json!(subscription);
+                    reply["type"] = json!("subscribed");
+                    reply
+                }
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "watch" {
+            let reply = match signal["id"].as_u64() {
+                Some(id) => {
+                    let pin = signal["pin"].as_str();
+                    streaming::watch(janus, &stream_handle(my_id), id, pin)
+                        .instrument(span)
+                        .await
+                        .map(|offer| json!({ "type": "watching", "id": id, "jsep": offer }))
+                }
+                None => Err(janus::Error::Unexpected("watch without an id".to_string())),
+            };
+            let reply =
+                reply.unwrap_or_else(|e| json!({ "type": "error", "error": e.to_string() }));
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "start" && signal["stream"] == true {
+            let answer = signal["jsep"].clone();
+            let reply = streaming::start(janus, &stream_handle(my_id), answer)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "started", "stream": true }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "pause" {
+            let reply = streaming::pause(janus, &stream_handle(my_id))
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "paused" }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "unwatch" {
+            let key = stream_handle(my_id);
+            let reply = async {
+                streaming::stop(janus, &key).await?;
+                janus.handles().detach(&key).await
+            }
+            .instrument(span)
+            .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "unwatched" }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "record" {
+            let reply = match (signal["name"].as_str(), signal.get("jsep")) {
+                (Some(name), Some(offer)) => {
+                    recordplay::record(janus, &recordplay_handle(my_id), name, offer.clone())
+                        .instrument(span)
+                        .await
+                }
+                _ => Err(janus::Error::Unexpected(
+                    "record without a name or an offer".to_string(),
+                )),
+            };
+            let reply = match reply {
+                Ok((id, answer)) => json!({ "type": "recording", "id": id, "jsep": answer }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "play" {
+            let reply = match signal["id"].as_u64() {
+                Some(id) => recordplay::play(janus, &recordplay_handle(my_id), id)
+                    .instrument(span)
+                    .await
+                    .map(|offer| json!({ "type": "playing", "id": id, "jsep": offer })),
+                None => Err(janus::Error::Unexpected("play without an id".to_string())),
+            };
+            let reply =
+                reply.unwrap_or_else(|e| json!({ "type": "error", "error": e.to_string() }));
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "start" && signal["recordplay"] == true {
+            let answer = signal["jsep"].clone();
+            let reply = recordplay::start(janus, &recordplay_handle(my_id), answer)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "started", "recordplay": true }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "stop" && signal["recordplay"] == true {
+            let reply = recordplay::stop(janus, &recordplay_handle(my_id))
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "stopped", "recordplay": true }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "start" {
+            let answer = signal["jsep"].clone();
+            let reply = videoroom::start(janus, &subscriber_handle(my_id), answer)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "started" }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "textroom" {
+            let reply = textroom::setup(janus, &textroom_handle(my_id))
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(offer) => json!({ "type": "textroom", "jsep": offer }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "textroom_join" {
+            let reply =
+                janus_textroom_join(janus, state.textroom_secret.as_deref(), my_id, &signal)
+                    .instrument(span)
+                    .await;
+            let reply = match reply {
+                Ok((join, participants)) => {
+                    let mut textroom_users = state.textroom_users.write().await;
+                    textroom_users.insert(my_id, join.room);
+                    json!({
+                        "type": "textroom_joined",
+                        "room": join.room,
+                        "username": join.username,
+                        "participants": participants,
+                    })
+                }
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "sip_register" {
+            let reply = async {
+                let register = serde_json::from_value(signal.clone())?;
+                sip::register(janus, &sip_handle(my_id), &register).await
+            }
+            .instrument(span)
+            .await;
+            send_to(users, my_id, sip_reply(reply, "registering")).await;
+            return;
+        }
+        if signal["type"] == "sip_call" {
+            let reply = match (signal["uri"].as_str(), signal.get("jsep")) {
+                (Some(uri), Some(offer)) => {
+                    sip::call(janus, &sip_handle(my_id), uri, offer.clone())
+                        .instrument(span)
+                        .await
+                }
+                _ => Err(janus::Error::Unexpected(
+                    "sip_call without an uri or an offer".to_string(),
+                )),
+            };
+            send_to(users, my_id, sip_reply(reply, "calling")).await;
+            return;
+        }
+        if signal["type"] == "sip_accept" {
+            let answer = signal["jsep"].clone();
+            let reply = sip::accept(janus, &sip_handle(my_id), answer)
+                .instrument(span)
+                .await;
+            send_to(users, my_id, sip_reply(reply, "accepting")).await;
+            return;
+        }
+        if signal["type"] == "nosip_generate" {
+            let reply = nosip::generate(
+                janus,
+                &nosip_handle(my_id),
+                signal["jsep"].clone(),
+                signal["srtp"].as_str(),
+            )
+            .instrument(span)
+            .await;
+            let reply = match reply {
+                Ok(sdp) => json!({ "type": "nosip", "event": "generated", "sdp": sdp }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "nosip_process" {
+            let reply = async {
+                let sdp = serde_json::from_value(signal["sdp"].clone())?;
+                nosip::process(janus, &nosip_handle(my_id), &sdp).await
+            }
+            .instrument(span)
+            .await;
+            let reply = match reply {
+                Ok(jsep) => json!({ "type": "nosip", "event": "processed", "jsep": jsep }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "nosip_hangup" {
+            let reply = nosip::hangup(janus, &nosip_handle(my_id))
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "nosip", "event": "hangingup" }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "videocall_register" {
+            let username = match signal["username"].as_str() {
+                Some(username) => username.to_string(),
+                None => my_id.to_string(),
+            };
+            let reply = videocall::register(janus, &videocall_handle(my_id), &username)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => {
+                    json!({ "type": "videocall", "event": "registered", "username": username })
+                }
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "call" {
+            let reply = match (signal["username"].as_str(), signal.get("jsep")) {
+                (Some(username), Some(offer)) => videocall::call(
+                    janus,
+                    &videocall_handle(my_id),
+                    username,
+                    offer.clone(),
+                )
+                .instrument(span)
+                .await
+                .map(|()| json!({ "type": "videocall", "event": "calling", "username": username })),
+                _ => Err(janus::Error::Unexpected(
+                    "call without a username or an offer".to_string(),
+                )),
+            };
+            let reply =
+                reply.unwrap_or_else(|e| json!({ "type": "error", "error": e.to_string() }));
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "videocall_accept" {
+            let answer = signal["jsep"].clone();
+            let reply = videocall::accept(janus, &videocall_handle(my_id), answer)
+                .instrument(span)
+                .await;
+            let reply = match reply {
+                Ok(()) => json!({ "type": "videocall", "event": "accepted" }),
+                Err(e) => json!({ "type": "error", "error": e.to_string() }),
+            };
+            send_to(users, my_id, reply.to_string()).await;
+            return;
+        }
+        if signal["type"] == "trickle" {
+            if let Some(key) = plugin_handle(my_id, &signal) {
+                let candidate = signal["candidate"].clone();
+                if let Err(e) = janus.trickle(&key, candidate).instrument(span).await {
+                    eprintln!("trickle error(uid={}): {}", my_id, e);
+                }
+                return;
+            }
+
+            let candidate = signal["candidate"].clone();
+            let subscriber = signal["subscriber"] == true;
+            if let Err(e) = janus_trickle(janus, my_id, subscriber, candidate)
+                .instrument(span)
+                .await
+            {
+                eprintln!("trickle error(uid={}): {}", my_id, e);
+            }
+            return;
+        }
+    }
+
+    // Commands for the gateway are answered to the sender alone.
+    let (name, args) = commands::parse(msg);
+    if let Some(text) = commands::run(name, &args, my_id, state).await {
+        let reply = Outbound::Reply {
+            command: name.to_string(),
+            text,
+            id: None,
+        };
+        deliver(users, my_id, &reply).await;
+        return;
+    }
+
+    chat_message(my_id, room, msg, state).await;
+}
+
+/// Sends chat message `msg` of user `my_id` to the others of chat room
+/// `room`, and keeps it for the users joining later.
+async fn chat_message(my_id: usize, room: &str, msg: &str, state: &State) {
+    let users = &state.users;
+    let janus = &state.janus;
+    let name = name_of(users, my_id).await;
+
+    // The filters may change the message, or drop it without a word.
+    let msg = match state.filters.apply(room, &name, msg) {
+        Some(msg) => msg,
+        None => return,
+    };
+    let message = Outbound::chat(room, &name, &msg);
+    let new_msg = message.render(Protocol::Text).unwrap_or_default();
+    let sender = sender_identity(users, my_id).await;
+
+    // Users of the room with a data channel get it in their TextRoom, from
+    // the sender if they are one of them...
+    let textroom_users: HashMap<usize, u64> = {
+        let users = users.read().await;
+        let members = match users.get(room) {
+            Some(members) => members,
+            None => return,
+        };
+        let textroom_users = state.textroom_users.read().await;
+        textroom_users
+            .iter()
+            .filter(|(uid, _)| members.contains_key(uid))
+            .map(|(&uid, &textroom)| (uid, textroom))
+            .collect()
+    };
+    let secret = state.textroom_secret.as_deref();
+    relay_to_textrooms(janus, secret, my_id, &msg, &new_msg, &textroom_users).await;
+
+    // New message from this user, send it to everyone else in the room
+    // (except same uid), and keep it for the users joining later...
+    let users = users.read().await;
+    let muting = muting(&users, my_id, &state.mutes);
+    let seq = state.history.publish(room, &sender, message, |message| {
+        for (&uid, user) in users.get(room).into_iter().flatten() {
+            if my_id != uid && !textroom_users.contains_key(&uid) && !muting.has(uid, user) {
+                // Should the tx be disconnected, our `user_disconnected`
+                // code is happening in another task, nothing more to do
+                // here.
+                user.deliver(message);
+            }
+        }
+    });
+
+    // ...and tell whoever it mentions, wherever they are.
+    notify_mentions(&users, my_id, room, &name, &msg, seq, &muting);
+    drop(users);
+
+    // ...and write it down, when the history is kept on disk.
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(store) = &state.store {
+            if let Err(e) = store.save(room, &name, &msg, seq).await {
+                eprintln!("chat message of user {} could not be stored: {}", my_id, e);
+            }
+        }
+    }
+}
+
+/// Tells the users `body` mentions as `@nick`, in chat room `room` or any
+/// other, that user `my_id` going by `name` mentioned them in message
+/// `seq`, as
+/// {"v": 1, "type": "event", "event": "mentioned", "text": "bob mentioned you in lobby: hi @al", "data": {"room": "lobby", "from": "bob", "seq": 42, "body": "hi @al"}}
+///
+/// The other connections of the identities mentioned are told as well,
+/// unless they muted the sender.
+fn notify_mentions(
+    users: &HashMap<String, HashMap<usize, ChatUser>>,
+    my_id: usize,
+    room: &str,
+    name: &str,
+    body: &str,
+    seq: u64,
+    muting: &Muting,
+) {
+    let nicks = mentions(body);
+    if nicks.is_empty() {
+        return;
+    }
+    let everyone = || users.values().flat_map(|members| members.iter());
+    let by_nick: Vec<(usize, &ChatUser)> = everyone()
+        .filter(|(_, user)| {
+            user.nick
+                .as_ref()
+                .is_some_and(|nick| nicks.contains(&nick.to_lowercase()))
+        })
+        .map(|(&uid, user)| (uid, user))
+        .collect();
+    let subjects: Vec<&String> = by_nick
+        .iter()
+        .filter_map(|(_, user)| user.credentials.subject.as_ref())
+        .collect();
+
+    let text = format!("{} mentioned you in {}: {}", name, room, body);
+    let notice = Outbound::event("mentioned", text)
+        .with_data(json!({ "room": room, "from": name, "seq": seq, "body": body }));
+    for (&uid, user) in everyone() {
+        let mentioned = by_nick.iter().any(|&(mentioned, _)| mentioned == uid)
+            || user
+                .credentials
+                .subject
+                .as_ref()
+                .is_some_and(|subject| subjects.contains(&subject));
+        if mentioned && uid != my_id && !muting.has(uid, user) {
+            user.deliver(&notice);
+        }
+    }
+}
+
+/// The nicknames `body` mentions as `@nick`, in lowercase.
+fn mentions(body: &str) -> Vec<String> {
+    let mut nicks: Vec<String> = body
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|nick| {
+            nick.trim_end_matches(|c: char| c.is_ascii_punctuation() && c != '-' && c != '_')
+                .to_lowercase()
+        })
+        .filter(|nick| valid_nick(nick))
+        .collect();
+    nicks.sort();
+    nicks.dedup();
+    nicks
+}
+
+/// Keeps file `bytes` of user `my_id` and sends the link to it to the others
+/// of chat room `room`, like their chat messages.
+async fn file_message(my_id: usize, room: &str, bytes: Vec<u8>, state: &State) {
+    let users = &state.users;
+    let size = bytes.len();
+    let (id, mime) = match state.files.keep(my_id, bytes) {
+        Ok(kept) => kept,
+        Err(refusal) => {
+            deliver(users, my_id, &Outbound::error(refusal.to_string())).await;
+            return;
+        }
+    };
+    let message = Outbound::File {
+        room: room.to_string(),
+        from: name_of(users, my_id).await,
+        url: format!("/files/{}", id),
+        mime: mime.to_string(),
+        size,
+        seq: None,
+    };
+    let sender = sender_identity(users, my_id).await;
+    let users = users.read().await;
+    let muting = muting(&users, my_id, &state.mutes);
+    state.history.publish(room, &sender, message, |message| {
+        for (&uid, user) in users.get(room).into_iter().flatten() {
+            if my_id != uid && !muting.has(uid, user) {
+                user.deliver(message);
+            }
+        }
+    });
+}
+
+/// The identities who muted a chat user, see `mutes`.
+pub struct Muting(HashSet<String>);
+
+impl Muting {
+    /// Whether user `user_id` is one of them.
+    pub fn has(&self, user_id: usize, user: &ChatUser) -> bool {
+        !self.0.is_empty()
+            && self
+                .0
+                .contains(&mutes::identity(user_id, &user.credentials))
+    }
+}
+
+/// Those who muted user `my_id` of `users`.
+fn muting(
+    users: &HashMap<String, HashMap<usize, ChatUser>>,
+    my_id: usize,
+    mutes: &Mutes,
+) -> Muting {
+    let sender = users
+        .values()
+        .find_map(|members| members.get(&my_id))
+        .map(|user| mutes::identity(my_id, &user.credentials));
+    Muting(
+        sender
+            .map(|sender| mutes.muting(&sender))
+            .unwrap_or_default(),
+    )
+}
+
+/// Whether user `to` muted user `from`.
+pub async fn has_muted(users: &Users, mutes: &Mutes, to: usize, from: usize) -> bool {
+    let users = users.read().await;
+    let to = users
+        .values()
+        .find_map(|members| members.get(&to).map(|user| (to, user)));
+    match to {
+        Some((uid, user)) => muting(&users, from, mutes).has(uid, user),
+        None => false,
+    }
+}
+
+/// The identity of user `user_id`, which mute lists are kept under, if
+/// they are connected.
+pub async fn identity_of(users: &Users, user_id: usize) -> Option<String> {
+    let users = users.read().await;
+    users
+        .values()
+        .find_map(|members| members.get(&user_id))
+        .map(|user| mutes::identity(user_id, &user.credentials))
+}
+
+/// The identity of chat user `user_id`, as the history keeps it for the
+/// messages they send.
+async fn sender_identity(users: &Users, user_id: usize) -> String {
+    identity_of(users, user_id)
+        .await
+        .unwrap_or_else(|| format!("user:{}", user_id))
+}
+
+/// Changes which `allowed` tokens the videoroom of chat room `room` lets
+/// in, if it has one, see `invites`.
+pub async fn room_acl(state: &State, room: &str, action: AllowedAction, tokens: &[String]) {
+    let room_id = match state.provisioner.room_id(room) {
+        Some(room_id) => room_id,
+        None => return,
+    };
+    let secret = state.secrets.get(room_id).map(|known| known.secret);
+    let allowed = videoroom::allowed(&state.janus, room_id, secret.as_deref(), action, tokens);
+    if let Err(e) = allowed.await {
+        eprintln!(
+            "tokens of videoroom {} could not be changed: {}",
+            room_id, e
+        );
+    }
+}
+
+/// Sends `text` to user `user_id`, if they are still connected.
+pub async fn send_to(users: &Users, user_id: usize, text: String) {
+    let users = users.read().await;
+    if let Some(user) = users.values().find_map(|members| members.get(&user_id)) {
+        let _ = user.tx.send(Ok(Message::text(text)));
+    }
+}
+
+/// Sends `message` to user `user_id`, written their way, if they are still
+/// connected.
+pub async fn deliver(users: &Users, user_id: usize, message: &Outbound) {
+    let users = users.read().await;
+    if let Some(user) = users.values().find_map(|members| members.get(&user_id)) {
+        user.deliver(message);
+    }
+}
+
+/// Closes the websocket of user `user_id` with `code` and `reason`, if they
+/// are still connected.
+pub async fn close(users: &Users, user_id: usize, code: u16, reason: &'static str) {
+    let users = users.read().await;
+    if let Some(user) = users.values().find_map(|members| members.get(&user_id)) {
+        let _ = user.tx.send(Ok(Message::close_with(code, reason)));
+    }
+}
+
+/// Closes the websocket of every connected user with `code` and `reason`.
+pub async fn close_all(users: &Users, code: u16, reason: &'static str) {
+    for user in users.read().await.values().flat_map(HashMap::values) {
+        let _ = user.tx.send(Ok(Message::close_with(code, reason)));
+    }
+}
+
+/// Sends `message` to every user, or to those of chat room `room` alone,
+/// and returns how many they are.
+pub async fn announce(users: &Users, room: Option<&str>, message: &Outbound) -> usize {
+    let users = users.read().await;
+    let mut told = 0;
+    for (_, members) in users
+        .iter()
+        .filter(|(name, _)| room.is_none_or(|room| room == name.as_str()))
+    {
+        for user in members.values() {
+            user.deliver(message);
+            told += 1;
+        }
+    }
+    told
+}
+
+/// Sends `message` to every user in chat room `room` but user `from`.
+pub async fn broadcast_room(users: &Users, room: &str, from: usize, message: &Outbound) {
+    for (&uid, user) in users.read().await.get(room).into_iter().flatten() {
+        if uid != from {
+            user.deliver(message);
+        }
+    }
+}
+
+/// Tells `members` that `member` joined, came back to, left or was kicked
+/// from chat room `room`, or changed their profile, as
+/// {"type": "presence", "event": "joined", "room": "lobby", "user": {"id": 3, "nick": "bob", "connected_since": 1588600931000, "role": "user", "read": 42, "profile": {"display_name": "Bob", "status": "away"}}}
+/// where "profile" has the fields they set, if any.
+fn announce_presence(members: &HashMap<usize, ChatUser>, room: &str, event: &str, member: &Member) {
+    let presence = json!({ "type": "presence", "event": event, "room": room, "user": member });
+    let presence = presence.to_string();
+    for user in members.values() {
+        let _ = user.tx.send(Ok(Message::text(presence.clone())));
+    }
+}
+
+/// The users of chat room `room`, by id.
+pub async fn roster(users: &Users, room: &str) -> Vec<Member> {
+    let users = users.read().await;
+    let mut roster: Vec<Member> = users
+        .get(room)
+        .into_iter()
+        .flatten()
+        .map(|(&uid, user)| Member::new(uid, user))
+        .collect();
+    roster.sort_by_key(|member| member.id);
+    roster
+}
+
+/// The role of user `user_id`, if they are connected.
+pub async fn role_of(users: &Users, user_id: usize) -> Option<Role> {
+    let users = users.read().await;
+    users
+        .values()
+        .find_map(|members| members.get(&user_id))
+        .map(|user| user.role)
+}
+
+/// The chat room user `user_id` is in, if they are connected.
+pub async fn room_of(users: &Users, user_id: usize) -> Option<String> {
+    let users = users.read().await;
+    users
+        .iter()
+        .find(|(_, members)| members.contains_key(&user_id))
+        .map(|(room, _)| room.clone())
+}
+
+/// The name user `user_id` goes by in the chat: their nickname, or else
+/// the name of their token, or else `User#<id>`.
+pub async fn name_of(users: &Users, user_id: usize) -> String {
+    let users = users.read().await;
+    let name = users
+        .values()
+        .find_map(|members| members.get(&user_id))
+        .and_then(|user| user.nick.clone().or_else(|| user.credentials.name.clone()));
+    name.unwrap_or_else(|| format!("User#{}", user_id))
+}
+
+/// The user `who` names for user `from`: the one going by nickname `who`
+/// in the chat room of `from`, or else the one with id `who`, if they are
+/// connected.
+pub async fn find_user(users: &Users, from: usize, who: &str) -> Option<usize> {
+    let users = users.read().await;
+    let by_nick = users
+        .values()
+        .find(|members| members.contains_key(&from))
+        .and_then(|members| {
+            members.iter().find_map(|(&uid, user)| match &user.nick {
+                Some(nick) if nick.eq_ignore_ascii_case(who) => Some(uid),
+                _ => None,
+            })
+        });
+    by_nick.or_else(|| {
+        let user_id = who.parse().ok()?;
+        let connected = users.values().any(|members| members.contains_key(&user_id));
+        Some(user_id).filter(|_| connected)
+    })
+}
+
+/// Gives user `user_id` nickname `nick`, unless someone else in their chat
+/// room goes by it already, and returns their room along with the name
+/// they went by until then.
+pub async fn rename(users: &Users, user_id: usize, nick: &str) -> Option<(String, String)> {
+    let mut users = users.write().await;
+    let (room, members) = users
+        .iter_mut()
+        .find(|(_, members)| members.contains_key(&user_id))?;
+    if nick_taken(members, nick, user_id) {
+        return None;
+    }
+    let user = members.get_mut(&user_id)?;
+    let before = user.nick.replace(nick.to_string());
+    let before = before.unwrap_or_else(|| format!("User#{}", user_id));
+    Some((room.clone(), before))
+}
+
+/// Sets `field` of the profile of user `user_id` to `value`, and tells
+/// everyone in their chat room about it with an "updated" presence, or
+/// else why `value` cannot be one.
+pub async fn set_profile(
+    users: &Users,
+    user_id: usize,
+    field: Field,
+    value: &str,
+) -> Result<Profile, String> {
+    let mut users = users.write().await;
+    let (room, members) = users
+        .iter_mut()
+        .find(|(_, members)| members.contains_key(&user_id))
+        .ok_or_else(|| "you are in no chat room".to_string())?;
+    let user = members
+        .get_mut(&user_id)
+        .ok_or_else(|| "you are in no chat room".to_string())?;
+    user.profile.set(field, value)?;
+    let profile = user.profile.clone();
+    let member = Member::new(user_id, user);
+    announce_presence(members, room, "updated", &member);
+    Ok(profile)
+}
+
+/// Notes that user `user_id` read the messages of their chat room up to
+/// sequence number `seq`, and tells whether that is further than before.
+pub async fn mark_read(users: &Users, user_id: usize, seq: u64) -> bool {
+    let mut users = users.write().await;
+    match users
+        .values_mut()
+        .find_map(|members| members.get_mut(&user_id))
+    {
+        Some(user) if seq > user.read => {
+            user.read = seq;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Whether `nick` can be a nickname: 1 to 32 letters, digits, `-`, `_` or
+/// `.`, other than `Janus`.
+pub fn valid_nick(nick: &str) -> bool {
+    (1..=32).contains(&nick.chars().count())
+        && nick
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !nick.eq_ignore_ascii_case("janus")
+}
+
+/// Whether someone in `members` other than user `user_id` goes by `nick`
+/// already, whatever the case.
+fn nick_taken(members: &HashMap<usize, ChatUser>, nick: &str, user_id: usize) -> bool {
+    let nick = nick.to_lowercase();
+    members.iter().any(|(&uid, user)| {
+        uid != user_id && user.nick.as_ref().map(|taken| taken.to_lowercase()) == Some(nick.clone())
+    })
+}
+
+/// Takes user `user_id` out of their chat room, telling the others, and
+/// closes their websocket with `code` and `reason`. Returns them as they
+/// were, if they were connected.
+pub async fn kick(users: &Users, user_id: usize, code: u16, reason: &str) -> Option<ChatUser> {
+    let mut users = users.write().await;
+    let room = users
+        .iter()
+        .find(|(_, members)| members.contains_key(&user_id))
+        .map(|(room, _)| room.clone())?;
+    let members = users.get_mut(&room)?;
+    let user = members.remove(&user_id)?;
+    announce_presence(members, &room, "kicked", &Member::new(user_id, &user));
+    if members.is_empty() {
+        users.remove(&room);
+    }
+    let _ = user
+        .tx
+        .send(Ok(Message::close_with(code, reason.to_string())));
+    Some(user)
+}
+
+/// Whether user `user_id` is connected.
+pub async fn is_connected(users: &Users, user_id: usize) -> bool {
+    let users = users.read().await;
+    users.values().any(|members| members.contains_key(&user_id))
+}
+
+async fn user_disconnected(my_id: usize, room: &str, users: &Users, janus: &janus::JanusClient) {
+    eprintln!("good bye user: {}", my_id);
+
+    // Stream closed up, so remove from the user list, and the room with
+    // them if they were the last one in it, or else tell the others
+    {
+        let mut users = users.write().await;
+        if let Some(members) = users.get_mut(room) {
+            if let Some(user) = members.remove(&my_id) {
+                announce_presence(members, room, "left", &Member::new(my_id, &user));
+            }
+            if members.is_empty() {
+                users.remove(room);
+            }
+        }
+    }
+
+    // Their WebRTC connections go away with them.
+    let user_key = user_handle(my_id);
+    let prefix = format!("{}/", user_key);
+    let keys = janus
+        .handles()
+        .keys()
+        .into_iter()
+        .filter(|key| *key == user_key || key.starts_with(&prefix));
+    for key in keys {
+        if let Err(e) = janus.handles().detach(&key).await {
+            eprintln!(
+                "janus handle {} of user {} could not be detached: {}",
+                key, my_id, e
+            );
+        }
+    }
+}
+
+/// The key the Janus handle of a chat user is registered under.
+pub fn user_handle(user_id: usize) -> String {
+    format!("user/{}", user_id)
+}
+
+/// The key the Janus handle a chat user subscribes to videoroom feeds with
+/// is registered under.
+pub fn subscriber_handle(user_id: usize) -> String {
+    format!("user/{}/subscriber", user_id)
+}
+
+/// The key the Janus handle of the data channel of a chat user is
+/// registered under.
+pub fn textroom_handle(user_id: usize) -> String {
+    format!("user/{}/textroom", user_id)
+}
+
+/// The key the Janus handle a chat user watches a mountpoint with is
+/// registered under.
+pub fn stream_handle(user_id: usize) -> String {
+    format!("user/{}/stream", user_id)
+}
+
+/// The key the Janus handle a chat user makes SIP calls with is registered
+/// under.
+pub fn sip_handle(user_id: usize) -> String {
+    format!("user/{}/sip", user_id)
+}
+
+/// The key the Janus handle a chat user records and plays recordings back
+/// with is registered under.
+pub fn recordplay_handle(user_id: usize) -> String {
+    format!("user/{}/recordplay", user_id)
+}
+
+/// The key the Janus handle a chat user calls other browsers with is
+/// registered under.
+pub fn videocall_handle(user_id: usize) -> String {
+    format!("user/{}/videocall", user_id)
+}
+
+/// The key the Janus handle a chat user is bridged to an RTP endpoint with
+/// is registered under.
+pub fn nosip_handle(user_id: usize) -> String {
+    format!("user/{}/nosip", user_id)
+}
+
+/// The key the Janus handle of a chat user to custom plugin `plugin` is
+/// registered under.
+pub fn custom_handle(user_id: usize, plugin: &str) -> String {
+    format!("user/{}/plugin/{}", user_id, plugin)
+}
+
+/// The key of the handle to another plugin than the VideoRoom a signal of
+/// user `user_id` is for, as told by its "textroom", "stream", "sip",
+/// "recordplay", "videocall" or "nosip" flag, or by its custom "plugin".
+fn plugin_handle(user_id: usize, signal: &serde_json::Value) -> Option<String> {
+    if signal["textroom"] == true {
+        Some(textroom_handle(user_id))
+    } else if signal["stream"] == true {
+        Some(stream_handle(user_id))
+    } else if signal["sip"] == true {
+        Some(sip_handle(user_id))
+    } else if signal["recordplay"] == true {
+        Some(recordplay_handle(user_id))
+    } else if signal["videocall"] == true {
+        Some(videocall_handle(user_id))
+    } else if signal["nosip"] == true {
+        Some(nosip_handle(user_id))
+    } else {
+        signal["plugin"]
+            .as_str()
+            .map(|plugin| custom_handle(user_id, plugin))
+    }
+}
+
+/// The answer to a SIP signal, as {"type": "sip", "event": "calling"} and
+/// so on.
+fn sip_reply(reply: janus::Result<()>, event: &str) -> String {
+    let reply = match reply {
+        Ok(()) => json!({ "type": "sip", "event": event }),
+        Err(e) => json!({ "type": "error", "error": e.to_string() }),
+    };
+    reply.to_string()
+}
+
+/// Sends a plugin message of a user's browser, with its offer or answer if
+/// any, to the user's Janus handle, attaching one first if they have none
+/// yet.
+async fn janus_message(
+    janus: &janus::JanusClient,
+    user_id: usize,
+    body: serde_json::Value,
+    jsep: Option<serde_json::Value>,
+) -> janus::Result<janus::PluginReply> {
+    let key = user_handle(user_id);
+    janus.handle(&key).await?;
+    janus.message_with_jsep(&key, body, jsep).await
+}
+
+/// Sends a plugin message of a user's browser to their handle to custom
+/// plugin `plugin`, attaching one first if they have none yet, and returns
+/// the plugin's answer as it is.
+///
+/// Only the plugins of `allowed` may be used.
+async fn janus_custom_message(
+    janus: &janus::JanusClient,
+    allowed: &[String],
+    user_id: usize,
+    plugin: &str,
+    body: serde_json::Value,
+    jsep: Option<serde_json::Value>,
+) -> janus::Result<janus::PluginReply> {
+    if !allowed.iter().any(|allowed| allowed == plugin) {
+        return Err(janus::Error::NoPlugin(plugin.to_string()));
+    }
+    let key = custom_handle(user_id, plugin);
+    janus.plugin_handle(&key, plugin).await?;
+    janus.message_raw(&key, body, jsep).await
+}
+
+/// Joins the videoroom a user's browser asks for as a publisher, and
+/// publishes the browser's offer in it.
+async fn janus_publish(
+    janus: &janus::JanusClient,
+    user_id: usize,
+    signal: &serde_json::Value,
+) -> janus::Result<videoroom::Joined> {
+    let room_id = match signal["room"].as_u64() {
+        Some(room_id) => room_id,
+        None => {
+            return Err(janus::Error::Unexpected(
+                "publish without a room".to_string(),
+            ))
+        }
+    };
+    let offer = match signal.get("jsep") {
+        Some(offer) => offer.clone(),
+        None => {
+            return Err(janus::Error::Unexpected(
+                "publish without an offer".to_string(),
+            ))
+        }
+    };
+    let join = videoroom::Join {
+        room: room_id,
+        display: match signal["display"].as_str() {
+            Some(display) => Some(display.to_string()),
+            None => Some(format!("User#{}", user_id)),
+        },
+        pin: signal["pin"].as_str().map(String::from),
+        token: signal["token"].as_str().map(String::from),
+    };
+
+    let key = user_handle(user_id);
+    janus.handle(&key).await?;
+    videoroom::join_and_publish(janus, &key, &join, offer, &videoroom::Publish::default()).await
+}
+
+/// Subscribes a user's browser to the videoroom feeds it asks for, joining
+/// the room as a subscriber first if it has not yet, or unsubscribes it.
+async fn janus_subscribe(
+    janus: &janus::JanusClient,
+    user_id: usize,
+    signal: &serde_json::Value,
+) -> janus::Result<videoroom::Subscription> {
+    let feeds: Vec<videoroom::Feed> = serde_json::from_value(signal["feeds"].clone())?;
+    let key = subscriber_handle(user_id);
+    if signal["type"] == "unsubscribe" {
+        return videoroom::unsubscribe(janus, &key, &feeds).await;
+    }
+    if janus.handles().get(&key).is_some() {
+        return videoroom::subscribe(janus, &key, &feeds).await;
+    }
+
+    let room_id = match signal["room"].as_u64() {
+        Some(room_id) => room_id,
+        None => {
+            return Err(janus::Error::Unexpected(
+                "subscribe without a room".to_string(),
+            ))
+        }
+    };
+    janus.handle(&key).await?;
+    let subscription = videoroom::join_subscriber(
+        janus,
+        &key,
+        room_id,
+        &feeds,
+        signal["private_id"].as_u64(),
+        signal["pin"].as_str(),
+    )
+    .await;
+    if subscription.is_err() {
+        // Joining again takes a new handle.
+        let _ = janus.handles().detach(&key).await;
+    }
+    subscription
+}
+
+/// Hands the answer of a user's browser to the data channel offered on their
+/// TextRoom handle, and joins them to the room it asks for under their user
+/// id, creating the room first if there is none.
+///
+/// Rooms are created with `secret`, if any, which our announcements in them
+/// take as well.
+async fn janus_textroom_join(
+    janus: &janus::JanusClient,
+    secret: Option<&str>,
+    user_id: usize,
+    signal: &serde_json::Value,
+) -> janus::Result<(textroom::Join, Vec<textroom::Participant>)> {
+    let room_id = match signal["room"].as_u64() {
+        Some(room_id) => room_id,
+        None => {
+            return Err(janus::Error::Unexpected(
+                "textroom_join without a room".to_string(),
+            ))
+        }
+    };
+    let key = textroom_handle(user_id);
+    if janus.handles().get(&key).is_none() {
+        return Err(janus::Error::Unexpected(
+            "textroom_join before textroom".to_string(),
+        ));
+    }
+    if let Some(answer) = signal.get("jsep") {
+        textroom::ack(janus, &key, answer.clone()).await?;
+    }
+
+    if !textroom::exists(janus, room_id).await? {
+        let room = textroom::CreateRoom {
+            room: Some(room_id),
+            description: Some("chat".to_string()),
+            secret: secret.map(String::from),
+            ..textroom::CreateRoom::default()
+        };
+        textroom::create_room(janus, &room).await?;
+    }
+    let join = textroom::Join {
+        room: room_id,
+        username: user_id.to_string(),
+        display: match signal["display"].as_str() {
+            Some(display) => Some(display.to_string()),
+            None => Some(format!("User#{}", user_id)),
+        },
+        pin: signal["pin"].as_str().map(String::from),
+        token: signal["token"].as_str().map(String::from),
+    };
+    let participants = textroom::join(janus, &key, &join).await?;
+    Ok((join, participants))
+}
+
+/// Sends the chat message `text` of user `user_id` to every TextRoom of
+/// `textroom_users`: as the sender in their own room, and as an
+/// announcement of `line`, with who sent it, in the other ones, which take
+/// `secret`.
+async fn relay_to_textrooms(
+    janus: &janus::JanusClient,
+    secret: Option<&str>,
+    user_id: usize,
+    text: &str,
+    line: &str,
+    textroom_users: &HashMap<usize, u64>,
+) {
+    let own_room = textroom_users.get(&user_id).copied();
+    let mut rooms: Vec<u64> = textroom_users.values().copied().collect();
+    rooms.sort_unstable();
+    rooms.dedup();
+
+    for room_id in rooms {
+        let sent = if Some(room_id) == own_room {
+            textroom::message(janus, &textroom_handle(user_id), room_id, text).await
+        } else {
+            textroom::announcement(janus, room_id, secret, line).await
+        };
+        if let Err(e) = sent {
+            eprintln!("textroom {} relay error(uid={}): {}", room_id, user_id, e);
+        }
+    }
+}
+
+/// Hands an ICE candidate of a user's browser to the user's Janus handle,
+/// or to the one of their subscription, attaching one first if they have
+/// none yet.
+async fn janus_trickle(
+    janus: &janus::JanusClient,
+    user_id: usize,
+    subscriber: bool,
+    candidate: serde_json::Value,
+) -> janus::Result<()> {
+    let key = if subscriber {
+        subscriber_handle(user_id)
+    } else {
+        user_handle(user_id)
+    };
+    janus.handle(&key).await?;
+    janus.trickle(&key, candidate).await
+}
+
+/// Sends the ICE candidates Janus trickles for a user's handles on to the
+/// user's browser, as
+/// {"type": "trickle", "candidate": {"sdpMid": "0", "sdpMLineIndex": 0, "candidate": "..."}}
+/// with "subscriber": true for the handle of their subscription,
+/// "textroom": true for the one of their data channel, "stream": true for
+/// the one they watch a mountpoint with, "sip": true for the one of their
+/// SIP calls, "recordplay": true for the one of their recordings,
+/// "videocall": true for the one of their calls with other browsers,
+/// "nosip": true for the one bridged to RTP and "plugin":
+/// "janus.plugin.custom" for the one of a custom plugin.
+pub struct TrickleRelay {
+    users: Users,
+    janus: janus::JanusClient,
+}
+
+impl TrickleRelay {
+    pub fn new(users: Users, janus: janus::JanusClient) -> TrickleRelay {
+        TrickleRelay { users, janus }
+    }
+}
+
+impl janus::JanusEventHandler for TrickleRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let (sender, candidate) = match event {
+            janus::Event::Trickle {
+                sender, candidate, ..
+            } => (*sender, candidate),
+            _ => return,
+        };
+        let (user_id, purpose) = match user_of_handle(&self.janus, sender) {
+            Some(user) => user,
+            None => return,
+        };
+
+        let mut msg = json!({ "type": "trickle", "candidate": candidate });
+        match purpose.as_deref() {
+            Some("subscriber") => msg["subscriber"] = json!(true),
+            Some("textroom") => msg["textroom"] = json!(true),
+            Some("stream") => msg["stream"] = json!(true),
+            Some("sip") => msg["sip"] = json!(true),
+            Some("recordplay") => msg["recordplay"] = json!(true),
+            Some("videocall") => msg["videocall"] = json!(true),
+            Some("nosip") => msg["nosip"] = json!(true),
+            Some(purpose) if purpose.starts_with("plugin/") => {
+                msg["plugin"] = json!(&purpose["plugin/".len()..])
+            }
+            _ => {}
+        }
+        let msg = msg.to_string();
+        let users = self.users.clone();
+        tokio::task::spawn(async move {
+            send_to(&users, user_id, msg).await;
+        });
+    }
+}
+
+/// Tells chat users in a videoroom who starts and stops talking there, as
+/// {"type": "talking", "room": 1234, "id": 42, "user": 3, "talking": true, "audio_level": 37.5}
+/// so that their page can show the active speaker, with the chat user they
+/// are if any.
+///
+/// The plugin tells every participant of the room on their own handle, so
+/// a user hears of it once they joined the room as a publisher or
+/// subscriber.
+pub struct TalkingRelay {
+    janus: janus::JanusClient,
+    identities: Identities,
+    /// To the task sending the notifications, one after the other so that
+    /// a user never hears of someone stopping before they started.
+    notifications: mpsc::UnboundedSender<(usize, String)>,
+}
+
+impl TalkingRelay {
+    pub fn new(users: Users, janus: janus::JanusClient, identities: Identities) -> TalkingRelay {
+        let (notifications, mut rx) = mpsc::unbounded_channel::<(usize, String)>();
+        tokio::task::spawn(async move {
+            while let Some((user_id, msg)) = rx.recv().await {
+                send_to(&users, user_id, msg).await;
+            }
+        });
+        TalkingRelay {
+            janus,
+            identities,
+            notifications,
+        }
+    }
+}
+
+impl janus::JanusEventHandler for TalkingRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let talking = match videoroom::Talking::parse(event) {
+            Some(talking) => talking,
+            None => return,
+        };
+        let user_id = match event
+            .sender()
+            .and_then(|sender| user_of_handle(&self.janus, sender))
+        {
+            Some((user_id, _)) => user_id,
+            None => return,
+        };
+
+        let mut msg = json!(talking);
+        msg["type"] = json!("talking");
+        msg["user"] = json!(self
+            .identities
+            .of_participant(talking.id)
+            .map(|identity| identity.user_id));
+        let _ = self.notifications.send((user_id, msg.to_string()));
+    }
+}
+
+/// Tells chat users how their SIP registration and calls turn out, as
+/// {"type": "sip", "event": "incomingcall", "username": "sip:bob@example.com", "jsep": {"type": "offer", "sdp": "..."}}
+/// and so on, see `sip::SipEvent`.
+pub struct SipRelay {
+    janus: janus::JanusClient,
+    /// To the task sending the events, one after the other so that a call
+    /// never hangs up before it rang.
+    events: mpsc::UnboundedSender<(usize, String)>,
+}
+
+impl SipRelay {
+    pub fn new(users: Users, janus: janus::JanusClient) -> SipRelay {
+        let (events, mut rx) = mpsc::unbounded_channel::<(usize, String)>();
+        tokio::task::spawn(async move {
+            while let Some((user_id, msg)) = rx.recv().await {
+                send_to(&users, user_id, msg).await;
+            }
+        });
+        SipRelay { janus, events }
+    }
+}
+
+impl janus::JanusEventHandler for SipRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let sip_event = match sip::SipEvent::parse(event) {
+            Some(sip_event) => sip_event,
+            None => return,
+        };
+        let user_id = match event
+            .sender()
+            .and_then(|sender| user_of_handle(&self.janus, sender))
+        {
+            Some((user_id, _)) => user_id,
+            None => return,
+        };
+
+        let mut msg = json!(sip_event);
+        msg["type"] = json!("sip");
+        let _ = self.events.send((user_id, msg.to_string()));
+    }
+}
+
+/// Tells chat users about the calls of other browsers to them and how
+/// their own calls go, as
+/// {"type": "videocall", "event": "incomingcall", "username": "alice", "jsep": {"type": "offer", "sdp": "..."}}
+/// and so on, see `videocall::VideoCallEvent`.
+pub struct VideoCallRelay {
+    janus: janus::JanusClient,
+    /// To the task sending the events, one after the other so that a call
+    /// never hangs up before it came in.
+    events: mpsc::UnboundedSender<(usize, String)>,
+}
+
+impl VideoCallRelay {
+    pub fn new(users: Users, janus: janus::JanusClient) -> VideoCallRelay {
+        let (events, mut rx) = mpsc::unbounded_channel::<(usize, String)>();
+        tokio::task::spawn(async move {
+            while let Some((user_id, msg)) = rx.recv().await {
+                send_to(&users, user_id, msg).await;
+            }
+        });
+        VideoCallRelay { janus, events }
+    }
+}
+
+impl janus::JanusEventHandler for VideoCallRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let videocall_event = match videocall::VideoCallEvent::parse(event) {
+            Some(videocall_event) => videocall_event,
+            None => return,
+        };
+        let user_id = match event
+            .sender()
+            .and_then(|sender| user_of_handle(&self.janus, sender))
+        {
+            Some((user_id, _)) => user_id,
+            None => return,
+        };
+
+        let mut msg = json!(videocall_event);
+        msg["type"] = json!("videocall");
+        let _ = self.events.send((user_id, msg.to_string()));
+    }
+}
+
+/// Hands the events of custom plugins to the chat users whose handle they
+/// are for, as they are:
+/// {"type": "plugin_event", "plugin": "janus.plugin.custom", "data": {...}, "jsep": null}
+pub struct PluginEventRelay {
+    janus: janus::JanusClient,
+    /// To the task sending the events, one after the other.
+    events: mpsc::UnboundedSender<(usize, String)>,
+}
+
+impl PluginEventRelay {
+    pub fn new(users: Users, janus: janus::JanusClient) -> PluginEventRelay {
+        let (events, mut rx) = mpsc::unbounded_channel::<(usize, String)>();
+        tokio::task::spawn(async move {
+            while let Some((user_id, msg)) = rx.recv().await {
+                send_to(&users, user_id, msg).await;
+            }
+        });
+        PluginEventRelay { janus, events }
+    }
+}
+
+impl janus::JanusEventHandler for PluginEventRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let event = match event {
+            janus::Event::Plugin(event) => event,
+            _ => return,
+        };
+        let (user_id, purpose) = match user_of_handle(&self.janus, event.sender) {
+            Some(user) => user,
+            None => return,
+        };
+        match purpose {
+            Some(purpose) if purpose.starts_with("plugin/") => {}
+            _ => return,
+        }
+
+        let msg = json!({
+            "type": "plugin_event",
+            "plugin": event.plugindata.plugin,
+            "data": event.plugindata.data,
+            "jsep": event.jsep,
+        });
+        let _ = self.events.send((user_id, msg.to_string()));
+    }
+}
+
+/// Tells chat users when the recording they play back is over, as
+/// {"type": "playback_done", "id": 1}
+pub struct RecordPlayRelay {
+    users: Users,
+    janus: janus::JanusClient,
+}
+
+impl RecordPlayRelay {
+    pub fn new(users: Users, janus: janus::JanusClient) -> RecordPlayRelay {
+        RecordPlayRelay { users, janus }
+    }
+}
+
+impl janus::JanusEventHandler for RecordPlayRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let done = match recordplay::PlaybackDone::parse(event) {
+            Some(done) => done,
+            None => return,
+        };
+        let user_id = match event
+            .sender()
+            .and_then(|sender| user_of_handle(&self.janus, sender))
+        {
+            Some((user_id, _)) => user_id,
+            None => return,
+        };
+
+        let mut msg = json!(done);
+        msg["type"] = json!("playback_done");
+        let users = self.users.clone();
+        tokio::task::spawn(async move { send_to(&users, user_id, msg.to_string()).await });
+    }
+}
+
+/// Tells the chat users in a videoroom when publishers come and go there,
+/// as `<Janus>: bob (42) is publishing in room 1234` and so on, keeping
+/// `roster` up to date. Participants who are chat users are told apart, as
+/// `bob (42, user 3)`, and forgotten by `identities` once they leave.
+pub struct RosterRelay {
+    roster: videoroom::Roster,
+    identities: Identities,
+    /// To the task announcing the changes, one after the other.
+    changes: mpsc::UnboundedSender<(u64, String)>,
+}
+
+impl RosterRelay {
+    pub fn new(
+        users: Users,
+        roster: videoroom::Roster,
+        router: RoomRouter,
+        identities: Identities,
+    ) -> RosterRelay {
+        let (changes, mut rx) = mpsc::unbounded_channel::<(u64, String)>();
+        tokio::task::spawn(async move {
+            while let Some((room, change)) = rx.recv().await {
+                router.announce(&users, room, &change).await;
+            }
+        });
+        RosterRelay {
+            roster,
+            identities,
+            changes,
+        }
+    }
+}
+
+impl janus::JanusEventHandler for RosterRelay {
+    fn on_event(&self, event: &janus::Event) {
+        let presence = match videoroom::Presence::parse(event) {
+            Some(presence) => presence,
+            None => return,
+        };
+        let (room, what) = match &presence {
+            videoroom::Presence::Publishers { room, .. } => (room, "is publishing in"),
+            videoroom::Presence::Unpublished { room, .. } => (room, "stopped publishing in"),
+            videoroom::Presence::Leaving { room, .. } => (room, "left"),
+        };
+        let left = match &presence {
+            videoroom::Presence::Leaving { id, .. } => self.identities.participant_left(*id),
+            _ => None,
+        };
+        for (id, display) in self.roster.apply(&presence) {
+            let user = left
+                .clone()
+                .filter(|identity| identity.janus_id == id)
+                .or_else(|| self.identities.of_participant(id));
+            let who = match (display, user) {
+                (Some(display), Some(user)) => {
+                    format!("{} ({}, user {})", display, id, user.user_id)
+                }
+                (Some(display), None) => format!("{} ({})", display, id),
+                (None, Some(user)) => format!("{} (user {})", id, user.user_id),
+                (None, None) => id.to_string(),
+            };
+            let _ = self
+                .changes
+                .send((*room, format!("{} {} room {}", who, what, room)));
+        }
+    }
+}
+
+/// The chat user Janus handle `handle_id` belongs to, and what the handle
+/// is for besides publishing: `subscriber`, `textroom`, `stream`, `sip`,
+/// `recordplay`, `videocall`, `nosip` or `plugin/<plugin>`.
+fn user_of_handle(janus: &janus::JanusClient, handle_id: u64) -> Option<(usize, Option<String>)> {
+    let key = janus.handles().key_of(handle_id)?;
+    let mut parts = key.splitn(3, '/');
+    let user_id = match (parts.next(), parts.next()) {
+        (Some("user"), Some(id)) => id.parse::<usize>().ok()?,
+        _ => return None,
+    };
+    Some((user_id, parts.next().map(String::from)))
+}
+
+static INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <title>Warp Chat</title>
+    </head>
+    <body>
+        <h1>Warp chat</h1>
+        <div id="chat">
+            <p><em>Connecting...</em></p>
+        </div>
+        <input type="text" id="text" />
+        <button type="button" id="send">Send</button>
+        <script type="text/javascript">
+        const chat = document.getElementById('chat');
+        const text = document.getElementById('text');
+        const room = location.hash.slice(1);
+        const uri = 'ws://' + location.host + '/chat' + (room ? '/' + room : '') + '?v=1';
+        const ws = new WebSocket(uri);
+
+        function message(data) {
+            const line = document.createElement('p');
+            line.innerText = data;
+            chat.appendChild(line);
+        }
+
+        ws.onopen = function() {
+            chat.innerHTML = '<p><em>Connected!</em></p>';
+        };
+
+        ws.onmessage = function(msg) {
+            const data = JSON.parse(msg.data);
+            switch (data.type) {
+            case 'chat':
+                message('<' + data.from + '>' + (data.private ? ' (private)' : '') + ': ' + data.body + (data.edited ? ' (edited)' : ''));
+                break;
+            case 'edited':
+                message('<Janus>: message ' + data.seq + ' is now: ' + data.body);
+                break;
+            case 'deleted':
+                message('<Janus>: message ' + data.seq + ' was deleted');
+                break;
+            case 'file':
+                message('<' + data.from + '> sent a file: ' + location.origin + data.url + ' (' + data.mime + ')');
+                break;
+            case 'reply':
+            case 'event':
+                message('<Janus>: ' + data.text);
+                break;
+            case 'announcement':
+                message('<Janus> (announcement): ' + data.text);
+                break;
+            case 'error':
+                message('<Janus>: ' + data.error);
+                break;
+            }
+        };
+
+        ws.onclose = function() {
+            chat.getElementsByTagName('em')[0].innerText = 'Disconnected!';
+        };
+
+        // Commands are written /name/arg/arg, such as /nick/bob.
+        send.onclick = function() {
+            const msg = text.value;
+            if (msg.startsWith('/')) {
+                const [name, ...args] = msg.slice(1).split('/');
+                ws.send(JSON.stringify({ type: 'command', name: name, args: args }));
+            } else {
+                ws.send(JSON.stringify({ type: 'chat', body: msg }));
+            }
+            text.value = '';
+
+            message('<You>: ' + msg);
+        };
+        </script>
+    </body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_are_lowercase_and_unique() {
+        assert_eq!(
+            mentions("@Bob, ask @alice and @bob again"),
+            vec!["alice".to_string(), "bob".to_string()]
+        );
+    }
+
+    #[test]
+    fn mentions_keep_dashes_and_underscores() {
+        assert_eq!(
+            mentions("thanks @jean-luc_ and @x!"),
+            vec!["jean-luc_".to_string(), "x".to_string()]
+        );
+    }
+
+    #[test]
+    fn mentions_skip_what_cannot_be_nicknames() {
+        assert!(mentions("mail me at bob@example.com @ @janus").is_empty());
+    }
+
+    #[test]
+    fn valid_nicks() {
+        assert!(valid_nick("bob"));
+        assert!(valid_nick("jean-luc.p_2"));
+        assert!(valid_nick(&"é".repeat(32)));
+    }
+
+    #[test]
+    fn invalid_nicks() {
+        assert!(!valid_nick(""));
+        assert!(!valid_nick(&"a".repeat(33)));
+        assert!(!valid_nick("bob smith"));
+        assert!(!valid_nick("@bob"));
+        assert!(!valid_nick("JANUS"));
+    }
+}
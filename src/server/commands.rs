@@ -0,0 +1,1736 @@
+//! The commands chat users send to manage the gateway, such as
+//! `createroom/1234` or `/createroom/1234`. They are answered to the
+//! sender only, instead of going to the other users.
+//!
+//! Every command is declared in `COMMANDS`, with its usage, the role it
+//! needs and how many args it takes.
+
+use std::convert::TryFrom;
+use std::iter;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde_json::json;
+
+use super::bans;
+use super::chat::{self, Users};
+use super::profiles::Field;
+use super::protocol::Outbound;
+use super::roles::Role;
+#[cfg(feature = "sqlite")]
+use super::store;
+use super::State;
+use crate::janus::videoroom::{
+    self, AllowedAction, CreateRoom, EditRoom, Layers, Moderate, Publish, RoomFilter, RoomSecret,
+    RoomSecrets, Switch,
+};
+use crate::janus::{sip, videocall, Error, JanusError, VideoRoomError};
+
+/// The name and the args of command `text`, such as `msg` and `["bob",
+/// "hi"]` for `msg/bob/hi` or `/msg/bob/hi`.
+pub fn parse(text: &str) -> (&str, Vec<String>) {
+    let text = text.strip_prefix('/').unwrap_or(text);
+    match text.find('/') {
+        Some(i) if i + 1 < text.len() => {
+            let args = text[i + 1..].split('/').map(String::from).collect();
+            (&text[..i], args)
+        }
+        Some(i) => (&text[..i], Vec::new()),
+        None => (text, Vec::new()),
+    }
+}
+
+/// The args of a command: those of a text message, which were separated by
+/// `/`, or those of a JSON one, as they were sent, `/` or not.
+#[derive(Clone, Copy, Debug)]
+pub struct Args<'a>(&'a [String]);
+
+impl<'a> Args<'a> {
+    /// How many there are.
+    pub fn len(self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Arg `i`, the first one being 0.
+    pub fn get(self, i: usize) -> Option<&'a str> {
+        self.0.get(i).map(String::as_str)
+    }
+
+    /// Arg `i` as a `T`, if it is one.
+    pub fn parse<T: FromStr>(self, i: usize) -> Option<T> {
+        self.get(i)?.parse().ok()
+    }
+
+    /// Arg `i` and all those after it, joined with `/`, such as the text of
+    /// `msg/bob/and/or`.
+    pub fn rest(self, i: usize) -> Option<String> {
+        self.0
+            .get(i..)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| rest.join("/"))
+    }
+
+    /// All of them, one by one.
+    pub fn iter(self) -> impl Iterator<Item = &'a str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// What a command is run with.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub args: Args<'a>,
+    /// The chat user who sent it.
+    pub user_id: usize,
+    pub state: &'a State,
+    /// The command it is.
+    pub command: &'static Command,
+}
+
+impl Context<'_> {
+    /// The usage of the command, the answer to args that do not make
+    /// sense to it.
+    pub fn usage(self) -> String {
+        format!("usage: {}", self.command.usage)
+    }
+}
+
+/// Runs a command, and returns what to answer.
+pub type Handler = for<'a> fn(Context<'a>) -> BoxFuture<'a, String>;
+
+/// A command chat users may send.
+pub struct Command {
+    pub name: &'static str,
+    /// How it is written, such as `createroom/<room_id>[/adopt]`.
+    pub usage: &'static str,
+    /// The role it needs.
+    pub role: Role,
+    /// How many args it takes at least.
+    pub min_args: usize,
+    /// How many args it takes at most, or `None` when the last one may
+    /// have `/` in it.
+    pub max_args: Option<usize>,
+    pub run: Handler,
+}
+
+/// Every command, in the order `help` lists them. Messages that are none
+/// of them are taken for chat messages, and need `roles::BROADCAST`.
+///
+/// A new command is one more entry here, whose handler gets the args
+/// already counted against `min_args` and `max_args`, reads them with
+/// `Args::get` and `Args::parse`, and answers those that make no sense to
+/// it with `Context::usage`.
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "createroom",
+        usage: "createroom/<room_id>[/adopt]",
+        role: Role::Admin,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(create_room(cx)),
+    },
+    Command {
+        name: "destroyroom",
+        usage: "destroyroom/<room_id>[/<secret>]",
+        role: Role::Admin,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(destroy_room(cx)),
+    },
+    Command {
+        name: "editroom",
+        usage: "editroom/<room_id>/<setting>=<value>/...",
+        role: Role::Admin,
+        min_args: 2,
+        max_args: None,
+        run: |cx| Box::pin(edit_room(cx)),
+    },
+    Command {
+        name: "announce",
+        usage: "announce/<text>",
+        role: Role::Admin,
+        min_args: 1,
+        max_args: None,
+        run: |cx| Box::pin(announce(cx, None, cx.args.rest(0).unwrap_or_default())),
+    },
+    Command {
+        name: "announceroom",
+        usage: "announceroom/<room>/<text>",
+        role: Role::Admin,
+        min_args: 2,
+        max_args: None,
+        run: |cx| {
+            let room = cx.args.get(0).unwrap_or_default();
+            let text = cx.args.rest(1).unwrap_or_default();
+            Box::pin(announce(cx, Some(room), text))
+        },
+    },
+    Command {
+        name: "kick",
+        usage: "kick/<user_id>",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(kick(cx)),
+    },
+    Command {
+        name: "mute",
+        usage: "mute/<user_id>[/audio|/video]",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(moderate(cx, true)),
+    },
+    Command {
+        name: "unmute",
+        usage: "unmute/<user_id>[/audio|/video]",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(moderate(cx, false)),
+    },
+    Command {
+        name: "record",
+        usage: "record/<room_id>[/on|/off[/<secret>]]",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(3),
+        run: |cx| Box::pin(record(cx)),
+    },
+    Command {
+        name: "recorduser",
+        usage: "recorduser/<user_id>/on|off",
+        role: Role::Moderator,
+        min_args: 2,
+        max_args: Some(2),
+        run: |cx| Box::pin(record_user(cx)),
+    },
+    Command {
+        name: "allowed",
+        usage: "allowed/on|off or allowed/add|remove/<token>/...",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: None,
+        run: |cx| Box::pin(allowed(cx)),
+    },
+    Command {
+        name: "invite",
+        usage: "invite/<user_id>",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(invite(cx)),
+    },
+    Command {
+        name: "chatkick",
+        usage: "chatkick/<user>",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(chat_kick(cx)),
+    },
+    Command {
+        name: "ban",
+        usage: "ban/<user>[/<minutes>]",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(ban(cx)),
+    },
+    Command {
+        name: "unban",
+        usage: "unban/<address or subject>",
+        role: Role::Moderator,
+        min_args: 1,
+        max_args: None,
+        run: |cx| Box::pin(unban(cx)),
+    },
+    Command {
+        name: "private",
+        usage: "private[/off]",
+        role: Role::User,
+        min_args: 0,
+        max_args: Some(1),
+        run: |cx| Box::pin(private(cx)),
+    },
+    Command {
+        name: "invitetoken",
+        usage: "invitetoken[/<minutes>]",
+        role: Role::User,
+        min_args: 0,
+        max_args: Some(1),
+        run: |cx| Box::pin(invite_token(cx)),
+    },
+    Command {
+        name: "listrooms",
+        usage: "listrooms[/<text>]",
+        role: Role::User,
+        min_args: 0,
+        max_args: None,
+        run: |cx| Box::pin(list_rooms(cx)),
+    },
+    Command {
+        name: "who",
+        usage: "who/<room_id>",
+        role: Role::User,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(who(cx)),
+    },
+    Command {
+        name: "layers",
+        usage: "layers/<mid>/<setting>=<value>/...",
+        role: Role::User,
+        min_args: 2,
+        max_args: None,
+        run: |cx| Box::pin(layers(cx)),
+    },
+    Command {
+        name: "switch",
+        usage: "switch/<feed_id>",
+        role: Role::User,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(switch(cx)),
+    },
+    Command {
+        name: "unpublish",
+        usage: "unpublish",
+        role: Role::User,
+        min_args: 0,
+        max_args: Some(0),
+        run: |cx| Box::pin(unpublish(cx)),
+    },
+    Command {
+        name: "leave",
+        usage: "leave",
+        role: Role::User,
+        min_args: 0,
+        max_args: Some(0),
+        run: |cx| Box::pin(leave(cx)),
+    },
+    Command {
+        name: "sip",
+        usage: "sip/hangup, sip/decline or sip/dtmf/<digits>",
+        role: Role::User,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(sip(cx)),
+    },
+    Command {
+        name: "call",
+        usage: "call/<username>",
+        role: Role::User,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(call(cx)),
+    },
+    Command {
+        name: "hangup",
+        usage: "hangup",
+        role: Role::User,
+        min_args: 0,
+        max_args: Some(0),
+        run: |cx| Box::pin(hangup(cx)),
+    },
+    Command {
+        name: "msg",
+        usage: "msg/<user>/<text>",
+        role: Role::User,
+        min_args: 2,
+        max_args: None,
+        run: |cx| Box::pin(msg(cx)),
+    },
+    Command {
+        name: "react",
+        usage: "react/<message>/<emoji>",
+        role: Role::User,
+        min_args: 2,
+        max_args: Some(2),
+        run: |cx| Box::pin(react(cx)),
+    },
+    Command {
+        name: "edit",
+        usage: "edit/<message>/<text>",
+        role: Role::User,
+        min_args: 2,
+        max_args: None,
+        run: |cx| Box::pin(edit_message(cx)),
+    },
+    Command {
+        name: "delete",
+        usage: "delete/<message>",
+        role: Role::User,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(delete_message(cx)),
+    },
+    Command {
+        name: "search",
+        usage: "search/<words>[/<before>]",
+        role: Role::Guest,
+        min_args: 1,
+        max_args: Some(2),
+        run: |cx| Box::pin(search(cx)),
+    },
+    Command {
+        name: "nick",
+        usage: "nick/<name>, with up to 32 letters, digits, '-', '_' or '.'",
+        role: Role::Guest,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(nick(cx)),
+    },
+    Command {
+        name: "profile",
+        usage: "profile/<name|avatar|status>[/<value>]",
+        role: Role::Guest,
+        min_args: 1,
+        max_args: None,
+        run: |cx| Box::pin(profile(cx)),
+    },
+    Command {
+        name: "chatmute",
+        usage: "chatmute[/<user>]",
+        role: Role::Guest,
+        min_args: 0,
+        max_args: Some(1),
+        run: |cx| Box::pin(chat_mute(cx)),
+    },
+    Command {
+        name: "chatunmute",
+        usage: "chatunmute/<user>",
+        role: Role::Guest,
+        min_args: 1,
+        max_args: Some(1),
+        run: |cx| Box::pin(chat_unmute(cx)),
+    },
+    Command {
+        name: "users",
+        usage: "users",
+        role: Role::Guest,
+        min_args: 0,
+        max_args: Some(0),
+        run: |cx| Box::pin(list_users(cx)),
+    },
+    Command {
+        name: "help",
+        usage: "help[/<command>]",
+        role: Role::Guest,
+        min_args: 0,
+        max_args: Some(1),
+        run: |cx| Box::pin(help(cx)),
+    },
+];
+
+/// The command named `name`, if there is one.
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Runs command `name` of chat user `user_id` with `args`, if there is
+/// such a command, and returns what to answer: its usage
+/// when the args do not fit it.
+///
+/// Who may run it is checked before, see `roles::Action`.
+pub async fn run(name: &str, args: &[String], user_id: usize, state: &State) -> Option<String> {
+    let command = find(name)?;
+    let args = Args(args);
+    let fits =
+        args.len() >= command.min_args && command.max_args.is_none_or(|max| args.len() <= max);
+    if !fits {
+        return Some(format!("usage: {}", command.usage));
+    }
+    let cx = Context {
+        args,
+        user_id,
+        state,
+        command,
+    };
+    Some((command.run)(cx).await)
+}
+
+/// `help`, for the commands the sender may run, and `help/<command>` for
+/// how to write one.
+async fn help(cx: Context<'_>) -> String {
+    let role = chat::role_of(&cx.state.users, cx.user_id)
+        .await
+        .unwrap_or(Role::Guest);
+    if let Some(name) = cx.args.get(0) {
+        return match find(name) {
+            Some(command) => format!("usage: {}, as a {} at least", command.usage, command.role),
+            None => format!("no such command: {}", name),
+        };
+    }
+    let allowed: Vec<&str> = COMMANDS
+        .iter()
+        .filter(|command| command.role <= role)
+        .map(|command| command.name)
+        .collect();
+    format!("you may run: {}", allowed.join(", "))
+}
+
+/// `createroom/<room_id>`, with the `admin_key` of the config if the plugin
+/// requires one. The room gets a secret and a pin of its own, which
+/// are kept in `secrets` for the commands that take them. A room that
+/// exists already is left as it is, and `createroom/<room_id>/adopt` takes
+/// it over instead of answering that it exists.
+async fn create_room(cx: Context<'_>) -> String {
+    let State { janus, secrets, .. } = cx.state;
+    let room_id = match cx.args.parse(0) {
+        Some(room_id) => room_id,
+        None => return cx.usage(),
+    };
+    let adopt = match cx.args.get(1) {
+        None => false,
+        Some("adopt") => true,
+        Some(_) => return cx.usage(),
+    };
+    match videoroom::exists(janus, room_id).await {
+        Ok(true) if adopt => return format!("room {} adopted", room_id),
+        Ok(true) => return format!("room {} already exists", room_id),
+        Ok(false) => {}
+        Err(e) => return format!("createroom failed: {}", e),
+    }
+
+    let secret = RoomSecret {
+        secret: random_token(16),
+        pin: Some(format!("{:06}", OsRng.gen_range(0, 1_000_000))),
+    };
+    let room = CreateRoom {
+        room: Some(room_id),
+        secret: Some(secret.secret.clone()),
+        pin: secret.pin.clone(),
+        admin_key: cx.state.admin_key.clone(),
+        ..CreateRoom::default()
+    };
+    match videoroom::create_room(janus, &room).await {
+        Ok(room_id) => {
+            let pin = secret.pin.clone().unwrap_or_default();
+            secrets.insert(room_id, secret);
+            format!("room {} created, with pin {}", room_id, pin)
+        }
+        Err(e) => format!("createroom failed: {}", e),
+    }
+}
+
+/// `destroyroom/<room_id>`, or `destroyroom/<room_id>/<secret>` for a room
+/// whose secret is not in `secrets`. The RTP forwarders of the room are
+/// stopped first, and the users of the chat rooms it was the videoroom of
+/// are told it is gone.
+async fn destroy_room(cx: Context<'_>) -> String {
+    let State {
+        janus,
+        users,
+        forwarders,
+        recordings,
+        secrets,
+        provisioner,
+        ..
+    } = cx.state;
+    let room_id = match cx.args.parse(0) {
+        Some(room_id) => room_id,
+        None => return cx.usage(),
+    };
+    let secret = secret_of(room_id, cx.args.get(1), secrets);
+    let secret = secret.as_deref();
+    forwarders.stop_room(janus, room_id, secret).await;
+    match videoroom::destroy_room(janus, room_id, secret, false).await {
+        Ok(()) => {
+            recordings.forget(room_id);
+            secrets.remove(room_id);
+            let closed = Outbound::event("room_closed", format!("room {} closed", room_id))
+                .with_data(json!({ "room": room_id }));
+            for chat_room in provisioner.forget(room_id) {
+                chat::announce(users, Some(&chat_room), &closed).await;
+            }
+            format!("room {} destroyed", room_id)
+        }
+        Err(e) => format!("destroyroom failed: {}", e),
+    }
+}
+
+/// `editroom/<room_id>/<setting>=<value>/...`, where the settings are
+/// `description`, `bitrate`, `publishers`, `pin`, `new_secret` and, for a
+/// room whose secret is not in `secrets`, its current `secret`. A new
+/// secret or pin is kept in `secrets`.
+async fn edit_room(cx: Context<'_>) -> String {
+    let State { janus, secrets, .. } = cx.state;
+    let mut edit = EditRoom {
+        room: match cx.args.parse(0) {
+            Some(room_id) => room_id,
+            None => return cx.usage(),
+        },
+        ..EditRoom::default()
+    };
+    for setting in cx.args.iter().skip(1) {
+        let (key, value) = match setting.find('=') {
+            Some(i) => (&setting[..i], &setting[i + 1..]),
+            None => return cx.usage(),
+        };
+        match key {
+            "secret" => edit.secret = Some(value.to_string()),
+            "description" => edit.new_description = Some(value.to_string()),
+            "bitrate" => match value.parse() {
+                Ok(bitrate) => edit.new_bitrate = Some(bitrate),
+                Err(_) => return format!("invalid bitrate: {}", value),
+            },
+            "publishers" => match value.parse() {
+                Ok(publishers) => edit.new_publishers = Some(publishers),
+                Err(_) => return format!("invalid publishers: {}", value),
+            },
+            "pin" => edit.new_pin = Some(value.to_string()),
+            "new_secret" => edit.new_secret = Some(value.to_string()),
+            _ => return format!("unknown setting: {}", key),
+        }
+    }
+
+    edit.secret = secret_of(edit.room, edit.secret.as_deref(), secrets);
+
+    match videoroom::edit_room(janus, &edit).await {
+        Ok(()) => {
+            let known = secrets.get(edit.room);
+            let secret = edit.new_secret.clone().or_else(|| edit.secret.clone());
+            let pin = match &edit.new_pin {
+                Some(pin) => Some(pin.clone()),
+                None => known.and_then(|known| known.pin),
+            };
+            if edit.new_secret.is_some() || edit.new_pin.is_some() {
+                if let Some(secret) = secret {
+                    secrets.insert(edit.room, RoomSecret { secret, pin });
+                }
+            }
+            format!("room {} edited", edit.room)
+        }
+        Err(e) => format!("editroom failed: {}", e),
+    }
+}
+
+/// `announce/<text>`, to send `text` to every chat user, and
+/// `announceroom/<room>/<text>` to those of chat room `room`, as an
+/// announcement.
+async fn announce(cx: Context<'_>, room: Option<&str>, text: String) -> String {
+    let users = &cx.state.users;
+    if text.trim().is_empty() {
+        return "there is nothing to announce".to_string();
+    }
+    let announcement = Outbound::Announcement {
+        text,
+        room: room.map(String::from),
+        from: Some(chat::name_of(users, cx.user_id).await),
+    };
+    match chat::announce(users, room, &announcement).await {
+        0 => "nobody was there to hear it".to_string(),
+        told => format!("announced to {} users", told),
+    }
+}
+
+/// `listrooms` or `listrooms/<text>`, for the rooms whose description
+/// contains `text`. The rooms that take a pin are left out.
+async fn list_rooms(cx: Context<'_>) -> String {
+    let janus = &cx.state.janus;
+    let filter = RoomFilter {
+        description: cx.args.rest(0),
+        hide_pin_required: true,
+        ..RoomFilter::default()
+    };
+    let rooms = match videoroom::list_rooms(janus, &filter).await {
+        Ok(rooms) => rooms,
+        Err(e) => return format!("listrooms failed: {}", e),
+    };
+    if rooms.is_empty() {
+        return "no rooms".to_string();
+    }
+
+    let rooms: Vec<_> = rooms
+        .iter()
+        .map(|room| {
+            format!(
+                "{} {:?} ({} participants)",
+                room.room, room.description, room.num_participants
+            )
+        })
+        .collect();
+    format!("rooms: {}", rooms.join(", "))
+}
+
+/// `who/<room_id>`, for the roster of a room.
+async fn who(cx: Context<'_>) -> String {
+    let janus = &cx.state.janus;
+    let room_id = match cx.args.parse(0) {
+        Some(room_id) => room_id,
+        None => return cx.usage(),
+    };
+    let participants = match videoroom::list_participants(janus, room_id).await {
+        Ok(participants) => participants,
+        Err(e) => return format!("who failed: {}", e),
+    };
+    if participants.is_empty() {
+        return format!("nobody in room {}", room_id);
+    }
+
+    let participants: Vec<_> = participants
+        .iter()
+        .map(|participant| {
+            let mut entry = match &participant.display {
+                Some(display) => format!("{} ({})", display, participant.id),
+                None => participant.id.to_string(),
+            };
+            if participant.publisher {
+                entry.push_str(" publishing");
+            }
+            if participant.talking == Some(true) {
+                entry.push_str(" talking");
+            }
+            entry
+        })
+        .collect();
+    format!("in room {}: {}", room_id, participants.join(", "))
+}
+
+/// `kick/<user_id>`, out of the videoroom they joined for a chat user, or
+/// else out of our `room`.
+async fn kick(cx: Context<'_>) -> String {
+    let State { janus, .. } = cx.state;
+    let user_id = match cx.args.parse(0) {
+        Some(user_id) => user_id,
+        None => return cx.usage(),
+    };
+    let (room_id, participant_id, secret) = participant(user_id, cx.state);
+
+    match videoroom::kick(janus, room_id, secret.as_deref(), participant_id).await {
+        Ok(()) => format!("user {} kicked out of room {}", user_id, room_id),
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::NoSuchFeed),
+            ..
+        }) => format!("there is no user {} in room {}", user_id, room_id),
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::NoSuchRoom),
+            ..
+        }) => format!("there is no room {}", room_id),
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::Unauthorized),
+            ..
+        }) => format!("not allowed to kick out of room {}", room_id),
+        Err(e) => format!("kick failed: {}", e),
+    }
+}
+
+/// `record/<room_id>/on` and `record/<room_id>/off`, followed by
+/// `/<secret>` for a room whose secret is not in `secrets`, to record every
+/// publisher of a room or stop it, and `record/<room_id>` to tell which it
+/// is. The chat users in the room hear about it.
+async fn record(cx: Context<'_>) -> String {
+    let state = cx.state;
+    let State {
+        janus,
+        recordings,
+        secrets,
+        ..
+    } = state;
+    let room_id = match cx.args.parse(0) {
+        Some(room_id) => room_id,
+        None => return cx.usage(),
+    };
+    let record = match cx.args.get(1) {
+        None if recordings.is_recording(room_id) => {
+            return format!("room {} is being recorded", room_id)
+        }
+        None => return format!("room {} is not being recorded", room_id),
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => return cx.usage(),
+    };
+    let secret = secret_of(room_id, cx.args.get(2), secrets);
+
+    match recordings
+        .set(janus, room_id, secret.as_deref(), record)
+        .await
+    {
+        Ok(()) => {
+            let change = if record { "started" } else { "stopped" };
+            let announcement = format!("recording {} in room {}", change, room_id);
+            state
+                .router
+                .announce(&state.users, room_id, &announcement)
+                .await;
+            if record {
+                format!("recording room {}", room_id)
+            } else {
+                format!("stopped recording room {}", room_id)
+            }
+        }
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::NoSuchRoom),
+            ..
+        }) => format!("there is no room {}", room_id),
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::Unauthorized),
+            ..
+        }) => format!("not allowed to record room {}", room_id),
+        Err(e) => format!("record failed: {}", e),
+    }
+}
+
+/// `recorduser/<user_id>/on` and `recorduser/<user_id>/off`, to record the
+/// chat user publishing in the room of `janus.room` or stop it, whatever
+/// the room does. Recordings are named after `janus.recording_filename`,
+/// see `videoroom::recording_filename`.
+async fn record_user(cx: Context<'_>) -> String {
+    let janus = &cx.state.janus;
+    let user_id: usize = match cx.args.parse(0) {
+        Some(user_id) => user_id,
+        None => return cx.usage(),
+    };
+    let record = match cx.args.get(1) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => return cx.usage(),
+    };
+    let key = chat::user_handle(user_id);
+    if janus.handles().get(&key).is_none() {
+        return format!("user {} is not publishing", user_id);
+    }
+
+    let mut publish = Publish {
+        record: Some(record),
+        ..Publish::default()
+    };
+    if record {
+        let room_id = cx.state.room_id;
+        let template = &cx.state.recording_filename;
+        publish.filename = Some(videoroom::recording_filename(template, room_id, user_id));
+    }
+    match videoroom::configure(janus, &key, &publish).await {
+        Ok(()) if record => format!("recording user {}", user_id),
+        Ok(()) => format!("stopped recording user {}", user_id),
+        Err(e) => format!("recorduser failed: {}", e),
+    }
+}
+
+/// `allowed/on` and `allowed/off`, to only let participants with an
+/// allowed token into the room of `janus.room` or anyone again, and
+/// `allowed/add/<token>/...` and `allowed/remove/<token>/...`.
+async fn allowed(cx: Context<'_>) -> String {
+    let State { janus, .. } = cx.state;
+    let action = match cx.args.get(0) {
+        Some("on") => AllowedAction::Enable,
+        Some("off") => AllowedAction::Disable,
+        Some("add") => AllowedAction::Add,
+        Some("remove") => AllowedAction::Remove,
+        _ => return cx.usage(),
+    };
+    let tokens: Vec<String> = cx
+        .args
+        .iter()
+        .skip(1)
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .collect();
+    let takes_tokens = action == AllowedAction::Add || action == AllowedAction::Remove;
+    if takes_tokens == tokens.is_empty() {
+        return cx.usage();
+    }
+    let (room_id, secret) = room(cx.state);
+
+    match videoroom::allowed(janus, room_id, secret.as_deref(), action, &tokens).await {
+        Ok(_) if action == AllowedAction::Disable => format!("anyone may join room {}", room_id),
+        Ok(allowed) => format!("room {} lets in {} tokens", room_id, allowed.len()),
+        Err(e) => format!("allowed failed: {}", e),
+    }
+}
+
+/// `invite/<user_id>`, to let chat user `user_id` into the room of
+/// `janus.room` while it only lets in the tokens it allows. The user is
+/// sent a token of their own, which the room is told to allow.
+async fn invite(cx: Context<'_>) -> String {
+    let State { janus, users, .. } = cx.state;
+    let user_id: usize = match cx.args.parse(0) {
+        Some(user_id) => user_id,
+        None => return cx.usage(),
+    };
+    if !chat::is_connected(users, user_id).await {
+        return format!("there is no user {}", user_id);
+    }
+    let token = random_token(16);
+    let (room_id, secret) = room(cx.state);
+
+    let tokens = [token.clone()];
+    let allowed = videoroom::allowed(
+        janus,
+        room_id,
+        secret.as_deref(),
+        AllowedAction::Add,
+        &tokens,
+    );
+    match allowed.await {
+        Ok(_) => {
+            let text = format!(
+                "you are invited to room {}, join it with token {}",
+                room_id, token
+            );
+            let invitation = Outbound::event("invited", text)
+                .with_data(json!({ "room": room_id, "token": token }));
+            chat::deliver(users, user_id, &invitation).await;
+            format!("user {} invited to room {}", user_id, room_id)
+        }
+        Err(e) => format!("invite failed: {}", e),
+    }
+}
+
+/// `layers/<mid>/<setting>=<value>/...`, to receive other simulcast or SVC
+/// layers of stream `mid` of the sender's subscription, where the settings
+/// are `substream` and `temporal` for simulcast, and `spatial_layer` and
+/// `temporal_layer` for SVC.
+async fn layers(cx: Context<'_>) -> String {
+    let (janus, user_id) = (&cx.state.janus, cx.user_id);
+    let mut layers = Layers {
+        mid: match cx.args.get(0) {
+            Some(mid) if !mid.is_empty() => mid.to_string(),
+            _ => return cx.usage(),
+        },
+        ..Layers::default()
+    };
+    for setting in cx.args.iter().skip(1) {
+        let (key, value) = match setting.find('=') {
+            Some(i) => (&setting[..i], &setting[i + 1..]),
+            None => return cx.usage(),
+        };
+        let value = match value.parse() {
+            Ok(value) => Some(value),
+            Err(_) => return format!("invalid {}: {}", key, value),
+        };
+        match key {
+            "substream" => layers.substream = value,
+            "temporal" => layers.temporal = value,
+            "spatial_layer" => layers.spatial_layer = value,
+            "temporal_layer" => layers.temporal_layer = value,
+            _ => return format!("unknown setting: {}", key),
+        }
+    }
+    let key = chat::subscriber_handle(user_id);
+    if janus.handles().get(&key).is_none() {
+        return "you are not subscribed to anything".to_string();
+    }
+
+    match videoroom::set_layers(janus, &key, &[layers]).await {
+        Ok(()) => "layers changed".to_string(),
+        Err(e) => format!("layers failed: {}", e),
+    }
+}
+
+/// `switch/<feed_id>`, to have the sender's subscription receive publisher
+/// `feed_id` instead, e.g. to follow the active speaker. Every stream is
+/// switched to the stream with the same mid of the new publisher.
+async fn switch(cx: Context<'_>) -> String {
+    let State {
+        janus,
+        subscriptions,
+        ..
+    } = cx.state;
+    let user_id = cx.user_id;
+    let feed_id = match cx.args.parse(0) {
+        Some(feed_id) => feed_id,
+        None => return cx.usage(),
+    };
+    let streams = match subscriptions.read().await.get(&user_id) {
+        Some(streams) => streams.clone(),
+        None => return "you are not subscribed to anything".to_string(),
+    };
+    let switches: Vec<_> = streams
+        .into_iter()
+        .filter_map(|stream| {
+            Some(Switch {
+                feed: feed_id,
+                mid: stream.feed_mid?,
+                sub_mid: stream.mid,
+            })
+        })
+        .collect();
+    if switches.is_empty() {
+        return "your subscription has no streams to switch".to_string();
+    }
+
+    let key = chat::subscriber_handle(user_id);
+    match videoroom::switch(janus, &key, &switches).await {
+        Ok(streams) => {
+            subscriptions.write().await.insert(user_id, streams);
+            format!("switched to {}", feed_id)
+        }
+        Err(Error::Janus {
+            kind: JanusError::VideoRoom(VideoRoomError::NoSuchFeed),
+            ..
+        }) => format!("there is no feed {}", feed_id),
+        Err(e) => format!("switch failed: {}", e),
+    }
+}
+
+/// `unpublish`, to stop publishing in the room the sender joined, while
+/// staying in it.
+async fn unpublish(cx: Context<'_>) -> String {
+    let (janus, user_id) = (&cx.state.janus, cx.user_id);
+    let key = chat::user_handle(user_id);
+    if janus.handles().get(&key).is_none() {
+        return "you are not publishing".to_string();
+    }
+
+    match videoroom::unpublish(janus, &key).await {
+        Ok(()) => "unpublished".to_string(),
+        Err(e) => format!("unpublish failed: {}", e),
+    }
+}
+
+/// `leave`, to leave the room the sender joined, as a publisher and as a
+/// subscriber.
+async fn leave(cx: Context<'_>) -> String {
+    let (state, user_id) = (cx.state, cx.user_id);
+    let janus = &state.janus;
+    let keys = [chat::user_handle(user_id), chat::subscriber_handle(user_id)];
+    let mut left = false;
+    for key in keys.iter().filter(|key| janus.handles().get(key).is_some()) {
+        if let Err(e) = videoroom::leave(janus, key).await {
+            return format!("leave failed: {}", e);
+        }
+        left = true;
+    }
+    state.subscriptions.write().await.remove(&user_id);
+    state.router.leave(user_id);
+    state.identities.user_left(user_id);
+
+    if left {
+        "left".to_string()
+    } else {
+        "you are not in a room".to_string()
+    }
+}
+
+/// `sip/hangup`, `sip/decline` and `sip/dtmf/<digits>`, for the SIP call of
+/// the sender.
+async fn sip(cx: Context<'_>) -> String {
+    let janus = &cx.state.janus;
+    let key = chat::sip_handle(cx.user_id);
+    if janus.handles().get(&key).is_none() {
+        return "you are not registered with SIP".to_string();
+    }
+
+    let (done, result) = match (cx.args.get(0), cx.args.get(1)) {
+        (Some("hangup"), None) => ("hanging up", sip::hangup(janus, &key).await),
+        (Some("decline"), None) => ("declined", sip::decline(janus, &key).await),
+        (Some("dtmf"), Some(digits)) if !digits.is_empty() => {
+            ("sent", sip::dtmf(janus, &key, digits).await)
+        }
+        _ => return cx.usage(),
+    };
+    match result {
+        Ok(()) => done.to_string(),
+        Err(e) => format!("sip failed: {}", e),
+    }
+}
+
+/// `call/<username>`, to call the browser that registered `username` with
+/// the VideoCall plugin, which is the user id of chat users who did not
+/// pick one. The sender is registered under their user id first if they
+/// did not register, and their browser is asked for the offer of the call
+/// with {"type": "call", "username": "bob"}.
+async fn call(cx: Context<'_>) -> String {
+    let State { janus, users, .. } = cx.state;
+    let user_id = cx.user_id;
+    let username = match cx.args.get(0) {
+        Some(username) if !username.is_empty() => username,
+        _ => return cx.usage(),
+    };
+    let key = chat::videocall_handle(user_id);
+    if janus.handles().get(&key).is_none() {
+        if let Err(e) = videocall::register(janus, &key, &user_id.to_string()).await {
+            return format!("call failed: {}", e);
+        }
+    }
+
+    let ask = serde_json::json!({ "type": "call", "username": username });
+    chat::send_to(users, user_id, ask.to_string()).await;
+    format!("calling {}", username)
+}
+
+/// `hangup`, for the call of the sender with another browser, or to
+/// decline the one coming in.
+async fn hangup(cx: Context<'_>) -> String {
+    let janus = &cx.state.janus;
+    let key = chat::videocall_handle(cx.user_id);
+    if janus.handles().get(&key).is_none() {
+        return "you are not in a call".to_string();
+    }
+
+    match videocall::hangup(janus, &key).await {
+        Ok(()) => "hung up".to_string(),
+        Err(e) => format!("hangup failed: {}", e),
+    }
+}
+
+/// `mute/<user_id>` and `unmute/<user_id>`, for the audio and video of a
+/// chat user or participant of our `room` as for `kick`, or
+/// `mute/<user_id>/audio` and so on for one of them.
+async fn moderate(cx: Context<'_>, mute: bool) -> String {
+    let State { janus, .. } = cx.state;
+    let command = cx.command.name;
+    let user_id = match cx.args.parse(0) {
+        Some(user_id) => user_id,
+        None => return cx.usage(),
+    };
+    let (media, moderate) = match cx.args.get(1) {
+        None => (
+            "audio and video",
+            Moderate {
+                mute_audio: Some(mute),
+                mute_video: Some(mute),
+                ..Moderate::default()
+            },
+        ),
+        Some("audio") => (
+            "audio",
+            Moderate {
+                mute_audio: Some(mute),
+                ..Moderate::default()
+            },
+        ),
+        Some("video") => (
+            "video",
+            Moderate {
+                mute_video: Some(mute),
+                ..Moderate::default()
+            },
+        ),
+        Some(_) => return cx.usage(),
+    };
+    let (room_id, participant_id, secret) = participant(user_id, cx.state);
+
+    match videoroom::moderate(janus, room_id, secret.as_deref(), participant_id, moderate).await {
+        Ok(()) => format!("{} of user {} {}d", media, user_id, command),
+        Err(e) => format!("{} failed: {}", command, e),
+    }
+}
+
+/// `nick/<name>`, to go by `name` in the chat instead of `User#<id>`. The
+/// other users of the chat room are told about it.
+async fn nick(cx: Context<'_>) -> String {
+    let (users, user_id) = (&cx.state.users, cx.user_id);
+    let name = match cx.args.get(0) {
+        Some(name) if chat::valid_nick(name) => name,
+        _ => return cx.usage(),
+    };
+    match chat::rename(users, user_id, name).await {
+        Some((room, before)) => {
+            let text = format!("{} is now known as {}", before, name);
+            let notice = Outbound::event("renamed", text)
+                .with_data(json!({ "id": user_id, "from": before, "to": name }));
+            chat::broadcast_room(users, &room, user_id, &notice).await;
+            format!("you are now known as {}", name)
+        }
+        None => format!("nickname {} is taken", name),
+    }
+}
+
+/// `profile/<field>/<value>`, to set `name`, `avatar` or `status` of the
+/// profile of the sender, or to clear it without a value. The users of the
+/// chat room hear of it as a presence.
+async fn profile(cx: Context<'_>) -> String {
+    let (users, user_id) = (&cx.state.users, cx.user_id);
+    let field = match cx.args.get(0).and_then(Field::of) {
+        Some(field) => field,
+        None => return cx.usage(),
+    };
+    let value = cx.args.rest(1).unwrap_or_default();
+    match chat::set_profile(users, user_id, field, &value).await {
+        Ok(_) if value.trim().is_empty() => format!("your {} is cleared", field),
+        Ok(_) => format!("your {} is now {}", field, value.trim()),
+        Err(refusal) => refusal,
+    }
+}
+
+/// `msg/<user>/<text>`, to send `text` to `user` alone, who is a nickname
+/// in the chat room of the sender or else a user id. Those who muted the
+/// sender do not get it, without the sender knowing.
+async fn msg(cx: Context<'_>) -> String {
+    let State { users, mutes, .. } = cx.state;
+    let user_id = cx.user_id;
+    let (who, text) = match (cx.args.get(0), cx.args.rest(1)) {
+        (Some(who), Some(text)) if !who.is_empty() && !text.is_empty() => (who, text),
+        _ => return cx.usage(),
+    };
+    let to = match chat::find_user(users, user_id, who).await {
+        Some(to) => to,
+        None => return format!("{} is not online, nothing was sent", who),
+    };
+    let from = chat::name_of(users, user_id).await;
+    let room = chat::room_of(users, user_id).await.unwrap_or_default();
+    let message = Outbound::Chat {
+        room,
+        from,
+        body: text,
+        private: true,
+        seq: None,
+        edited: false,
+    };
+    if !chat::has_muted(users, mutes, to, user_id).await {
+        chat::deliver(users, to, &message).await;
+    }
+    format!("sent to {}", chat::name_of(users, to).await)
+}
+
+/// `users`, the roster of the chat room of the sender, with how long
+/// everyone has been there.
+async fn list_users(cx: Context<'_>) -> String {
+    let users = &cx.state.users;
+    let room = match chat::room_of(users, cx.user_id).await {
+        Some(room) => room,
+        None => return "you are in no chat room".to_string(),
+    };
+    let now = SystemTime::now();
+    let roster: Vec<String> = chat::roster(users, &room)
+        .await
+        .into_iter()
+        .map(|member| {
+            let since = UNIX_EPOCH + Duration::from_millis(member.connected_since);
+            let here = now.duration_since(since).unwrap_or_default().as_secs();
+            let id = member.id;
+            let name = member.nick.unwrap_or_else(|| format!("User#{}", id));
+            format!("{} (user {}, here for {})", name, id, elapsed(here))
+        })
+        .collect();
+    format!("{} in room {}: {}", roster.len(), room, roster.join(", "))
+}
+
+/// `react/<seq>/<emoji>`, to react to message `seq` of the chat room of the
+/// sender with `emoji`, or to take it back when they did already. Everyone
+/// in the room is told about the reactions to the message.
+async fn react(cx: Context<'_>) -> String {
+    let State { users, history, .. } = cx.state;
+    let user_id = cx.user_id;
+    let seq = match cx.args.parse(0) {
+        Some(seq) => seq,
+        None => return cx.usage(),
+    };
+    let emoji = match cx.args.get(1) {
+        Some(emoji) if valid_emoji(emoji) => emoji,
+        _ => return cx.usage(),
+    };
+    let room = match chat::room_of(users, user_id).await {
+        Some(room) => room,
+        None => return "you are in no chat room".to_string(),
+    };
+    let (added, reactions) = match history.react(&room, seq, emoji, user_id) {
+        Some(reacted) => reacted,
+        None => return format!("message {} is not kept", seq),
+    };
+    let update = Outbound::Reactions {
+        room: room.clone(),
+        seq,
+        reactions,
+    };
+    chat::broadcast_room(users, &room, user_id, &update).await;
+    chat::deliver(users, user_id, &update).await;
+    if added {
+        format!("you reacted {} to message {}", emoji, seq)
+    } else {
+        format!("you took back {} from message {}", emoji, seq)
+    }
+}
+
+/// `edit/<message>/<text>`, for the sender of chat message `message` of
+/// their room or a moderator to change it into `text`, which goes through
+/// the filters like a new one.
+async fn edit_message(cx: Context<'_>) -> String {
+    let State {
+        users,
+        history,
+        filters,
+        ..
+    } = cx.state;
+    let seq = match cx.args.parse(0) {
+        Some(seq) => seq,
+        None => return cx.usage(),
+    };
+    let room = match may_change(cx, seq).await {
+        Ok(room) => room,
+        Err(refusal) => return refusal,
+    };
+    let name = chat::name_of(users, cx.user_id).await;
+    let body = match filters.apply(&room, &name, &cx.args.rest(1).unwrap_or_default()) {
+        Some(body) => body,
+        None => return format!("message {} cannot say that", seq),
+    };
+    if !history.edit(&room, seq, &body) {
+        return format!("message {} is not a chat message", seq);
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(store) = &cx.state.store {
+            if let Err(e) = store.edit(&room, seq, &body).await {
+                eprintln!("edit of message {} could not be stored: {}", seq, e);
+            }
+        }
+    }
+
+    let update = Outbound::Edited {
+        room: room.clone(),
+        seq,
+        body,
+    };
+    chat::broadcast_room(users, &room, cx.user_id, &update).await;
+    chat::deliver(users, cx.user_id, &update).await;
+    format!("you edited message {}", seq)
+}
+
+/// `delete/<message>`, for the sender of message `message` of their room or
+/// a moderator to take it back.
+async fn delete_message(cx: Context<'_>) -> String {
+    let State { users, history, .. } = cx.state;
+    let seq = match cx.args.parse(0) {
+        Some(seq) => seq,
+        None => return cx.usage(),
+    };
+    let room = match may_change(cx, seq).await {
+        Ok(room) => room,
+        Err(refusal) => return refusal,
+    };
+    if !history.delete(&room, seq) {
+        return format!("message {} is not kept", seq);
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(store) = &cx.state.store {
+            if let Err(e) = store.delete(&room, seq).await {
+                eprintln!("deletion of message {} could not be stored: {}", seq, e);
+            }
+        }
+    }
+
+    let update = Outbound::Deleted {
+        room: room.clone(),
+        seq,
+    };
+    chat::broadcast_room(users, &room, cx.user_id, &update).await;
+    chat::deliver(users, cx.user_id, &update).await;
+    format!("you deleted message {}", seq)
+}
+
+/// The chat room of the sender of `cx`, if they may change message `seq`
+/// of it: they sent it, or are a moderator.
+async fn may_change(cx: Context<'_>, seq: u64) -> Result<String, String> {
+    let State { users, history, .. } = cx.state;
+    let room = match chat::room_of(users, cx.user_id).await {
+        Some(room) => room,
+        None => return Err("you are in no chat room".to_string()),
+    };
+    let sender = match history.sender(&room, seq) {
+        Some(sender) => sender,
+        None => return Err(format!("message {} is not kept", seq)),
+    };
+    let identity = chat::identity_of(users, cx.user_id).await;
+    let role = chat::role_of(users, cx.user_id).await;
+    if identity.as_deref() == Some(sender.as_str()) || role >= Some(Role::Moderator) {
+        Ok(room)
+    } else {
+        Err(format!("message {} is not yours", seq))
+    }
+}
+
+/// How many messages the `search` command answers with.
+#[cfg(feature = "sqlite")]
+const SEARCH_PAGE: u32 = 10;
+
+/// `search/<words>`, for the last messages of the chat room of the sender
+/// with every one of `words`, and `search/<words>/<before>` for those
+/// before message `before` of the store. The messages are numbered as
+/// `edit`, `delete` and `react` take them. See `store`.
+async fn search(cx: Context<'_>) -> String {
+    #[cfg(feature = "sqlite")]
+    {
+        let store = match &cx.state.store {
+            Some(store) => store,
+            None => return "the chat history is not kept".to_string(),
+        };
+        let room = match chat::room_of(&cx.state.users, cx.user_id).await {
+            Some(room) => room,
+            None => return "you are in no chat room".to_string(),
+        };
+        let words = cx.args.get(0).unwrap_or_default();
+        let before = match cx.args.get(1) {
+            Some(before) => match before.parse() {
+                Ok(before) => Some(before),
+                Err(_) => return cx.usage(),
+            },
+            None => None,
+        };
+        let page = store::Page {
+            before,
+            limit: Some(SEARCH_PAGE),
+        };
+        let found = match store.search(&room, words, &page).await {
+            Ok(found) => found,
+            Err(e) => return format!("the search failed: {}", e),
+        };
+        if found.is_empty() {
+            return format!("no messages of room {} with {}", room, words);
+        }
+        let mut lines: Vec<String> = found
+            .iter()
+            .map(|message| match message.seq {
+                Some(seq) => format!("#{} <{}>: {}", seq, message.sender, message.body),
+                None => format!("<{}>: {}", message.sender, message.body),
+            })
+            .collect();
+        if found.len() == SEARCH_PAGE as usize {
+            let oldest = found.last().map(|message| message.id).unwrap_or_default();
+            lines.push(format!("older ones: search/{}/{}", words, oldest));
+        }
+        lines.join("\n")
+    }
+    #[cfg(not(feature = "sqlite"))]
+    {
+        let _ = cx;
+        "the chat history is not kept".to_string()
+    }
+}
+
+/// `private`, to make the chat room of the sender invite-only, with them
+/// as its owner, and `private/off`, for its owner or a moderator to let
+/// anyone in again. See `invites`.
+async fn private(cx: Context<'_>) -> String {
+    let State { users, invites, .. } = cx.state;
+    let room = match chat::room_of(users, cx.user_id).await {
+        Some(room) => room,
+        None => return "you are in no chat room".to_string(),
+    };
+    match cx.args.get(0) {
+        None => {
+            let owner = chat::identity_of(users, cx.user_id)
+                .await
+                .unwrap_or_default();
+            let members = chat::roster(users, &room).await;
+            let members = members.into_iter().map(|member| member.id);
+            let tokens = match invites.make_private(&room, &owner, members) {
+                Some(tokens) => tokens,
+                None => return format!("room {} is private already", room),
+            };
+            chat::room_acl(cx.state, &room, AllowedAction::Enable, &[]).await;
+            chat::room_acl(cx.state, &room, AllowedAction::Add, &tokens).await;
+            let text = format!("room {} is now invite only", room);
+            let notice = Outbound::event("room_private", text).with_data(json!({ "room": room }));
+            chat::broadcast_room(users, &room, cx.user_id, &notice).await;
+            format!(
+                "room {} is now invite only, invite others with invitetoken",
+                room
+            )
+        }
+        Some("off") => {
+            if let Err(refusal) = manages(cx, &room).await {
+                return refusal;
+            }
+            if !invites.make_public(&room) {
+                return format!("room {} is not private", room);
+            }
+            chat::room_acl(cx.state, &room, AllowedAction::Disable, &[]).await;
+            let text = format!("anyone may join room {} again", room);
+            let notice =
+                Outbound::event("room_public", text.clone()).with_data(json!({ "room": room }));
+            chat::broadcast_room(users, &room, cx.user_id, &notice).await;
+            text
+        }
+        Some(_) => cx.usage(),
+    }
+}
+
+/// The longest an invite may be used for, in minutes: a week.
+const MAX_INVITE_MINUTES: u64 = 7 * 24 * 60;
+
+/// `invitetoken`, for an invite to the private chat room of the sender that
+/// may be used once, and `invitetoken/<minutes>` for one that may be used
+/// for `minutes`, up to `MAX_INVITE_MINUTES`, by its owner or a moderator.
+async fn invite_token(cx: Context<'_>) -> String {
+    let State { users, invites, .. } = cx.state;
+    let room = match chat::room_of(users, cx.user_id).await {
+        Some(room) => room,
+        None => return "you are in no chat room".to_string(),
+    };
+    let minutes = match cx.args.get(0) {
+        Some(_) => match cx
+            .args
+            .parse::<u64>(0)
+            .filter(|minutes| (1..=MAX_INVITE_MINUTES).contains(minutes))
+        {
+            Some(minutes) => Some(minutes),
+            None => return cx.usage(),
+        },
+        None => None,
+    };
+    if let Err(refusal) = manages(cx, &room).await {
+        return refusal;
+    }
+    let lifetime = minutes.and_then(|minutes| minutes.checked_mul(60).map(Duration::from_secs));
+    let token = match invites.issue(&room, lifetime) {
+        Some(token) => token,
+        None => return format!("room {} is not private, see private", room),
+    };
+    let good_for = match minutes {
+        Some(minutes) => format!("for {} minutes", minutes),
+        None => "once".to_string(),
+    };
+    format!(
+        "join room {} with ?invite={}, which may be used {}",
+        room, token, good_for
+    )
+}
+
+/// Whether the sender of `cx` owns private chat room `room`, or is a
+/// moderator, who may manage it.
+async fn manages(cx: Context<'_>, room: &str) -> Result<(), String> {
+    let State { users, invites, .. } = cx.state;
+    let identity = chat::identity_of(users, cx.user_id)
+        .await
+        .unwrap_or_default();
+    let role = chat::role_of(users, cx.user_id).await;
+    if invites.is_owner(room, &identity) || role >= Some(Role::Moderator) {
+        Ok(())
+    } else {
+        Err(format!("only the owner of room {} may do that", room))
+    }
+}
+
+/// `chatmute/<user>`, to no longer hear `user`, a nickname in the chat room
+/// of the sender or else a user id, and `chatmute` for those muted. See
+/// `mutes`.
+async fn chat_mute(cx: Context<'_>) -> String {
+    let State { users, mutes, .. } = cx.state;
+    let owner = match chat::identity_of(users, cx.user_id).await {
+        Some(owner) => owner,
+        None => return "you are in no chat room".to_string(),
+    };
+    let who = match cx.args.get(0) {
+        Some(who) => who,
+        None => {
+            let muted = mutes.muted(&owner);
+            if muted.is_empty() {
+                return "you muted nobody".to_string();
+            }
+            return format!("you muted {}", muted.join(", "));
+        }
+    };
+    let target = match chat::find_user(users, cx.user_id, who).await {
+        Some(target) if target != cx.user_id => target,
+        Some(_) => return "you cannot mute yourself".to_string(),
+        None => return format!("{} is not online", who),
+    };
+    let name = chat::name_of(users, target).await;
+    let identity = match chat::identity_of(users, target).await {
+        Some(identity) => identity,
+        None => return format!("{} is not online", who),
+    };
+    if mutes.mute(&owner, &identity, &name) {
+        format!("you no longer hear {}", name)
+    } else {
+        format!("you muted {} already", name)
+    }
+}
+
+/// `chatunmute/<user>`, to hear `user` again, the name they went by when
+/// they were muted, or else a nickname in the chat room of the sender or a
+/// user id.
+async fn chat_unmute(cx: Context<'_>) -> String {
+    let State { users, mutes, .. } = cx.state;
+    let owner = match chat::identity_of(users, cx.user_id).await {
+        Some(owner) => owner,
+        None => return "you are in no chat room".to_string(),
+    };
+    let who = cx.args.get(0).unwrap_or_default();
+    let identity = match chat::find_user(users, cx.user_id, who).await {
+        Some(target) => chat::identity_of(users, target).await,
+        None => None,
+    };
+    let unmuted = identity
+        .and_then(|identity| mutes.unmute(&owner, &identity))
+        .or_else(|| mutes.unmute(&owner, who));
+    match unmuted {
+        Some(name) => format!("you hear {} again", name),
+        None => format!("you did not mute {}", who),
+    }
+}
+
+/// Whether `emoji` can be a reaction: 1 to 16 characters, as emojis may
+/// be made of several, none of them ASCII or spaces.
+fn valid_emoji(emoji: &str) -> bool {
+    (1..=16).contains(&emoji.chars().count())
+        && emoji.chars().all(|c| !c.is_ascii() && !c.is_whitespace())
+}
+
+/// The close code of the websockets of kicked chat users.
+const KICKED: u16 = 4002;
+
+/// `chatkick/<user>`, to close the chat websocket of `user`, a nickname in
+/// the chat room of the sender or else a user id. They may come back.
+async fn chat_kick(cx: Context<'_>) -> String {
+    let users = &cx.state.users;
+    let who = match cx.args.get(0) {
+        Some(who) if !who.is_empty() => who,
+        _ => return cx.usage(),
+    };
+    let target = match kickable(who, users, cx.user_id).await {
+        Ok(target) => target,
+        Err(refusal) => return refusal,
+    };
+    let name = chat::name_of(users, target).await;
+    match chat::kick(users, target, KICKED, "kicked").await {
+        Some(_) => format!("{} was kicked", name),
+        None => format!("{} is not online", who),
+    }
+}
+
+/// The longest a ban may last, in minutes: a year.
+pub const MAX_BAN_MINUTES: u64 = 365 * 24 * 60;
+
+/// `ban/<user>[/<minutes>]`, to kick `user` and keep them out for
+/// `minutes`, up to `MAX_BAN_MINUTES`, or else `ban_minutes` of `[chat]`
+/// in the config, 60 by default. Both their address and the subject of their token are banned,
+/// so that they cannot come back with either.
+async fn ban(cx: Context<'_>) -> String {
+    let State { users, bans, .. } = cx.state;
+    let who = match cx.args.get(0) {
+        Some(who) if !who.is_empty() => who,
+        _ => return cx.usage(),
+    };
+    let minutes = match cx.args.get(1) {
+        Some(_) => match cx
+            .args
+            .parse::<u64>(1)
+            .filter(|&minutes| minutes <= MAX_BAN_MINUTES)
+        {
+            Some(minutes) => minutes,
+            None => return cx.usage(),
+        },
+        None => cx.state.ban_minutes,
+    };
+    let target = match kickable(who, users, cx.user_id).await {
+        Ok(target) => target,
+        Err(refusal) => return refusal,
+    };
+    let name = chat::name_of(users, target).await;
+    let user = match chat::kick(users, target, bans::BANNED, "banned").await {
+        Some(user) => user,
+        None => return format!("{} is not online", who),
+    };
+    let duration = Duration::from_secs(minutes.saturating_mul(60));
+    let mut banned = Vec::new();
+    if let Some(addr) = user.addr {
+        bans.ban(&bans::of_addr(addr), duration);
+        banned.push(addr.to_string());
+    }
+    if let Some(subject) = &user.credentials.subject {
+        bans.ban(&bans::of_subject(subject), duration);
+        banned.push(subject.clone());
+    }
+    if banned.is_empty() {
+        return format!("{} was kicked, but there was nothing to ban", name);
+    }
+    format!(
+        "{} is banned for {} minutes: {}",
+        name,
+        minutes,
+        banned.join(", ")
+    )
+}
+
+/// `unban/<who>`, to lift the ban of an address or of a token subject.
+async fn unban(cx: Context<'_>) -> String {
+    let banned = match cx.args.rest(0) {
+        Some(banned) if !banned.is_empty() => banned,
+        _ => return cx.usage(),
+    };
+    let who = match banned.parse::<IpAddr>() {
+        Ok(addr) => bans::of_addr(addr),
+        Err(_) => bans::of_subject(&banned),
+    };
+    if cx.state.bans.unban(&who) {
+        format!("{} is no longer banned", banned)
+    } else {
+        format!("{} is not banned", banned)
+    }
+}
+
+/// The user `who` names for `chatkick` and `ban` by user `user_id`, unless
+/// they are not online, or they are the sender or someone of a higher
+/// role.
+async fn kickable(who: &str, users: &Users, user_id: usize) -> Result<usize, String> {
+    let target = match chat::find_user(users, user_id, who).await {
+        Some(target) => target,
+        None => return Err(format!("{} is not online", who)),
+    };
+    if target == user_id {
+        return Err("you cannot kick yourself".to_string());
+    }
+    if chat::role_of(users, target).await > chat::role_of(users, user_id).await {
+        return Err(format!("you may not kick {}", who));
+    }
+    Ok(target)
+}
+
+/// `secs` seconds as `1h 2m`, `5m 3s` or `12s`.
+fn elapsed(secs: u64) -> String {
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// The videoroom moderation commands act on: the one of `janus.room`, 1234
+/// by default, with its secret in `secrets` or else `janus.room_secret`.
+fn room(state: &State) -> (u64, Option<String>) {
+    let room_id = state.room_id;
+    let secret = secret_of(room_id, None, &state.secrets).or_else(|| state.room_secret.clone());
+    (room_id, secret)
+}
+
+/// The videoroom participant `user_id` of `kick` and the like stands for:
+/// the chat user of that id in the room they joined, or else the
+/// participant of that id in our `room`. Along with the room and its
+/// secret.
+fn participant(user_id: u64, state: &State) -> (u64, u64, Option<String>) {
+    let identity = usize::try_from(user_id)
+        .ok()
+        .and_then(|user_id| state.identities.of_user(user_id));
+    match identity {
+        Some(identity) => {
+            let secret = secret_of(identity.room, None, &state.secrets)
+                .or_else(|| state.room_secret.clone());
+            (identity.room, identity.janus_id, secret)
+        }
+        None => {
+            let (room_id, secret) = room(state);
+            (room_id, user_id, secret)
+        }
+    }
+}
+
+/// The `secret` given for room `room_id`, or else the one in `secrets`.
+fn secret_of(room_id: u64, secret: Option<&str>, secrets: &RoomSecrets) -> Option<String> {
+    match secret {
+        Some(secret) => Some(secret.to_string()),
+        None => secrets.get(room_id).map(|known| known.secret),
+    }
+}
+
+/// Random alphanumeric strings, for secrets and tokens.
+pub fn random_token(len: usize) -> String {
+    iter::repeat(())
+        .map(|()| OsRng.sample(Alphanumeric))
+        .take(len)
+        .collect()
+}
@@ -0,0 +1,172 @@
+//! Small files sent in the chat: every binary message of a chat user is
+//! kept for an hour, and the others of their room get a link to
+//! `GET /files/<id>` instead of the bytes.
+//!
+//! Files are `max_file` of `[limits]` in the config bytes at most, 256 KiB
+//! by default, and every connection may send its `file_quota` bytes of
+//! them, 4 MiB by default.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use super::commands;
+
+/// How long files are kept.
+const KEPT_FOR: Duration = Duration::from_secs(60 * 60);
+
+/// A file sent in the chat.
+#[derive(Clone)]
+struct StoredFile {
+    mime: &'static str,
+    bytes: Arc<Vec<u8>>,
+    sent: Instant,
+}
+
+/// Why a file was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// It is bigger than the limit.
+    TooBig(usize),
+    /// The sender used up their quota, of that many bytes.
+    OverQuota(usize),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::TooBig(max) => write!(f, "files may be {} bytes at most", max),
+            Refusal::OverQuota(quota) => {
+                write!(
+                    f,
+                    "you may send {} bytes of files in all, this one is over",
+                    quota
+                )
+            }
+        }
+    }
+}
+
+/// The files sent in the chat, and how much every user sent.
+#[derive(Clone)]
+pub struct Files {
+    /// The biggest file, in bytes.
+    pub max_size: usize,
+    /// How many bytes of files a user may send.
+    pub quota: usize,
+    files: Arc<Mutex<HashMap<String, StoredFile>>>,
+    sent: Arc<Mutex<HashMap<usize, usize>>>,
+}
+
+impl Default for Files {
+    fn default() -> Files {
+        Files::new(256 * 1024, 4 * 1024 * 1024)
+    }
+}
+
+impl Files {
+    pub fn new(max_size: usize, quota: usize) -> Files {
+        Files {
+            max_size,
+            quota,
+            files: Arc::default(),
+            sent: Arc::default(),
+        }
+    }
+
+    /// Keeps file `bytes` of user `user_id`, unless it is over the limits,
+    /// and returns its id along with its MIME type.
+    pub fn keep(&self, user_id: usize, bytes: Vec<u8>) -> Result<(String, &'static str), Refusal> {
+        if bytes.len() > self.max_size {
+            return Err(Refusal::TooBig(self.max_size));
+        }
+        {
+            let mut sent = self.sent.lock().unwrap();
+            let sent = sent.entry(user_id).or_default();
+            if *sent + bytes.len() > self.quota {
+                return Err(Refusal::OverQuota(self.quota));
+            }
+            *sent += bytes.len();
+        }
+
+        let mut files = self.files.lock().unwrap();
+        files.retain(|_, file| file.sent.elapsed() < KEPT_FOR);
+        let id = commands::random_token(16);
+        let mime = sniff(&bytes);
+        let file = StoredFile {
+            mime,
+            bytes: Arc::new(bytes),
+            sent: Instant::now(),
+        };
+        files.insert(id.clone(), file);
+        Ok((id, mime))
+    }
+
+    /// Forgets how much user `user_id` sent, as they left. Their files
+    /// stay.
+    pub fn user_left(&self, user_id: usize) {
+        self.sent.lock().unwrap().remove(&user_id);
+    }
+
+    fn get(&self, id: &str) -> Option<StoredFile> {
+        let files = self.files.lock().unwrap();
+        files
+            .get(id)
+            .filter(|file| file.sent.elapsed() < KEPT_FOR)
+            .cloned()
+    }
+}
+
+/// The MIME type of `bytes`, from the magic numbers of the usual formats.
+pub fn sniff(bytes: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return mime;
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if bytes.len() >= 8 && &bytes[4..8] == b"ftyp" {
+        return "video/mp4";
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return "text/plain; charset=utf-8";
+    }
+    "application/octet-stream"
+}
+
+/// `GET /files/<id>` with a file sent in the chat, or 404 once it is gone.
+pub fn routes(
+    files: Files,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("files" / String)
+        .and(warp::get())
+        .map(move |id: String| match files.get(&id) {
+            Some(file) => {
+                let bytes = file.bytes.as_ref().clone();
+                let reply = warp::reply::with_header(bytes, "content-type", file.mime);
+                let reply = warp::reply::with_header(reply, "x-content-type-options", "nosniff");
+                warp::reply::with_header(reply, "content-disposition", "attachment").into_response()
+            }
+            None => warp::reply::with_status(
+                warp::reply::json(&json!({ "error": "no such file" })),
+                StatusCode::NOT_FOUND,
+            )
+            .into_response(),
+        })
+}
@@ -0,0 +1,154 @@
+//! What chat messages go through before they are broadcast: a chain of
+//! `MessageFilter`s, which may change them or drop them silently.
+//!
+//! The chain is set up at startup with `filters` of `[chat]` in the config,
+//! such as `["drop", "profanity", "links"]`, out of `NAMES`:
+//!
+//! - `profanity`, masking the words of its `profanity`,
+//! - `links`, taking out the links,
+//! - `drop`, dropping the messages with any of the words of its
+//!   `drop_words`.
+//!
+//! Applications may add filters of their own with `Filters::with`.
+
+use std::sync::Arc;
+
+/// The filters `Filters::named` knows.
+pub const NAMES: &[&str] = &["profanity", "links", "drop"];
+
+/// Looks at a chat message before it is broadcast.
+pub trait MessageFilter: Send + Sync {
+    /// What becomes of `body`, sent by `sender` in chat room `room`: the
+    /// body to broadcast, changed or not, or `None` to drop it.
+    fn filter(&self, room: &str, sender: &str, body: String) -> Option<String>;
+}
+
+/// The filters chat messages go through, one after the other.
+#[derive(Clone, Default)]
+pub struct Filters {
+    chain: Vec<Arc<dyn MessageFilter>>,
+}
+
+impl Filters {
+    /// The filters of `names`, in order, with the words of `profanity` and
+    /// `drop_words`.
+    pub fn named(names: &[String], profanity: &[String], drop_words: &[String]) -> Filters {
+        let mut filters = Filters::default();
+        for name in names {
+            filters = match name.as_str() {
+                "profanity" => filters.with(Profanity::new(lowercase(profanity))),
+                "links" => filters.with(StripLinks),
+                "drop" => filters.with(DropWords::new(lowercase(drop_words))),
+                _ => {
+                    eprintln!("unknown chat filter ignored: {}", name);
+                    filters
+                }
+            };
+        }
+        filters
+    }
+
+    /// These filters, then `filter`.
+    pub fn with<F>(mut self, filter: F) -> Filters
+    where
+        F: MessageFilter + 'static,
+    {
+        self.chain.push(Arc::new(filter));
+        self
+    }
+
+    /// Puts `body` through every filter, until one drops it.
+    pub fn apply(&self, room: &str, sender: &str, body: &str) -> Option<String> {
+        self.chain
+            .iter()
+            .try_fold(body.to_string(), |body, filter| {
+                filter.filter(room, sender, body)
+            })
+    }
+}
+
+/// `words`, in lowercase.
+fn lowercase(words: &[String]) -> Vec<String> {
+    words.iter().map(|word| word.to_lowercase()).collect()
+}
+
+/// Whether `token`, without the punctuation around it, is one of `words`.
+fn is_one_of(token: &str, words: &[String]) -> bool {
+    let word = token
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    words.contains(&word)
+}
+
+/// Masks some words with `*`, whatever their case.
+pub struct Profanity {
+    words: Vec<String>,
+}
+
+impl Profanity {
+    pub fn new(words: Vec<String>) -> Profanity {
+        Profanity { words }
+    }
+}
+
+impl MessageFilter for Profanity {
+    fn filter(&self, _room: &str, _sender: &str, body: String) -> Option<String> {
+        let masked = body
+            .split_inclusive(char::is_whitespace)
+            .map(|token| {
+                if is_one_of(token, &self.words) {
+                    token
+                        .chars()
+                        .map(|c| if c.is_alphanumeric() { '*' } else { c })
+                        .collect()
+                } else {
+                    token.to_string()
+                }
+            })
+            .collect();
+        Some(masked)
+    }
+}
+
+/// Takes the `http://`, `https://` and `www.` links out.
+pub struct StripLinks;
+
+impl MessageFilter for StripLinks {
+    fn filter(&self, _room: &str, _sender: &str, body: String) -> Option<String> {
+        let stripped = body
+            .split_inclusive(char::is_whitespace)
+            .map(|token| {
+                let link = ["http://", "https://", "www."]
+                    .iter()
+                    .any(|scheme| token.to_lowercase().starts_with(scheme));
+                if link {
+                    let space = &token[token.trim_end().len()..];
+                    format!("[link removed]{}", space)
+                } else {
+                    token.to_string()
+                }
+            })
+            .collect();
+        Some(stripped)
+    }
+}
+
+/// Drops the messages with some words, whatever their case.
+pub struct DropWords {
+    words: Vec<String>,
+}
+
+impl DropWords {
+    pub fn new(words: Vec<String>) -> DropWords {
+        DropWords { words }
+    }
+}
+
+impl MessageFilter for DropWords {
+    fn filter(&self, _room: &str, _sender: &str, body: String) -> Option<String> {
+        let dropped = body
+            .split_whitespace()
+            .any(|token| is_one_of(token, &self.words));
+        Some(body).filter(|_| !dropped)
+    }
+}
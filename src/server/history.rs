@@ -0,0 +1,276 @@
+//! The last messages of every chat room, replayed to the users joining it
+//! so that they do not come into an empty chat.
+//!
+//! Every chat message of a room gets the next sequence number of the room,
+//! and reaches its users in that order: the numbers are handed out and the
+//! messages sent under the same lock. The messages of the users in a
+//! TextRoom are not ordered along, as they go through Janus.
+//!
+//! The messages kept can get reactions, as emojis of the users of the
+//! room, and be edited or deleted.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use super::protocol::Outbound;
+
+/// The last `capacity` messages of every chat room, oldest first.
+#[derive(Clone)]
+pub struct History {
+    capacity: usize,
+    rooms: Arc<Mutex<HashMap<String, RoomLog>>>,
+}
+
+/// The messages of a chat room, and the sequence number of its last one.
+#[derive(Default)]
+struct RoomLog {
+    seq: u64,
+    messages: VecDeque<Outbound>,
+    /// The identity of the sender of every message, see `mutes::identity`,
+    /// by sequence number.
+    senders: HashMap<u64, String>,
+    /// The users who reacted to the messages with every emoji, by
+    /// sequence number.
+    reactions: HashMap<u64, BTreeMap<String, BTreeSet<usize>>>,
+}
+
+/// How many users reacted to a message with every emoji.
+pub type Reactions = BTreeMap<String, usize>;
+
+impl History {
+    /// Keeps up to `capacity` messages per room, none at all with 0.
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            rooms: Arc::default(),
+        }
+    }
+
+    /// Gives chat message `message` of `sender` in chat room `room` its
+    /// sequence number, hands it to `fan_out` to send it, and keeps it,
+    /// forgetting the oldest one of the room if it is full. Returns its
+    /// number.
+    pub fn publish<F>(&self, room: &str, sender: &str, mut message: Outbound, fan_out: F) -> u64
+    where
+        F: FnOnce(&Outbound),
+    {
+        let mut rooms = self.rooms.lock().unwrap();
+        let log = rooms.entry(room.to_string()).or_default();
+        log.seq += 1;
+        message.sequence(log.seq);
+        fan_out(&message);
+        if self.capacity > 0 {
+            if log.messages.len() == self.capacity {
+                let forgotten = log.messages.pop_front().and_then(|message| message.seq());
+                if let Some(seq) = forgotten {
+                    log.reactions.remove(&seq);
+                    log.senders.remove(&seq);
+                }
+            }
+            log.messages.push_back(message);
+            log.senders.insert(log.seq, sender.to_string());
+        }
+        log.seq
+    }
+
+    /// Adds the reaction `emoji` of user `user_id` to message `seq` of chat
+    /// room `room`, or takes it back if they reacted so already. Tells
+    /// which it was, along with the reactions to the message, unless it is
+    /// not kept.
+    pub fn react(
+        &self,
+        room: &str,
+        seq: u64,
+        emoji: &str,
+        user_id: usize,
+    ) -> Option<(bool, Reactions)> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let log = rooms.get_mut(room)?;
+        if !log
+            .messages
+            .iter()
+            .any(|message| message.seq() == Some(seq))
+        {
+            return None;
+        }
+        let reactions = log.reactions.entry(seq).or_default();
+        let users = reactions.entry(emoji.to_string()).or_default();
+        let added = users.insert(user_id);
+        if !added {
+            users.remove(&user_id);
+            if users.is_empty() {
+                reactions.remove(emoji);
+            }
+        }
+        let counts = reactions
+            .iter()
+            .map(|(emoji, users)| (emoji.clone(), users.len()))
+            .collect();
+        Some((added, counts))
+    }
+
+    /// The identity of the sender of message `seq` of chat room `room`,
+    /// unless it is not kept.
+    pub fn sender(&self, room: &str, seq: u64) -> Option<String> {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(room)?.senders.get(&seq).cloned()
+    }
+
+    /// Changes chat message `seq` of chat room `room` into `body`, and tells
+    /// whether there was such a message kept. Files are not changed.
+    pub fn edit(&self, room: &str, seq: u64, body: &str) -> bool {
+        let mut rooms = self.rooms.lock().unwrap();
+        let message = rooms.get_mut(room).and_then(|log| {
+            log.messages
+                .iter_mut()
+                .find(|message| message.seq() == Some(seq))
+        });
+        match message {
+            Some(Outbound::Chat {
+                body: kept, edited, ..
+            }) => {
+                *kept = body.to_string();
+                *edited = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets message `seq` of chat room `room`, along with its reactions,
+    /// and tells whether it was kept.
+    pub fn delete(&self, room: &str, seq: u64) -> bool {
+        let mut rooms = self.rooms.lock().unwrap();
+        let log = match rooms.get_mut(room) {
+            Some(log) => log,
+            None => return false,
+        };
+        let before = log.messages.len();
+        log.messages.retain(|message| message.seq() != Some(seq));
+        log.reactions.remove(&seq);
+        log.senders.remove(&seq);
+        log.messages.len() < before
+    }
+
+    /// The sequence number of the last message of chat room `room`, 0
+    /// before the first one.
+    pub fn last_seq(&self, room: &str) -> u64 {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(room).map_or(0, |log| log.seq)
+    }
+
+    /// The last messages of chat room `room`, oldest first, or only those
+    /// after sequence number `since`.
+    pub fn replay(&self, room: &str, since: Option<u64>) -> Vec<Outbound> {
+        let rooms = self.rooms.lock().unwrap();
+        let since = since.unwrap_or(0);
+        match rooms.get(room) {
+            Some(log) => log
+                .messages
+                .iter()
+                .filter(|message| message.seq().unwrap_or(0) > since)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(history: &History, room: &str, body: &str) -> u64 {
+        history.publish(room, "user:1", Outbound::chat(room, "bob", body), |_| ())
+    }
+
+    fn bodies(history: &History, room: &str) -> Vec<String> {
+        history
+            .replay(room, None)
+            .into_iter()
+            .filter_map(|message| match message {
+                Outbound::Chat { body, .. } => Some(body),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_room_numbers_its_messages() {
+        let history = History::new(10);
+
+        assert_eq!(history.last_seq("a"), 0);
+        assert_eq!(publish(&history, "a", "one"), 1);
+        assert_eq!(publish(&history, "a", "two"), 2);
+        assert_eq!(publish(&history, "b", "one"), 1);
+        assert_eq!(history.last_seq("a"), 2);
+    }
+
+    #[test]
+    fn messages_are_numbered_even_when_none_are_kept() {
+        let history = History::new(0);
+
+        assert_eq!(publish(&history, "a", "one"), 1);
+        assert_eq!(publish(&history, "a", "two"), 2);
+        assert!(history.replay("a", None).is_empty());
+    }
+
+    #[test]
+    fn the_oldest_messages_are_forgotten() {
+        let history = History::new(2);
+        for body in &["one", "two", "three"] {
+            publish(&history, "a", body);
+        }
+
+        assert_eq!(bodies(&history, "a"), vec!["two", "three"]);
+        assert_eq!(history.sender("a", 1), None);
+        assert_eq!(history.sender("a", 3), Some("user:1".to_string()));
+    }
+
+    #[test]
+    fn replay_since_skips_what_was_read() {
+        let history = History::new(10);
+        for body in &["one", "two", "three"] {
+            publish(&history, "a", body);
+        }
+
+        let seqs: Vec<_> = history
+            .replay("a", Some(1))
+            .iter()
+            .filter_map(Outbound::seq)
+            .collect();
+        assert_eq!(seqs, vec![2, 3]);
+    }
+
+    #[test]
+    fn edit_changes_the_body_and_marks_it() {
+        let history = History::new(10);
+        let seq = publish(&history, "a", "helo");
+
+        assert!(history.edit("a", seq, "hello"));
+        assert!(!history.edit("a", seq + 1, "nope"));
+        assert!(!history.edit("b", seq, "nope"));
+        match &history.replay("a", None)[0] {
+            Outbound::Chat { body, edited, .. } => {
+                assert_eq!(body, "hello");
+                assert!(edited);
+            }
+            other => panic!("not a chat message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn delete_forgets_the_message_and_its_reactions() {
+        let history = History::new(10);
+        let first = publish(&history, "a", "one");
+        publish(&history, "a", "two");
+        history.react("a", first, "👍", 2);
+
+        assert!(history.delete("a", first));
+        assert!(!history.delete("a", first));
+        assert_eq!(bodies(&history, "a"), vec!["two"]);
+        assert_eq!(history.react("a", first, "👍", 2), None);
+        assert_eq!(history.sender("a", first), None);
+        assert_eq!(publish(&history, "a", "three"), 3);
+    }
+}
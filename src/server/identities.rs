@@ -0,0 +1,72 @@
+//! Who is who between the chat and the videorooms: the chat user behind a
+//! videoroom participant and the other way around, with the display name
+//! they joined with.
+//!
+//! It is kept up to date as chat users join and leave videorooms, and as
+//! the plugin tells about participants leaving.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A chat user as a participant of a videoroom.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub user_id: usize,
+    pub room: u64,
+    /// Their id in the videoroom.
+    pub janus_id: u64,
+    pub display: Option<String>,
+}
+
+/// The identities of the chat users in videorooms.
+#[derive(Clone, Default)]
+pub struct Identities {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    by_user: HashMap<usize, Identity>,
+    /// The chat user of every participant id.
+    by_janus: HashMap<u64, usize>,
+}
+
+impl Identities {
+    /// Chat user `identity.user_id` joined a videoroom as a publisher.
+    pub fn joined(&self, identity: Identity) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(previous) = inner.by_user.remove(&identity.user_id) {
+            inner.by_janus.remove(&previous.janus_id);
+        }
+        inner.by_janus.insert(identity.janus_id, identity.user_id);
+        inner.by_user.insert(identity.user_id, identity);
+    }
+
+    /// Chat user `user_id` left their videoroom.
+    pub fn user_left(&self, user_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(identity) = inner.by_user.remove(&user_id) {
+            inner.by_janus.remove(&identity.janus_id);
+        }
+    }
+
+    /// Participant `janus_id` left their videoroom, and returns the chat
+    /// user they were, if any.
+    pub fn participant_left(&self, janus_id: u64) -> Option<Identity> {
+        let mut inner = self.inner.lock().unwrap();
+        let user_id = inner.by_janus.remove(&janus_id)?;
+        inner.by_user.remove(&user_id)
+    }
+
+    /// The identity of chat user `user_id` in their videoroom.
+    pub fn of_user(&self, user_id: usize) -> Option<Identity> {
+        self.inner.lock().unwrap().by_user.get(&user_id).cloned()
+    }
+
+    /// The identity of participant `janus_id`, if they are a chat user.
+    pub fn of_participant(&self, janus_id: u64) -> Option<Identity> {
+        let inner = self.inner.lock().unwrap();
+        let user_id = inner.by_janus.get(&janus_id)?;
+        inner.by_user.get(user_id).cloned()
+    }
+}
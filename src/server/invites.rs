@@ -0,0 +1,168 @@
+//! Invite-only chat rooms: `private` makes the chat room of the sender
+//! invite-only, with them as its owner, and `invitetoken` hands out the
+//! tokens others join it with, as `?invite=<token>`: single-use ones, or
+//! ones good for some minutes. Users coming back with a resume token are
+//! let in again.
+//!
+//! The videoroom of a private chat room only lets in its members, with the
+//! `allowed` tokens of the plugin: every member gets one of their own,
+//! which fills in the "token" of their publish and subscribe signals.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::commands;
+
+/// The close code of the websockets of users joining a private chat room
+/// without an invite.
+pub const NOT_INVITED: u16 = 4005;
+
+/// An invite to a private chat room.
+#[derive(Clone, Copy, Debug)]
+struct Invite {
+    /// Until when it may be used, or `None` for once.
+    expires: Option<Instant>,
+}
+
+impl Invite {
+    fn is_valid(&self) -> bool {
+        self.expires.is_none_or(|expires| expires > Instant::now())
+    }
+}
+
+/// A private chat room.
+#[derive(Clone, Debug, Default)]
+struct PrivateRoom {
+    /// The identity of its owner, see `mutes::identity`.
+    owner: String,
+    invites: HashMap<String, Invite>,
+    /// The `allowed` token of every member in its videoroom, by user id.
+    members: HashMap<usize, String>,
+}
+
+/// The private chat rooms, by name.
+#[derive(Clone, Default)]
+pub struct Invites {
+    rooms: Arc<Mutex<HashMap<String, PrivateRoom>>>,
+}
+
+impl Invites {
+    /// Whether chat room `room` is private.
+    pub fn is_private(&self, room: &str) -> bool {
+        self.rooms.lock().unwrap().contains_key(room)
+    }
+
+    /// Whether `identity` owns private chat room `room`.
+    pub fn is_owner(&self, room: &str, identity: &str) -> bool {
+        let rooms = self.rooms.lock().unwrap();
+        rooms
+            .get(room)
+            .is_some_and(|private| private.owner == identity)
+    }
+
+    /// Makes chat room `room` private, owned by `owner`, with `members` in
+    /// it, and returns their `allowed` tokens, or `None` if it is private
+    /// already.
+    pub fn make_private(
+        &self,
+        room: &str,
+        owner: &str,
+        members: impl IntoIterator<Item = usize>,
+    ) -> Option<Vec<String>> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.contains_key(room) {
+            return None;
+        }
+        let members: HashMap<usize, String> = members
+            .into_iter()
+            .map(|user_id| (user_id, commands::random_token(16)))
+            .collect();
+        let tokens = members.values().cloned().collect();
+        let private = PrivateRoom {
+            owner: owner.to_string(),
+            members,
+            ..PrivateRoom::default()
+        };
+        rooms.insert(room.to_string(), private);
+        Some(tokens)
+    }
+
+    /// Lets anyone into chat room `room` again, and tells whether it was
+    /// private.
+    pub fn make_public(&self, room: &str) -> bool {
+        self.rooms.lock().unwrap().remove(room).is_some()
+    }
+
+    /// A new invite to private chat room `room`, used once without
+    /// `lifetime` or as many times as needed for `lifetime`, unless the room
+    /// is not private or `lifetime` is too long to tell when it ends.
+    pub fn issue(&self, room: &str, lifetime: Option<Duration>) -> Option<String> {
+        let invite = Invite {
+            expires: match lifetime {
+                Some(lifetime) => Some(Instant::now().checked_add(lifetime)?),
+                None => None,
+            },
+        };
+        let token = commands::random_token(16);
+        let mut rooms = self.rooms.lock().unwrap();
+        let private = rooms.get_mut(room)?;
+        private.invites.retain(|_, invite| invite.is_valid());
+        private.invites.insert(token.clone(), invite);
+        Some(token)
+    }
+
+    /// Whether `identity` may join chat room `room` with `invite`, which is
+    /// used up if it is a single-use one. Anyone may join a chat room that
+    /// is not private, and its owner needs no invite.
+    pub fn admit(&self, room: &str, identity: &str, invite: Option<&str>) -> bool {
+        let mut rooms = self.rooms.lock().unwrap();
+        let private = match rooms.get_mut(room) {
+            Some(private) => private,
+            None => return true,
+        };
+        if private.owner == identity {
+            return true;
+        }
+        let invite = match invite {
+            Some(invite) => invite,
+            None => return false,
+        };
+        match private.invites.get(invite).copied() {
+            Some(found) if found.is_valid() => {
+                if found.expires.is_none() {
+                    private.invites.remove(invite);
+                }
+                true
+            }
+            Some(_) => {
+                private.invites.remove(invite);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Makes user `user_id` a member of private chat room `room`, and
+    /// returns their new `allowed` token.
+    pub fn join(&self, room: &str, user_id: usize) -> Option<String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let private = rooms.get_mut(room)?;
+        let token = commands::random_token(16);
+        private.members.insert(user_id, token.clone());
+        Some(token)
+    }
+
+    /// Forgets member `user_id` of private chat room `room`, and returns
+    /// their `allowed` token.
+    pub fn leave(&self, room: &str, user_id: usize) -> Option<String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.get_mut(room)?.members.remove(&user_id)
+    }
+
+    /// The `allowed` token of member `user_id` of private chat room `room`.
+    pub fn media_token(&self, room: &str, user_id: usize) -> Option<String> {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(room)?.members.get(&user_id).cloned()
+    }
+}
@@ -0,0 +1,23 @@
+//! How half-open chat connections are found out: every websocket is pinged
+//! every `ping_secs` of `[chat]` in the config, 30 by default, and dropped
+//! once it missed `ping_misses` pongs in a row, 3 by default, so that the
+//! users of a browser long gone leave their room.
+
+use std::time::Duration;
+
+/// When chat websockets are pinged, and when they are given up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    /// How many pings in a row may go unanswered.
+    pub misses: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Keepalive {
+        Keepalive {
+            interval: Duration::from_secs(30),
+            misses: 3,
+        }
+    }
+}
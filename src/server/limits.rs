@@ -0,0 +1,27 @@
+//! How big the messages of chat users may be: chat messages and commands
+//! longer than `max_text` of `[limits]` in the config, 4 KiB by default,
+//! are refused with an error, while websocket messages and frames over its
+//! `max_message`, 64 KiB by default so that SDPs fit, are not even read and
+//! close the websocket with `MESSAGE_TOO_BIG`.
+
+/// The close code of the websockets of users sending messages over the
+/// limit.
+pub const MESSAGE_TOO_BIG: u16 = 1009;
+
+/// The largest messages chat users may send, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SizeLimits {
+    /// For chat messages and commands.
+    pub text: usize,
+    /// For any websocket message or frame, signals included.
+    pub message: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> SizeLimits {
+        SizeLimits {
+            text: 4 * 1024,
+            message: 64 * 1024,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! The warp server: the chat, and a look into the gateway for operators.
+
+pub mod admin;
+pub mod auth;
+pub mod bans;
+pub mod chat;
+pub mod commands;
+pub mod files;
+pub mod filters;
+pub mod history;
+pub mod identities;
+pub mod invites;
+pub mod keepalive;
+pub mod limits;
+pub mod mutes;
+pub mod profiles;
+pub mod protocol;
+pub mod provision;
+pub mod ratelimit;
+pub mod roles;
+pub mod router;
+pub mod sessions;
+#[cfg(feature = "sqlite")]
+pub mod store;
+
+use std::convert::Infallible;
+
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::Filter;
+
+use crate::janus::videoroom::{self, Forwarders, Recordings, RoomFilter, RoomSecrets};
+use crate::janus::{echotest, recordplay, streaming};
+use crate::janus::{AdminClient, JanusClient};
+
+/// What the chat and its commands share.
+#[derive(Clone)]
+pub struct State {
+    pub users: chat::Users,
+    pub janus: JanusClient,
+    /// The RTP forwarders started through the admin routes, which go away
+    /// with their room.
+    pub forwarders: Forwarders,
+    /// The rooms recording was turned on for with the record command.
+    pub recordings: Recordings,
+    /// The secrets and pins of the rooms created with the createroom
+    /// command.
+    pub secrets: RoomSecrets,
+    pub subscriptions: chat::Subscriptions,
+    /// The chat users in every videoroom, who hear about its media events.
+    pub router: router::RoomRouter,
+    /// The chat users behind the videoroom participants.
+    pub identities: identities::Identities,
+    pub textroom_users: chat::TextRoomUsers,
+    /// The videorooms of the chat rooms.
+    pub provisioner: provision::Provisioner,
+    /// The videoroom the moderation commands act on when they name none.
+    pub room_id: u64,
+    /// The `admin_key` of the videoroom plugin, for creating videorooms.
+    pub admin_key: Option<String>,
+    /// The secret of the videorooms `secrets` has none for.
+    pub room_secret: Option<String>,
+    /// The secret of the TextRooms we create.
+    pub textroom_secret: Option<String>,
+    /// The plugins besides ours chat users may send messages to.
+    pub custom_plugins: Vec<String>,
+    /// Where the `recorduser` command records.
+    pub recording_filename: String,
+    /// How long the `ban` command bans for when it is not told, in minutes.
+    pub ban_minutes: u64,
+    /// The last messages of every chat room.
+    pub history: history::History,
+    /// How chat users authenticate.
+    pub auth: auth::ChatAuth,
+    /// The role of chat users whose token tells none.
+    pub default_role: roles::Role,
+    /// The chat users kept out for a while.
+    pub bans: bans::Bans,
+    /// Who every chat user muted.
+    pub mutes: mutes::Mutes,
+    /// The private chat rooms, and their invites.
+    pub invites: invites::Invites,
+    /// How fast chat users may send messages, as fast as they like without
+    /// it.
+    pub rate_limit: Option<ratelimit::RateLimit>,
+    /// How fast chat users may send WebRTC signaling, as fast as they like
+    /// without it.
+    pub signal_rate_limit: Option<ratelimit::RateLimit>,
+    /// How big the messages of chat users may be.
+    pub size_limits: limits::SizeLimits,
+    /// What chat messages go through before they are broadcast.
+    pub filters: filters::Filters,
+    /// The files sent in the chat.
+    pub files: files::Files,
+    /// When chat websockets are pinged.
+    pub keepalive: keepalive::Keepalive,
+    /// The chat users who left lately, and may come back.
+    pub sessions: sessions::Sessions,
+    /// Every message of the chat rooms, when they are kept on disk.
+    #[cfg(feature = "sqlite")]
+    pub store: Option<store::Store>,
+}
+
+/// Every route of the server.
+pub fn routes(
+    state: State,
+    admin: AdminClient,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let janus = state.janus.clone();
+    let admin = admin::routes(
+        admin,
+        janus.clone(),
+        state.forwarders.clone(),
+        state.users.clone(),
+    );
+    #[cfg(feature = "sqlite")]
+    let store = store::routes(state.clone());
+    let files = files::routes(state.files.clone());
+    let chat = chat::routes(state).or(files);
+    #[cfg(feature = "sqlite")]
+    let chat = chat.or(store);
+    let with_janus = warp::any().map(move || janus.clone());
+
+    // GET /janus/info -> version, transports and plugins of the gateway
+    let janus_info = warp::path!("janus" / "info")
+        .and(warp::get())
+        .and(with_janus.clone())
+        .map(|janus: JanusClient| match janus.info() {
+            Some(info) => warp::reply::with_status(warp::reply::json(&info), StatusCode::OK),
+            None => warp::reply::with_status(
+                warp::reply::json(&json!({ "error": "not connected to janus yet" })),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+        });
+
+    // GET /rooms -> the public videorooms, filtered by the query string,
+    // e.g. ?prefix=team&hide_pin_required=true
+    let rooms = warp::path!("rooms")
+        .and(warp::get())
+        .and(warp::query::<RoomFilter>())
+        .and(with_janus.clone())
+        .and_then(|filter: RoomFilter, janus: JanusClient| async move {
+            let reply = match videoroom::list_rooms(&janus, &filter).await {
+                Ok(rooms) => warp::reply::with_status(warp::reply::json(&rooms), StatusCode::OK),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": e.to_string() })),
+                    StatusCode::BAD_GATEWAY,
+                ),
+            };
+            Ok::<_, Infallible>(reply)
+        });
+
+    // GET /mountpoints -> the public mountpoints of the Streaming plugin,
+    // which chat users can watch
+    let mountpoints = warp::path!("mountpoints")
+        .and(warp::get())
+        .and(with_janus.clone())
+        .and_then(|janus: JanusClient| async move {
+            let reply = match streaming::list_mountpoints(&janus).await {
+                Ok(list) => warp::reply::with_status(warp::reply::json(&list), StatusCode::OK),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": e.to_string() })),
+                    StatusCode::BAD_GATEWAY,
+                ),
+            };
+            Ok::<_, Infallible>(reply)
+        });
+
+    // GET /recordings -> the recordings of the Record&Play plugin, which
+    // chat users can play back
+    let recordings = warp::path!("recordings")
+        .and(warp::get())
+        .and(with_janus.clone())
+        .and_then(|janus: JanusClient| async move {
+            let reply = match recordplay::list_recordings(&janus).await {
+                Ok(list) => warp::reply::with_status(warp::reply::json(&list), StatusCode::OK),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": e.to_string() })),
+                    StatusCode::BAD_GATEWAY,
+                ),
+            };
+            Ok::<_, Infallible>(reply)
+        });
+
+    // GET /debug/janus -> a round trip through the EchoTest plugin, with
+    // how long its steps took, to check the signaling path to the gateway
+    let debug_janus = warp::path!("debug" / "janus")
+        .and(warp::get())
+        .and(with_janus.clone())
+        .and_then(|janus: JanusClient| async move {
+            let reply = match echotest::round_trip(&janus).await {
+                Ok(round_trip) => {
+                    let mut body = json!(round_trip);
+                    body["ok"] = json!(true);
+                    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&json!({ "ok": false, "error": e.to_string() })),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ),
+            };
+            Ok::<_, Infallible>(reply)
+        });
+
+    // GET /metrics -> latency of the gateway's replies, for Prometheus
+    let metrics = warp::path!("metrics")
+        .and(warp::get())
+        .and(with_janus)
+        .map(|janus: JanusClient| janus.metrics().render());
+
+    chat.or(janus_info)
+        .or(rooms)
+        .or(mountpoints)
+        .or(recordings)
+        .or(debug_janus)
+        .or(metrics)
+        .or(admin)
+}
@@ -0,0 +1,132 @@
+//! Who chat users would rather not hear: every user may mute others with
+//! the `chatmute` command, whose new chat messages, files, private messages
+//! and mentions then no longer reach them over the websocket. The history
+//! of the room is replayed as it is.
+//!
+//! Mute lists are kept by identity, the subject of the JWT of the user or
+//! else their user id, which they keep when they come back with a resume
+//! token. Those between JWT subjects are saved to a JSON file so that they
+//! outlive a restart as well, while user ids start over with it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+
+use super::auth::Credentials;
+use super::bans;
+
+/// The identity of chat user `user_id` with `credentials`, which mute
+/// lists are kept under.
+pub fn identity(user_id: usize, credentials: &Credentials) -> String {
+    match &credentials.subject {
+        Some(subject) => bans::of_subject(subject),
+        None => format!("user:{}", user_id),
+    }
+}
+
+/// Whether `identity` stays the same after a restart.
+fn lasting(identity: &str) -> bool {
+    identity.starts_with("sub:")
+}
+
+/// The mute lists, by the identity of their owner.
+#[derive(Clone)]
+pub struct Mutes {
+    path: PathBuf,
+    /// The identities every user muted, along with the name they went by
+    /// then.
+    lists: Arc<Mutex<HashMap<String, BTreeMap<String, String>>>>,
+}
+
+impl Mutes {
+    /// The mute lists saved to `path`, if any.
+    pub fn load(path: impl Into<PathBuf>) -> Mutes {
+        let path = path.into();
+        let lists = match fs::read(&path) {
+            Ok(saved) => serde_json::from_slice(&saved).unwrap_or_else(|e| {
+                eprintln!("chat mutes in {} ignored: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Mutes {
+            path,
+            lists: Arc::new(Mutex::new(lists)),
+        }
+    }
+
+    /// Mutes `target`, going by `name`, for `owner`, and tells whether it
+    /// was not already.
+    pub fn mute(&self, owner: &str, target: &str, name: &str) -> bool {
+        let mut lists = self.lists.lock().unwrap();
+        let list = lists.entry(owner.to_string()).or_default();
+        let muted = list.insert(target.to_string(), name.to_string()).is_none();
+        if lasting(owner) && lasting(target) {
+            self.save(&lists);
+        }
+        muted
+    }
+
+    /// Unmutes `who` for `owner`, an identity or the name it went by when
+    /// it was muted, and returns that name if it was muted.
+    pub fn unmute(&self, owner: &str, who: &str) -> Option<String> {
+        let mut lists = self.lists.lock().unwrap();
+        let list = lists.get_mut(owner)?;
+        let target = list
+            .iter()
+            .find(|(target, name)| *target == who || name.eq_ignore_ascii_case(who))
+            .map(|(target, _)| target.clone())?;
+        let name = list.remove(&target);
+        if list.is_empty() {
+            lists.remove(owner);
+        }
+        if lasting(owner) && lasting(&target) {
+            self.save(&lists);
+        }
+        name
+    }
+
+    /// The names of those `owner` muted.
+    pub fn muted(&self, owner: &str) -> Vec<String> {
+        let lists = self.lists.lock().unwrap();
+        lists
+            .get(owner)
+            .map(|list| list.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The identities who muted `sender`.
+    pub fn muting(&self, sender: &str) -> HashSet<String> {
+        let lists = self.lists.lock().unwrap();
+        lists
+            .iter()
+            .filter(|(_, list)| list.contains_key(sender))
+            .map(|(owner, _)| owner.clone())
+            .collect()
+    }
+
+    /// Saves the lists of lasting identities.
+    fn save(&self, lists: &HashMap<String, BTreeMap<String, String>>) {
+        let kept: HashMap<&String, BTreeMap<&String, &String>> = lists
+            .iter()
+            .filter(|(owner, _)| lasting(owner))
+            .map(|(owner, list)| {
+                let list: BTreeMap<_, _> =
+                    list.iter().filter(|(target, _)| lasting(target)).collect();
+                (owner, list)
+            })
+            .filter(|(_, list)| !list.is_empty())
+            .collect();
+        let saved = fs::write(&self.path, json!(kept).to_string());
+        if let Err(e) = saved {
+            eprintln!(
+                "chat mutes could not be saved to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
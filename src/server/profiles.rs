@@ -0,0 +1,108 @@
+//! What chat users tell about themselves besides their nickname: a display
+//! name, the URL of an avatar and a status, set with the `profile` command
+//! and shown in the roster and the presence of their room.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// The longest display name, in characters.
+const MAX_DISPLAY_NAME: usize = 64;
+/// The longest avatar URL, in bytes.
+const MAX_AVATAR: usize = 512;
+/// The longest status, in characters.
+const MAX_STATUS: usize = 140;
+
+/// The profile of a chat user, every field of it optional.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// An `http://` or `https://` URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// One of the fields of a `Profile`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    DisplayName,
+    Avatar,
+    Status,
+}
+
+impl Field {
+    /// The field named `name`, as the `profile` command writes it.
+    pub fn of(name: &str) -> Option<Field> {
+        match name {
+            "name" => Some(Field::DisplayName),
+            "avatar" => Some(Field::Avatar),
+            "status" => Some(Field::Status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::DisplayName => "display name",
+            Field::Avatar => "avatar",
+            Field::Status => "status",
+        })
+    }
+}
+
+impl Profile {
+    /// Whether nothing is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Profile::default()
+    }
+
+    /// Sets `field` to `value`, or clears it with an empty one, unless
+    /// `value` cannot be one: the answer tells why.
+    pub fn set(&mut self, field: Field, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let valid = match field {
+            Field::DisplayName => value.chars().count() <= MAX_DISPLAY_NAME && printable(value),
+            Field::Avatar => {
+                value.is_empty()
+                    || value.len() <= MAX_AVATAR
+                        && (value.starts_with("https://") || value.starts_with("http://"))
+                        && !value.chars().any(|c| c.is_whitespace() || c.is_control())
+            }
+            Field::Status => value.chars().count() <= MAX_STATUS && printable(value),
+        };
+        if !valid {
+            return Err(match field {
+                Field::DisplayName => format!(
+                    "a display name is up to {} characters, without control characters",
+                    MAX_DISPLAY_NAME
+                ),
+                Field::Avatar => format!(
+                    "an avatar is an http:// or https:// URL of up to {} bytes",
+                    MAX_AVATAR
+                ),
+                Field::Status => format!(
+                    "a status is up to {} characters, without control characters",
+                    MAX_STATUS
+                ),
+            });
+        }
+
+        let value = Some(value.to_string()).filter(|value| !value.is_empty());
+        match field {
+            Field::DisplayName => self.display_name = value,
+            Field::Avatar => self.avatar = value,
+            Field::Status => self.status = value,
+        }
+        Ok(())
+    }
+}
+
+/// Whether `text` is without control characters.
+fn printable(text: &str) -> bool {
+    !text.chars().any(char::is_control)
+}
@@ -0,0 +1,356 @@
+//! The JSON protocol of the chat, version `VERSION`, which clients ask for
+//! with `?v=1` when they connect. Those asking for another version get an
+//! "error" and their websocket is closed with `UNSUPPORTED_VERSION`.
+//! Without it, chat messages and system messages are plain text such as
+//! `<bob>: hi` and `<Janus>: ...`, and commands are written `name/arg/arg`.
+//!
+//! Clients send, whatever protocol they asked for,
+//!
+//! - {"type": "chat", "room": "lobby", "body": "hi"}, with the chat room of
+//!   their websocket or no "room" at all
+//! - {"type": "command", "name": "nick", "args": ["bob"]}, whose args are
+//!   taken as they are, `/` and all, unlike those of `nick/bob`
+//! - {"type": "history", "since": 42}, for the chat messages missed, such
+//!   as after a reconnect; `?since=42` when connecting does the same
+//! - {"type": "read", "seq": 42}, once the chat messages up to 42 are read
+//!
+//! All of them but "read" may come with an "id" of their own, such as
+//! "id": "m-42", to hear back about them: chat messages are acknowledged
+//! with an "ack" once they are sent to the room, commands with their
+//! "reply", and whatever is refused with an "error", all of them with the
+//! same "id".
+//!
+//! They get, with the JSON protocol,
+//!
+//! - {"v": 1, "type": "chat", "room": "lobby", "from": "bob", "body": "hi", "seq": 42},
+//!   where "seq" grows by one with every message of the room, which is
+//!   sent in that order, or with "private": true and no "seq" for the
+//!   messages of the `msg` command
+//! - {"v": 1, "type": "file", "room": "lobby", "from": "bob", "url": "/files/x1Y2", "mime": "image/png", "size": 5120, "seq": 43},
+//!   for the binary messages of the room, see `files`
+//! - {"v": 1, "type": "history", "room": "lobby", "messages": [...]}, the
+//!   chat messages after sequence number "since" of a
+//!   {"type": "history", "since": 42}, as far as `history` keeps them
+//! - {"v": 1, "type": "read", "room": "lobby", "user": 3, "seq": 42}, when
+//!   user 3 read the messages of the room up to 42, which is also the
+//!   "read" of their member in the roster
+//! - {"v": 1, "type": "reactions", "room": "lobby", "seq": 42, "reactions": {"👍": 2}},
+//!   when someone reacted to message 42 with the `react` command
+//! - {"v": 1, "type": "edited", "room": "lobby", "seq": 42, "body": "hi all"}
+//!   and {"v": 1, "type": "deleted", "room": "lobby", "seq": 42}, when
+//!   message 42 was changed or deleted with the `edit` and `delete`
+//!   commands, after which the history has it so and a chat message has
+//!   "edited": true
+//! - {"v": 1, "type": "ack", "id": "m-42"}
+//! - {"v": 1, "type": "reply", "command": "nick", "text": "you are now known as bob", "id": "m-43"}
+//! - {"v": 1, "type": "event", "event": "renamed", "text": "bob is now known as al", "data": {...}},
+//!   or "mentioned" when a chat message mentions them as `@nick`, in their
+//!   room or another
+//! - {"v": 1, "type": "announcement", "text": "restarting at noon", "from": "al"},
+//!   with "room" when it is for their room alone, from the operators
+//! - {"v": 1, "type": "error", "error": "...", "id": "m-44"}
+//!
+//! The WebRTC signaling and its answers are JSON already, and stay as they
+//! are.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::history::Reactions;
+
+/// The version of the JSON protocol.
+pub const VERSION: u32 = 1;
+
+/// The close code of the websockets of clients asking for a version of the
+/// protocol other than `VERSION`.
+pub const UNSUPPORTED_VERSION: u16 = 4006;
+
+/// How the messages to a chat user are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// As lines of text.
+    Text,
+    /// As `Outbound` envelopes.
+    Json,
+}
+
+impl Protocol {
+    /// The protocol of version `v`, plain text without one, unless it is
+    /// a version we do not speak.
+    pub fn of(v: Option<u32>) -> Result<Protocol, String> {
+        match v {
+            Some(VERSION) => Ok(Protocol::Json),
+            Some(v) => Err(format!(
+                "protocol version {} is not supported, only {} is",
+                v, VERSION
+            )),
+            None => Ok(Protocol::Text),
+        }
+    }
+}
+
+/// What chat users send, besides signaling and plain text.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Inbound {
+    /// A message for the chat room.
+    Chat {
+        #[serde(default)]
+        room: Option<String>,
+        body: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// One of the commands, such as `nick` with args `["bob"]`.
+    Command {
+        name: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The chat messages of the room after sequence number `since`.
+    History {
+        #[serde(default)]
+        since: Option<u64>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The messages of the room were read up to sequence number `seq`.
+    Read { seq: u64 },
+}
+
+impl Inbound {
+    /// `msg`, if it is one of these.
+    pub fn parse(msg: &str) -> Option<Inbound> {
+        serde_json::from_str(msg).ok()
+    }
+
+    /// The id the client gave to `msg`, if it is one of these with one.
+    pub fn id_of(msg: &str) -> Option<String> {
+        match Inbound::parse(msg)? {
+            Inbound::Chat { id, .. }
+            | Inbound::Command { id, .. }
+            | Inbound::History { id, .. } => id,
+            Inbound::Read { .. } => None,
+        }
+    }
+}
+
+/// What chat users get, besides the answers to their signaling.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Outbound {
+    /// A message of another user.
+    Chat {
+        room: String,
+        from: String,
+        body: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        private: bool,
+        /// Its place among the messages of the room, see `History`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// Whether its sender changed it since, see `Edited`.
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        edited: bool,
+    },
+    /// A file another user sent, to download from `url`.
+    File {
+        room: String,
+        from: String,
+        url: String,
+        mime: String,
+        size: usize,
+        /// Its place among the messages of the room, see `History`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+    },
+    /// Their chat message of id `id` went out to the room.
+    Ack { id: String },
+    /// The chat messages they asked for.
+    History {
+        room: String,
+        messages: Vec<Outbound>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    /// The answer to a command of theirs.
+    Reply {
+        command: String,
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    /// Something that happened, told by the gateway.
+    Event {
+        event: String,
+        text: String,
+        #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+        data: serde_json::Value,
+    },
+    /// User `user` of chat room `room` read its messages up to sequence
+    /// number `seq`.
+    Read { room: String, user: usize, seq: u64 },
+    /// Message `seq` of chat room `room` is now `body`.
+    Edited {
+        room: String,
+        seq: u64,
+        body: String,
+    },
+    /// Message `seq` of chat room `room` was deleted.
+    Deleted { room: String, seq: u64 },
+    /// The reactions to message `seq` of chat room `room`, by emoji.
+    Reactions {
+        room: String,
+        seq: u64,
+        reactions: Reactions,
+    },
+    /// A notice of the operators, to everyone or to chat room `room`, such
+    /// as before maintenance.
+    Announcement {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        room: Option<String>,
+        /// The name of the admin who sent it, if it was not sent over HTTP.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+    },
+    /// Something they sent that was refused.
+    Error {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+}
+
+impl Outbound {
+    /// A message of user `from` in chat room `room`.
+    pub fn chat(room: &str, from: &str, body: &str) -> Outbound {
+        Outbound::Chat {
+            room: room.to_string(),
+            from: from.to_string(),
+            body: body.to_string(),
+            private: false,
+            seq: None,
+            edited: false,
+        }
+    }
+
+    /// The sequence number of this chat message or file, if it has one.
+    pub fn seq(&self) -> Option<u64> {
+        match self {
+            Outbound::Chat { seq, .. } | Outbound::File { seq, .. } => *seq,
+            _ => None,
+        }
+    }
+
+    /// Gives this chat message or file sequence number `number`.
+    pub fn sequence(&mut self, number: u64) {
+        if let Outbound::Chat { seq, .. } | Outbound::File { seq, .. } = self {
+            *seq = Some(number);
+        }
+    }
+
+    /// Event `event`, told as `text`.
+    pub fn event(event: &str, text: impl Into<String>) -> Outbound {
+        Outbound::Event {
+            event: event.to_string(),
+            text: text.into(),
+            data: serde_json::Value::Null,
+        }
+    }
+
+    /// This event, along with `data` for the clients of the JSON protocol.
+    pub fn with_data(mut self, more: serde_json::Value) -> Outbound {
+        if let Outbound::Event { data, .. } = &mut self {
+            *data = more;
+        }
+        self
+    }
+
+    pub fn error(error: impl Into<String>) -> Outbound {
+        Outbound::Error {
+            error: error.into(),
+            id: None,
+        }
+    }
+
+    /// This reply, history or error, about the message of id `id` if it had one.
+    pub fn in_reply_to(mut self, id: Option<String>) -> Outbound {
+        match &mut self {
+            Outbound::Reply { id: about, .. }
+            | Outbound::History { id: about, .. }
+            | Outbound::Error { id: about, .. } => *about = id,
+            _ => {}
+        }
+        self
+    }
+
+    /// As the JSON protocol writes it, messages of a history included.
+    fn envelope(&self) -> serde_json::Value {
+        let mut envelope = json!(self);
+        if let Outbound::History { messages, .. } = self {
+            let messages: Vec<_> = messages.iter().map(Outbound::envelope).collect();
+            envelope["messages"] = json!(messages);
+        }
+        envelope["v"] = json!(VERSION);
+        envelope
+    }
+
+    /// As `protocol` writes it, if it is of any use to its clients: read
+    /// receipts are not, in plain text.
+    pub fn render(&self, protocol: Protocol) -> Option<String> {
+        let text = match protocol {
+            Protocol::Json => self.envelope().to_string(),
+            Protocol::Text => match self {
+                Outbound::Chat {
+                    from,
+                    body,
+                    private: false,
+                    edited,
+                    ..
+                } => {
+                    let edited = if *edited { " (edited)" } else { "" };
+                    format!("<{}>: {}{}", from, body, edited)
+                }
+                Outbound::Chat { from, body, .. } => format!("<{}> (private): {}", from, body),
+                Outbound::File {
+                    from,
+                    url,
+                    mime,
+                    size,
+                    ..
+                } => format!("<{}> sent a file: {} ({}, {} bytes)", from, url, mime, size),
+                Outbound::Reply { text, .. } | Outbound::Event { text, .. } => {
+                    format!("<Janus>: {}", text)
+                }
+                Outbound::Ack { id } => format!("<Janus>: {} sent", id),
+                Outbound::History { messages, .. } => messages
+                    .iter()
+                    .filter_map(|message| message.render(protocol))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Outbound::Read { .. } => return None,
+                Outbound::Edited { seq, body, .. } => {
+                    format!("<Janus>: message {} is now: {}", seq, body)
+                }
+                Outbound::Deleted { seq, .. } => format!("<Janus>: message {} was deleted", seq),
+                Outbound::Reactions { seq, reactions, .. } => {
+                    let counts: Vec<_> = reactions
+                        .iter()
+                        .map(|(emoji, count)| format!("{} {}", emoji, count))
+                        .collect();
+                    format!(
+                        "<Janus>: reactions to message {}: {}",
+                        seq,
+                        counts.join(", ")
+                    )
+                }
+                Outbound::Announcement { text, .. } => format!("<Janus> (announcement): {}", text),
+                Outbound::Error { error, .. } => format!("<Janus>: {}", error),
+            },
+        };
+        Some(text)
+    }
+}
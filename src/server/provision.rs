@@ -0,0 +1,211 @@
+//! The videorooms of the chat rooms: a chat room gets a videoroom when it
+//! is created, and the videoroom is destroyed when the last chat user
+//! leaves, so that nobody has to create or destroy them with commands.
+//!
+//! A chat room named after a number gets the videoroom with that id, any
+//! other one a videoroom with an id the plugin picks. A videoroom that
+//! existed before its chat room, such as one of the plugin's config file,
+//! is used as it is and left alone when the chat room empties. The videoroom
+//! of a chat room is opened and closed one at a time, so that the users
+//! joining while it is being destroyed get a new one.
+//!
+//! Chat rooms take `capacity` of `[rooms]` in the config users at most, as
+//! many as they like without it, and their videorooms as many publishers.
+//! A videoroom that existed before takes its own `max_publishers` instead,
+//! when it is fewer.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Mutex as AsyncMutex;
+
+use super::chat::Users;
+use super::commands;
+use crate::janus::videoroom::{self, CreateRoom, RoomFilter, RoomSecret, RoomSecrets};
+use crate::janus::{JanusClient, Result};
+
+/// The close code of the websockets of users joining a full chat room.
+pub const ROOM_FULL: u16 = 4004;
+
+/// The videorooms we opened for chat rooms.
+#[derive(Clone)]
+pub struct Provisioner {
+    janus: JanusClient,
+    secrets: RoomSecrets,
+    /// How many users a chat room takes, if not as many as they like.
+    capacity: Option<usize>,
+    /// The `admin_key` of the plugin, if it requires one for creating
+    /// videorooms.
+    admin_key: Option<String>,
+    /// The videoroom of every chat room, by chat room name.
+    rooms: Arc<Mutex<HashMap<String, Opened>>>,
+    /// What opening or closing the videoroom of a chat room waits for, by
+    /// chat room name, while it is being done.
+    busy: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Opened {
+    room_id: u64,
+    /// Whether we created the videoroom, rather than found it.
+    created: bool,
+    /// How many publishers the videoroom takes, when we found it.
+    publishers: Option<usize>,
+}
+
+impl Provisioner {
+    /// With the `admin_key` of the plugin, for creating videorooms, if it
+    /// has one.
+    pub fn new(
+        janus: JanusClient,
+        secrets: RoomSecrets,
+        capacity: Option<usize>,
+        admin_key: Option<String>,
+    ) -> Provisioner {
+        Provisioner {
+            janus,
+            secrets,
+            capacity,
+            admin_key,
+            rooms: Arc::default(),
+            busy: Arc::default(),
+        }
+    }
+
+    /// How many users chat room `chat_room` takes, if not as many as they
+    /// like.
+    pub fn capacity(&self, chat_room: &str) -> Option<usize> {
+        let rooms = self.rooms.lock().unwrap();
+        let publishers = rooms.get(chat_room).and_then(|opened| opened.publishers);
+        match (self.capacity, publishers) {
+            (Some(capacity), Some(publishers)) => Some(capacity.min(publishers)),
+            (capacity, publishers) => capacity.or(publishers),
+        }
+    }
+
+    /// The videoroom of chat room `chat_room`, if it has one.
+    pub fn room_id(&self, chat_room: &str) -> Option<u64> {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(chat_room).map(|opened| opened.room_id)
+    }
+
+    /// Forgets videoroom `room_id`, which was destroyed, and returns the
+    /// chat rooms it was the videoroom of.
+    pub fn forget(&self, room_id: u64) -> Vec<String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let chat_rooms: Vec<String> = rooms
+            .iter()
+            .filter(|(_, opened)| opened.room_id == room_id)
+            .map(|(chat_room, _)| chat_room.clone())
+            .collect();
+        for chat_room in &chat_rooms {
+            rooms.remove(chat_room);
+        }
+        chat_rooms
+    }
+
+    /// Opens the videoroom of chat room `chat_room`, creating it unless it
+    /// exists already, and returns its id.
+    pub async fn open(&self, chat_room: &str) -> Result<u64> {
+        let busy = self.busy(chat_room);
+        let _done = busy.lock().await;
+        let opened = self.open_now(chat_room).await;
+        self.done(chat_room, &busy);
+        opened
+    }
+
+    async fn open_now(&self, chat_room: &str) -> Result<u64> {
+        if let Some(room_id) = self.room_id(chat_room) {
+            return Ok(room_id);
+        }
+        let wanted = chat_room.parse().ok();
+        let opened = match wanted {
+            Some(room_id) if videoroom::exists(&self.janus, room_id).await? => Opened {
+                room_id,
+                created: false,
+                publishers: self.publishers_of(room_id).await,
+            },
+            _ => {
+                let secret = RoomSecret {
+                    secret: commands::random_token(16),
+                    pin: None,
+                };
+                let room = CreateRoom {
+                    room: wanted,
+                    description: Some(format!("chat room {}", chat_room)),
+                    secret: Some(secret.secret.clone()),
+                    publishers: self.capacity.map(|capacity| capacity as u32),
+                    admin_key: self.admin_key.clone(),
+                    ..CreateRoom::default()
+                };
+                let room_id = videoroom::create_room(&self.janus, &room).await?;
+                self.secrets.insert(room_id, secret);
+                Opened {
+                    room_id,
+                    created: true,
+                    publishers: None,
+                }
+            }
+        };
+
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.insert(chat_room.to_string(), opened);
+        Ok(opened.room_id)
+    }
+
+    /// The `max_publishers` of videoroom `room_id`, if the plugin tells.
+    async fn publishers_of(&self, room_id: u64) -> Option<usize> {
+        let rooms = videoroom::list_rooms(&self.janus, &RoomFilter::default()).await;
+        rooms
+            .ok()?
+            .into_iter()
+            .find(|room| room.room == room_id)
+            .map(|room| room.max_publishers as usize)
+    }
+
+    /// Destroys the videoroom of chat room `chat_room`, once its last user
+    /// left `users`, if we created it. It is kept when someone joined the
+    /// chat room again in the meantime.
+    pub async fn close(&self, chat_room: &str, users: &Users) -> Result<()> {
+        let busy = self.busy(chat_room);
+        let _done = busy.lock().await;
+        let closed = if users.read().await.contains_key(chat_room) {
+            Ok(())
+        } else {
+            self.close_now(chat_room).await
+        };
+        self.done(chat_room, &busy);
+        closed
+    }
+
+    async fn close_now(&self, chat_room: &str) -> Result<()> {
+        let room_id = match self.rooms.lock().unwrap().remove(chat_room) {
+            Some(Opened {
+                room_id,
+                created: true,
+                ..
+            }) => room_id,
+            _ => return Ok(()),
+        };
+        let secret = self.secrets.get(room_id).map(|known| known.secret);
+        videoroom::destroy_room(&self.janus, room_id, secret.as_deref(), false).await?;
+        self.secrets.remove(room_id);
+        Ok(())
+    }
+
+    /// What opening or closing the videoroom of `chat_room` waits for.
+    fn busy(&self, chat_room: &str) -> Arc<AsyncMutex<()>> {
+        let mut busy = self.busy.lock().unwrap();
+        busy.entry(chat_room.to_string()).or_default().clone()
+    }
+
+    /// Forgets what opening or closing the videoroom of `chat_room` waits
+    /// for, `busy`, unless someone else waits for it too.
+    fn done(&self, chat_room: &str, busy: &Arc<AsyncMutex<()>>) {
+        let mut rooms = self.busy.lock().unwrap();
+        // Ours, and the one of `self.busy`.
+        if Arc::strong_count(busy) <= 2 {
+            rooms.remove(chat_room);
+        }
+    }
+}
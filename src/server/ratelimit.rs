@@ -0,0 +1,135 @@
+//! How fast chat users may send messages and commands: every connection
+//! has a token bucket, of `rate_limit` of `[limits]` in the config such as
+//! `5/s` or `60/min`, 5 per second by default, or `off`. Messages over the
+//! limit are dropped with a warning, and after its `rate_strikes` of them,
+//! 10 by default, the websocket is closed with `POLICY_VIOLATION`.
+//!
+//! The WebRTC signaling of browsers goes through a bucket of its own, 50
+//! per second by default, as ICE candidates come in bursts, and its strikes
+//! count the same.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The close code of the websockets of users sending too fast.
+pub const POLICY_VIOLATION: u16 = 1008;
+
+/// How many messages a connection may send per `period`, at most `burst`
+/// of them at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub period: Duration,
+    /// How many messages over the limit are dropped before the websocket
+    /// is closed.
+    pub strikes: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            burst: 5,
+            period: Duration::from_secs(1),
+            strikes: 10,
+        }
+    }
+}
+
+impl RateLimit {
+    /// The default limit of the WebRTC signaling of a connection.
+    pub fn signaling() -> RateLimit {
+        RateLimit {
+            burst: 50,
+            ..RateLimit::default()
+        }
+    }
+
+    /// `5/s`, `60/min` or `600/h`.
+    pub fn parse(limit: &str) -> Option<RateLimit> {
+        let mut parts = limit.splitn(2, '/');
+        let burst = parts
+            .next()?
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&burst| burst > 0)?;
+        let period = match parts.next()?.trim() {
+            "s" => Duration::from_secs(1),
+            "min" => Duration::from_secs(60),
+            "h" => Duration::from_secs(60 * 60),
+            _ => return None,
+        };
+        Some(RateLimit {
+            burst,
+            period,
+            ..RateLimit::default()
+        })
+    }
+
+    /// A full bucket for a new connection.
+    pub fn bucket(self) -> TokenBucket {
+        TokenBucket {
+            limit: self,
+            tokens: f64::from(self.burst),
+            refilled: Instant::now(),
+            strikes: 0,
+        }
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per = match self.period.as_secs() {
+            1 => "second",
+            60 => "minute",
+            _ => "hour",
+        };
+        write!(f, "{} messages per {}", self.burst, per)
+    }
+}
+
+/// What became of a message going through a `TokenBucket`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Allowed,
+    /// Dropped, with the number of strikes left.
+    Dropped(u32),
+    /// Dropped one time too many.
+    Exceeded,
+}
+
+/// The messages a connection may still send, refilled over time.
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+    strikes: u32,
+}
+
+impl TokenBucket {
+    /// Takes a token for a message, if there is one left.
+    pub fn take(&mut self) -> Verdict {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        let rate = f64::from(self.limit.burst) / self.limit.period.as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.burst));
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Verdict::Allowed;
+        }
+        self.strikes += 1;
+        if self.strikes >= self.limit.strikes {
+            Verdict::Exceeded
+        } else {
+            Verdict::Dropped(self.limit.strikes - self.strikes)
+        }
+    }
+
+    /// The limit it keeps.
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+}
@@ -0,0 +1,131 @@
+//! What chat users may do: every user has a role, from the `roles` claim of
+//! their JWT or else `default_role` of `[auth]` in the config, and every
+//! command needs one of them, see `commands::COMMANDS`.
+//!
+//! Without `default_role`, users are plain users, with or without
+//! authentication: admins only ever come from a claim or from
+//! `default_role = "admin"`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::auth::Credentials;
+use super::commands;
+use super::protocol::Inbound;
+
+/// The roles, each allowed what the ones before are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Reads the chat.
+    Guest,
+    /// Chats, and publishes or calls.
+    User,
+    /// Keeps the videorooms and their participants in order.
+    Moderator,
+    /// Creates and destroys videorooms.
+    Admin,
+}
+
+/// The role sending a chat message to the room needs.
+pub const BROADCAST: Role = Role::User;
+
+/// The role the WebRTC signaling of a browser needs.
+pub const SIGNAL: Role = Role::User;
+
+impl Role {
+    /// The highest of the roles `credentials` claim, or else `default`.
+    pub fn of(credentials: &Credentials, default: Role) -> Role {
+        let claimed = credentials
+            .roles
+            .iter()
+            .filter_map(|role| role.parse().ok());
+        claimed.max().unwrap_or(default)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Role, String> {
+        match role {
+            "guest" => Ok(Role::Guest),
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("no such role: {}", role)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Guest => "guest",
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// What a message of a chat user does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// A signal, as {"type": "..."}.
+    Signal(String),
+    /// One of the commands of `commands::COMMANDS`, by name.
+    Command(&'static str),
+    /// A chat message for the room.
+    Chat,
+}
+
+impl Action {
+    /// What `msg` does.
+    pub fn of(msg: &str) -> Action {
+        let name = match Inbound::parse(msg) {
+            Some(Inbound::Chat { .. }) => return Action::Chat,
+            Some(Inbound::History { .. }) => return Action::Signal("history".to_string()),
+            Some(Inbound::Read { .. }) => return Action::Signal("read".to_string()),
+            Some(Inbound::Command { name, .. }) => name,
+            None => {
+                if let Ok(signal) = serde_json::from_str::<serde_json::Value>(msg) {
+                    if let Some(kind) = signal["type"].as_str() {
+                        return Action::Signal(kind.to_string());
+                    }
+                }
+                commands::parse(msg).0.to_string()
+            }
+        };
+        match commands::find(&name) {
+            Some(command) => Action::Command(command.name),
+            None => Action::Chat,
+        }
+    }
+
+    /// The role it needs.
+    pub fn required(&self) -> Role {
+        match self {
+            Action::Signal(kind) if ["users", "history", "read"].contains(&kind.as_str()) => {
+                Role::Guest
+            }
+            Action::Signal(_) => SIGNAL,
+            Action::Command(name) => {
+                commands::find(name).map_or(Role::Admin, |command| command.role)
+            }
+            Action::Chat => BROADCAST,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Signal(kind) => f.write_str(kind),
+            Action::Command(name) => f.write_str(name),
+            Action::Chat => f.write_str("chat"),
+        }
+    }
+}
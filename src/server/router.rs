@@ -0,0 +1,55 @@
+//! Which chat users hear about what happens in a videoroom.
+//!
+//! The chat room of a videoroom is made of the chat users who joined it, as
+//! publishers or subscribers: they get the media events of the videoroom,
+//! such as publishers coming and going, as system messages inline with the
+//! chat.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+
+use super::chat::{self, Users};
+use super::protocol::Outbound;
+
+/// The chat users in every videoroom, by room id.
+#[derive(Clone, Default)]
+pub struct RoomRouter {
+    rooms: Arc<Mutex<HashMap<u64, HashSet<usize>>>>,
+}
+
+impl RoomRouter {
+    /// Chat user `user_id` joined videoroom `room_id`.
+    pub fn join(&self, room_id: u64, user_id: usize) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.entry(room_id).or_default().insert(user_id);
+    }
+
+    /// Chat user `user_id` left every videoroom they were in.
+    pub fn leave(&self, user_id: usize) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.retain(|_, members| {
+            members.remove(&user_id);
+            !members.is_empty()
+        });
+    }
+
+    /// The chat users in videoroom `room_id`.
+    pub fn members(&self, room_id: u64) -> Vec<usize> {
+        let rooms = self.rooms.lock().unwrap();
+        match rooms.get(&room_id) {
+            Some(members) => members.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Sends `text` as a system message to the chat users in videoroom
+    /// `room_id`.
+    pub async fn announce(&self, users: &Users, room_id: u64, text: &str) {
+        let notice = Outbound::event("videoroom", text).with_data(json!({ "room": room_id }));
+        for user_id in self.members(room_id) {
+            chat::deliver(users, user_id, &notice).await;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Chat users coming back: every connection is given a resume token, and
+//! whoever reconnects to the same chat room with it within `resume_secs` of
+//! `[chat]` in the config, 120 by default, is the same user again, with their
+//! id, nickname, role, profile and identity, instead of a new one. With
+//! `?since=<seq>` along, they get the messages they missed.
+//!
+//! Kicked users cannot come back this way.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::auth::Credentials;
+use super::chat::ChatUser;
+use super::profiles::Profile;
+use super::roles::Role;
+
+/// A chat user who left, as they were.
+#[derive(Clone, Debug)]
+pub struct Parked {
+    pub user_id: usize,
+    pub room: String,
+    pub nick: Option<String>,
+    pub credentials: Credentials,
+    pub role: Role,
+    pub profile: Profile,
+    /// The sequence number of the last message of the room they read.
+    pub read: u64,
+    left: Instant,
+}
+
+impl Parked {
+    /// Chat user `user` of id `user_id`, who just left chat room `room`.
+    pub fn new(user_id: usize, room: String, user: &ChatUser) -> Parked {
+        Parked {
+            user_id,
+            room,
+            nick: user.nick.clone(),
+            credentials: user.credentials.clone(),
+            role: user.role,
+            profile: user.profile.clone(),
+            read: user.read,
+            left: Instant::now(),
+        }
+    }
+}
+
+/// The chat users who left lately, by resume token.
+#[derive(Clone)]
+pub struct Sessions {
+    /// How long they may come back, not at all with 0.
+    window: Duration,
+    parked: Arc<Mutex<HashMap<String, Parked>>>,
+}
+
+impl Sessions {
+    pub fn new(window: Duration) -> Sessions {
+        Sessions {
+            window,
+            parked: Arc::default(),
+        }
+    }
+
+    /// Whether chat users may come back at all.
+    pub fn enabled(&self) -> bool {
+        self.window > Duration::from_secs(0)
+    }
+
+    /// Keeps `user`, who left, for whoever comes back with `token`.
+    pub fn park(&self, token: String, user: Parked) {
+        if !self.enabled() {
+            return;
+        }
+        let mut parked = self.parked.lock().unwrap();
+        parked.retain(|_, user| user.left.elapsed() < self.window);
+        parked.insert(token, user);
+    }
+
+    /// The user who left with `token`, if they may come back to chat room
+    /// `room`. The token cannot be used twice.
+    pub fn resume(&self, token: &str, room: &str) -> Option<Parked> {
+        let mut parked = self.parked.lock().unwrap();
+        let user = parked.remove(token)?;
+        Some(user).filter(|user| user.room == room && user.left.elapsed() < self.window)
+    }
+}
@@ -0,0 +1,412 @@
+//! Chat history on disk: every message sent in a chat room is written to a
+//! SQLite database, to page through later with `GET /history/<room>`, and
+//! to search with `GET /search?room=<room>&q=<words>` or the `search`
+//! command, through an FTS5 index of their bodies.
+//!
+//! Only built with the `sqlite` feature, and only used when `db` of
+//! `[store]` in the config names the database file. Messages older than
+//! its `retention_days` are deleted every hour, if it is set. Messages
+//! edited or deleted with the `edit` and `delete` commands are changed here
+//! as well.
+
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::Filter;
+
+use super::{bans, mutes, State};
+
+/// A message as it was sent in a chat room.
+#[derive(Clone, Debug, Serialize)]
+pub struct StoredMessage {
+    /// Grows with every message, whatever the room, and pages through
+    /// them as `before`.
+    pub id: i64,
+    pub room: String,
+    /// Its sequence number in the room, see `History`, which the `edit`,
+    /// `delete` and `react` commands take. Messages saved before they had
+    /// one have none.
+    pub seq: Option<u64>,
+    /// The name the sender went by.
+    pub sender: String,
+    /// When it was sent, in milliseconds since the Unix epoch.
+    pub sent_at: i64,
+    pub body: String,
+}
+
+/// Which messages of a room to page through: the `limit` last ones sent
+/// before message `before`, or the last ones without it.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Page {
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// What to search for in the messages of a room: those with every one of
+/// the words of `q`, by pages like `Page` but newest first.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Search {
+    pub room: String,
+    pub q: String,
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// The most messages a page has, and how many without a `limit`.
+const MAX_PAGE: u32 = 200;
+const DEFAULT_PAGE: u32 = 50;
+
+/// The database of the chat history.
+#[derive(Clone)]
+pub struct Store {
+    connection: Arc<Mutex<Connection>>,
+    /// How long messages are kept, forever without it.
+    retention: Option<Duration>,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>, retention: Option<Duration>) -> rusqlite::Result<Store> {
+        let connection = Connection::open(path)?;
+        let indexed: bool = connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'messages_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room TEXT NOT NULL,
+                sender TEXT NOT NULL,
+                sent_at INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                seq INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS messages_by_room ON messages (room, id);",
+        )?;
+        // Databases from before messages had their sequence number get it,
+        // and already have it otherwise.
+        let _ = connection.execute("ALTER TABLE messages ADD COLUMN seq INTEGER", []);
+        // The search index follows the messages through triggers, and
+        // indexes those of databases from before it once.
+        connection.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts
+                USING fts5(body, content = 'messages', content_rowid = 'id');
+            CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+                INSERT INTO messages_fts (rowid, body) VALUES (new.id, new.body);
+            END;
+            CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+                INSERT INTO messages_fts (messages_fts, rowid, body)
+                    VALUES ('delete', old.id, old.body);
+            END;
+            CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF body ON messages BEGIN
+                INSERT INTO messages_fts (messages_fts, rowid, body)
+                    VALUES ('delete', old.id, old.body);
+                INSERT INTO messages_fts (rowid, body) VALUES (new.id, new.body);
+            END;",
+        )?;
+        if !indexed {
+            connection.execute(
+                "INSERT INTO messages_fts (messages_fts) VALUES ('rebuild')",
+                [],
+            )?;
+        }
+        Ok(Store {
+            connection: Arc::new(Mutex::new(connection)),
+            retention,
+        })
+    }
+
+    /// Writes down message `body` of `sender` in chat room `room`, which
+    /// has sequence number `seq` there, see `History`.
+    pub async fn save(
+        &self,
+        room: &str,
+        sender: &str,
+        body: &str,
+        seq: u64,
+    ) -> rusqlite::Result<()> {
+        let (room, sender, body) = (room.to_string(), sender.to_string(), body.to_string());
+        self.blocking(move |connection| {
+            connection.execute(
+                "INSERT INTO messages (room, sender, sent_at, body, seq)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![room, sender, now_millis(), body, seq as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Changes message `seq` of chat room `room` into `body`. The sequence
+    /// numbers start over with a restart, so it is the last message of the
+    /// room with that number.
+    pub async fn edit(&self, room: &str, seq: u64, body: &str) -> rusqlite::Result<usize> {
+        let (room, body) = (room.to_string(), body.to_string());
+        self.blocking(move |connection| {
+            connection.execute(
+                "UPDATE messages SET body = ?3
+                WHERE id = (SELECT MAX(id) FROM messages WHERE room = ?1 AND seq = ?2)",
+                params![room, seq as i64, body],
+            )
+        })
+        .await
+    }
+
+    /// Deletes message `seq` of chat room `room`, the last one of the room
+    /// with that number.
+    pub async fn delete(&self, room: &str, seq: u64) -> rusqlite::Result<usize> {
+        let room = room.to_string();
+        self.blocking(move |connection| {
+            connection.execute(
+                "DELETE FROM messages
+                WHERE id = (SELECT MAX(id) FROM messages WHERE room = ?1 AND seq = ?2)",
+                params![room, seq as i64],
+            )
+        })
+        .await
+    }
+
+    /// A page of the messages of chat room `room`, oldest first.
+    pub async fn page(&self, room: &str, page: &Page) -> rusqlite::Result<Vec<StoredMessage>> {
+        let room = room.to_string();
+        let before = page.before.unwrap_or(i64::MAX);
+        let limit = page.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+        self.blocking(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT id, room, seq, sender, sent_at, body FROM messages
+                WHERE room = ?1 AND id < ?2 ORDER BY id DESC LIMIT ?3",
+            )?;
+            let rows = statement.query_map(params![room, before, limit], |row| {
+                Ok(StoredMessage {
+                    id: row.get(0)?,
+                    room: row.get(1)?,
+                    seq: row.get::<_, Option<i64>>(2)?.map(|seq| seq as u64),
+                    sender: row.get(3)?,
+                    sent_at: row.get(4)?,
+                    body: row.get(5)?,
+                })
+            })?;
+            let mut messages = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+            messages.reverse();
+            Ok(messages)
+        })
+        .await
+    }
+
+    /// A page of the messages of chat room `room` with every one of `words`,
+    /// newest first.
+    pub async fn search(
+        &self,
+        room: &str,
+        words: &str,
+        page: &Page,
+    ) -> rusqlite::Result<Vec<StoredMessage>> {
+        let query = match_query(words);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let room = room.to_string();
+        let before = page.before.unwrap_or(i64::MAX);
+        let limit = page.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+        self.blocking(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT m.id, m.room, m.seq, m.sender, m.sent_at, m.body
+                FROM messages_fts JOIN messages m ON m.id = messages_fts.rowid
+                WHERE messages_fts MATCH ?1 AND m.room = ?2 AND m.id < ?3
+                ORDER BY m.id DESC LIMIT ?4",
+            )?;
+            let rows = statement.query_map(params![query, room, before, limit], |row| {
+                Ok(StoredMessage {
+                    id: row.get(0)?,
+                    room: row.get(1)?,
+                    seq: row.get::<_, Option<i64>>(2)?.map(|seq| seq as u64),
+                    sender: row.get(3)?,
+                    sent_at: row.get(4)?,
+                    body: row.get(5)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Deletes the messages older than the retention, and returns how many
+    /// there were.
+    pub async fn prune(&self) -> rusqlite::Result<usize> {
+        let retention = match self.retention {
+            Some(retention) => retention.as_millis() as i64,
+            None => return Ok(0),
+        };
+        self.blocking(move |connection| {
+            connection.execute(
+                "DELETE FROM messages WHERE sent_at < ?1",
+                params![now_millis() - retention],
+            )
+        })
+        .await
+    }
+
+    /// Runs `f` on the connection out of the async tasks, as SQLite blocks.
+    async fn blocking<T, F>(&self, f: F) -> rusqlite::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&connection.lock().unwrap()))
+            .await
+            .expect("chat store task panicked")
+    }
+}
+
+/// The FTS5 query for the messages with every one of `words`, each of them
+/// quoted so that none is taken for the syntax of the queries.
+fn match_query(words: &str) -> String {
+    words
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// `GET /history/<room>?before=<id>&limit=50` with a page of the messages of
+/// chat room `room`, oldest first, and
+/// `GET /search?room=<room>&q=<words>&before=<id>&limit=50` with a page of
+/// those with every one of `words`, newest first, or 404 when they are not
+/// kept.
+///
+/// Both take the `token` of `auth` like the chat websocket, in the query or
+/// as an `Authorization: Bearer` header, and only answer about a private
+/// chat room its owner and the users in it.
+pub fn routes(
+    state: State,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let with_state = warp::any().map(move || state.clone());
+    let history = warp::path!("history" / String)
+        .and(warp::get())
+        .and(warp::query::<Page>())
+        .and(token())
+        .and(with_state.clone())
+        .and_then(
+            |room: String, page: Page, token: Option<String>, state: State| async move {
+                if let Err(refusal) = may_read(&state, &room, token.as_deref()).await {
+                    return Ok::<_, Infallible>(refusal);
+                }
+                let messages = match &state.store {
+                    Some(store) => Some(store.page(&room, &page).await),
+                    None => None,
+                };
+                Ok(reply(messages))
+            },
+        );
+    let search = warp::path!("search")
+        .and(warp::get())
+        .and(warp::query::<Search>())
+        .and(token())
+        .and(with_state)
+        .and_then(
+            |search: Search, token: Option<String>, state: State| async move {
+                if let Err(refusal) = may_read(&state, &search.room, token.as_deref()).await {
+                    return Ok::<_, Infallible>(refusal);
+                }
+                let page = Page {
+                    before: search.before,
+                    limit: search.limit,
+                };
+                let messages = match &state.store {
+                    Some(store) => Some(store.search(&search.room, &search.q, &page).await),
+                    None => None,
+                };
+                Ok(reply(messages))
+            },
+        );
+    history.or(search)
+}
+
+/// The `token` of a request, from `?token=` or else its `Authorization:
+/// Bearer` header.
+fn token() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+    #[derive(Deserialize)]
+    struct Access {
+        token: Option<String>,
+    }
+
+    warp::query::<Access>()
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|access: Access, header: Option<String>| {
+            access.token.or_else(|| {
+                header.and_then(|header| header.strip_prefix("Bearer ").map(String::from))
+            })
+        })
+}
+
+/// Whether whoever has `token` may read the messages of chat room `room`,
+/// or else why not: 401 when `auth` does not let them in, and 403 when
+/// the room is private and they are neither its owner nor in it.
+async fn may_read(
+    state: &State,
+    room: &str,
+    token: Option<&str>,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let refuse = |error: &str, status: StatusCode| {
+        warp::reply::with_status(warp::reply::json(&json!({ "error": error })), status)
+    };
+    let credentials = match state.auth.check(token) {
+        Some(credentials) => credentials,
+        None => return Err(refuse("authentication failed", StatusCode::UNAUTHORIZED)),
+    };
+    if !state.invites.is_private(room) {
+        return Ok(());
+    }
+    let identity = credentials.subject.as_deref().map(bans::of_subject);
+    let member = match &identity {
+        Some(identity) if state.invites.is_owner(room, identity) => true,
+        Some(identity) => state.users.read().await.get(room).is_some_and(|members| {
+            members
+                .iter()
+                .any(|(&user_id, user)| mutes::identity(user_id, &user.credentials) == *identity)
+        }),
+        None => false,
+    };
+    if member {
+        Ok(())
+    } else {
+        Err(refuse(
+            &format!("room {} is invite only", room),
+            StatusCode::FORBIDDEN,
+        ))
+    }
+}
+
+/// Answers with `messages`, or with why there are none.
+fn reply(
+    messages: Option<rusqlite::Result<Vec<StoredMessage>>>,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    match messages {
+        Some(Ok(messages)) => {
+            warp::reply::with_status(warp::reply::json(&messages), StatusCode::OK)
+        }
+        Some(Err(e)) => warp::reply::with_status(
+            warp::reply::json(&json!({ "error": e.to_string() })),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+        None => warp::reply::with_status(
+            warp::reply::json(&json!({ "error": "the chat history is not kept" })),
+            StatusCode::NOT_FOUND,
+        ),
+    }
+}